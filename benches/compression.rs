@@ -1,10 +1,45 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::{Duration, Instant};
 use criterion::{black_box, Criterion, criterion_group, criterion_main};
+use flate2::Compression;
+use flate2::write::DeflateEncoder;
 use rand::prelude::{SliceRandom, StdRng};
-use rand::SeedableRng;
-use fpc_compression::compress_into;
+use rand::{Rng, SeedableRng};
+use fpc_compression::{compress, compress_into, compress_with, estimate_compressed_size, FpcOptions, ResidualLayout, ResidualSizing};
 
-fn deterministic_shuffle(vec: &mut Vec<f64>, seed: u64) {
+/// Counts reallocations, so benchmarks can report how often a buffer had
+/// to grow on top of how long they took.
+struct ReallocCountingAllocator;
+
+static REALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for ReallocCountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        REALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: ReallocCountingAllocator = ReallocCountingAllocator;
+
+fn reallocations_during<T>(f: impl FnOnce() -> T) -> usize {
+    let before = REALLOCATIONS.load(Ordering::Relaxed);
+    black_box(f());
+    REALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+fn deterministic_shuffle(vec: &mut [f64], seed: u64) {
     let mut rng = StdRng::seed_from_u64(seed);
     vec.shuffle(&mut rng);
 }
@@ -22,11 +57,12 @@ fn criterion_compress_ten_million_double_precision_values(c: &mut Criterion) {
             |b, &tsz| { b.iter_custom(|iters| {
                 let mut total_elapsed = Duration::new(0, 0);
                 for _ in 0..iters {
-                    let mut encoding = vec![0_u8; (vals.len() + 1)/2];
+                    let mut encoding = Vec::with_capacity(vals.len().div_ceil(2));
                     let mut residual = Vec::with_capacity(size_of::<f64>() * vals.len());
                     {
                         let start = Instant::now();
-                        black_box(compress_into(tsz, &vals, &mut encoding, &mut residual));
+                        compress_into(tsz, &vals, (&mut encoding, &mut residual)).unwrap();
+                        black_box(&residual);
                         total_elapsed += start.elapsed();
                     }
                 }
@@ -35,8 +71,61 @@ fn criterion_compress_ten_million_double_precision_values(c: &mut Criterion) {
     }
 }
 
+fn criterion_compress_incompressible_values_with_residual_sizing(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(124840);
+    let vals = (0..1_000_000).map(|_| f64::from_bits(rng.gen())).collect::<Vec<f64>>();
+    let mut bench_group = c.benchmark_group("compress_incompressible_1M");
+    for (name, residual_sizing) in [
+        ("conservative", ResidualSizing::Conservative),
+        ("exact", ResidualSizing::Exact),
+        ("pessimistic", ResidualSizing::Pessimistic),
+    ] {
+        let options = FpcOptions { residual_sizing, ..FpcOptions::default() };
+        let reallocations = reallocations_during(|| compress_with(32, &vals, &options).unwrap());
+        println!("{name}: {reallocations} reallocations");
+        bench_group.bench_with_input(
+            criterion::BenchmarkId::new("residual_sizing", name),
+            &options,
+            |b, options| b.iter(|| black_box(compress_with(32, &vals, options).unwrap())),
+        );
+    }
+}
+
+fn criterion_estimate_versus_compress(c: &mut Criterion) {
+    let vals = (0..1_000_000).map(|x| 20.0 + (x as f64 * 0.001).sin()).collect::<Vec<f64>>();
+    let mut bench_group = c.benchmark_group("size_1M");
+    bench_group.bench_function("estimate_compressed_size", |b| {
+        b.iter(|| black_box(estimate_compressed_size(1024, &vals).unwrap()))
+    });
+    bench_group.bench_function("compress", |b| b.iter(|| black_box(compress(1024, &vals).unwrap().compressed_len())));
+}
+
+fn deflate(bytes: &[u8]) -> Vec<u8> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(bytes).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn criterion_deflate_serialized_block_with_residual_layout(c: &mut Criterion) {
+    let vals = (0..1_000_000).map(|x| 20.0 + (x as f64 * 0.001).sin()).collect::<Vec<f64>>();
+    let blk = compress(32, &vals).unwrap();
+    let mut bench_group = c.benchmark_group("deflate_serialized_1M");
+    for (name, layout) in [("interleaved", ResidualLayout::Interleaved), ("transposed", ResidualLayout::Transposed)] {
+        let bytes = blk.to_bytes_with_layout(layout);
+        println!("{name}: {} bytes serialized, {} bytes deflated", bytes.len(), deflate(&bytes).len());
+        bench_group.bench_with_input(
+            criterion::BenchmarkId::new("residual_layout", name),
+            &layout,
+            |b, &layout| b.iter(|| black_box(deflate(&blk.to_bytes_with_layout(layout)))),
+        );
+    }
+}
+
 criterion_group!(benches,
     criterion_compress_ten_million_double_precision_values,
+    criterion_compress_incompressible_values_with_residual_sizing,
+    criterion_estimate_versus_compress,
+    criterion_deflate_serialized_block_with_residual_layout,
 );
 
 criterion_main!(benches);
\ No newline at end of file
@@ -0,0 +1,51 @@
+//! Python bindings of the crate for NumPy `float64` arrays. Build them
+//! with `maturin develop` in this directory, then
+//!
+//! ```python
+//! import fpc
+//! data = fpc.compress(32, values)
+//! values = fpc.decompress(32, data, len(values))
+//! ```
+//!
+//! The bytes are a block serialized by `FPCCompressedBlock::to_bytes`, so
+//! they carry everything needed to decode them. Errors raise `ValueError`.
+
+use fpc_compression::{try_decompress, FPCCompressedBlock, FpcError};
+use numpy::{IntoPyArray, PyArray1, PyReadonlyArray1};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+fn value_error(e: FpcError) -> PyErr {
+    PyValueError::new_err(e.to_string())
+}
+
+/// Compresses a one-dimensional float64 array. Contiguous arrays are read
+/// in place; others are copied first.
+#[pyfunction]
+fn compress<'py>(py: Python<'py>, table_size: u64, values: PyReadonlyArray1<'py, f64>) -> PyResult<Bound<'py, PyBytes>> {
+    let blk = match values.as_slice() {
+        Ok(values) => fpc_compression::compress(table_size, values),
+        Err(_) => fpc_compression::compress(table_size, &values.as_array().iter().copied().collect::<Vec<_>>()),
+    }
+    .map_err(value_error)?;
+    Ok(PyBytes::new_bound(py, &blk.to_bytes()))
+}
+
+/// Decompresses the `n` values of a block returned by `compress`.
+#[pyfunction]
+fn decompress<'py>(py: Python<'py>, table_size: u64, data: &[u8], n: usize) -> PyResult<Bound<'py, PyArray1<f64>>> {
+    let blk = FPCCompressedBlock::from_bytes(data).map_err(value_error)?;
+    if blk.num_values() != n {
+        return Err(PyValueError::new_err(format!("block holds {} values, not {n}", blk.num_values())));
+    }
+    let values = try_decompress(table_size, &blk).map_err(value_error)?;
+    Ok(values.into_pyarray_bound(py))
+}
+
+#[pymodule]
+fn fpc(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(compress, m)?)?;
+    m.add_function(wrap_pyfunction!(decompress, m)?)?;
+    Ok(())
+}
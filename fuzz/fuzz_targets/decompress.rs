@@ -0,0 +1,23 @@
+//! Feeds arbitrary bytes to the block parser and decodes whatever parses.
+//! Neither step may panic: corrupt input must come back as an error.
+//!
+//! Run with `cargo fuzz run decompress` from the repository root.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use fpc_compression::{try_decompress, FPCCompressedBlock};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(blk) = FPCCompressedBlock::from_bytes(data) else {
+        return;
+    };
+    if let Ok(values) = try_decompress(blk.table_size(), &blk) {
+        assert_eq!(values.len(), blk.num_values());
+    }
+    // The lazy iterator shares the decoding code but not the up-front
+    // encoding length check.
+    let decoded = blk.iter(blk.table_size()).unwrap().take_while(Result::is_ok).count();
+    assert!(decoded <= blk.num_values());
+});
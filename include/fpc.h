@@ -0,0 +1,53 @@
+/*
+ * C interface of the fpc-compression crate, available when it is built
+ * with the `ffi` feature, for example as a static library with
+ *
+ *     cargo rustc --release --features ffi --crate-type staticlib
+ *
+ * Every function returns FPC_OK or a negative FPC_ERR_* code.
+ */
+#ifndef FPC_H
+#define FPC_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define FPC_OK 0
+/* A pointer that must not be null was null. */
+#define FPC_ERR_NULL_POINTER -1
+/* The table size is not a power of two of at most 2^24. */
+#define FPC_ERR_INVALID_TABLE_SIZE -2
+/* An output buffer is too small; the lengths hold the sizes needed. */
+#define FPC_ERR_BUFFER_TOO_SMALL -3
+/* The encoding and residual do not describe the given number of values. */
+#define FPC_ERR_MALFORMED_BLOCK -4
+
+/*
+ * Compresses the n doubles at values into an encoding and a residual.
+ *
+ * *enc_len and *res_len give the capacities of out_encoding and
+ * out_residual and are set to the lengths of the two streams. With both
+ * buffers null only the lengths are set, which sizes the buffers for a
+ * second call. If either buffer is too small nothing is written to them
+ * and FPC_ERR_BUFFER_TOO_SMALL is returned.
+ */
+int fpc_compress(uint64_t table_size, const double *values, size_t n, uint8_t *out_encoding, size_t *enc_len,
+                 uint8_t *out_residual, size_t *res_len);
+
+/*
+ * Decompresses n doubles from an encoding and residual written by
+ * fpc_compress with the same table size into out_values. The caller
+ * keeps track of n.
+ */
+int fpc_decompress(uint64_t table_size, const uint8_t *encoding, size_t enc_len, const uint8_t *residual,
+                   size_t res_len, double *out_values, size_t n);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* FPC_H */
@@ -0,0 +1,380 @@
+use alloc::vec::Vec;
+
+use crate::encoding_mode::uniform_bits;
+use crate::{
+    encode_value, residual_len, residual_len_of_code, validate_table_size, CompressError, FPCCompressedBlock, FpcError,
+    HashParams, Predictors,
+};
+
+/// Fraction of values encoded with each of the 16 possible codes
+/// (predictor selection bit plus leading-zero byte count).
+fn code_histogram(blk: &FPCCompressedBlock) -> [f64; 16] {
+    let mut histogram = [0.0; 16];
+    if blk.num_bytes_encoded == 0 {
+        return histogram;
+    }
+    for code in blk.full_codes() {
+        histogram[code as usize] += 1.0;
+    }
+    for bucket in &mut histogram {
+        *bucket /= blk.num_bytes_encoded as f64;
+    }
+    histogram
+}
+
+/// Estimates how differently two blocks compressed, as the L1 distance
+/// between the normalized histograms of their encoding codes. Only the
+/// encoding stream is read, so this is cheap enough to run over many
+/// blocks as a pre-filter. Constant blocks count with the codes they would
+/// have had, which costs compressing their first few values again.
+///
+/// This is a heuristic and not a distance between the underlying values:
+/// two unrelated series that happen to compress alike are 0 apart. The
+/// result lies in `[0, 2]`.
+pub fn block_distance(a: &FPCCompressedBlock, b: &FPCCompressedBlock) -> f64 {
+    code_histogram(a)
+        .iter()
+        .zip(code_histogram(b).iter())
+        .map(|(x, y)| (x - y).abs())
+        .sum()
+}
+
+/// Decodes `blk` and splits its values by the predictor that encoded them,
+/// returning `(fcm_values, dfcm_values)`. Each part keeps the original
+/// order of its values. The values of a constant block are split by the
+/// codes they would have had. Fails like
+/// [`try_decompress`](crate::try_decompress) on an invalid or mismatched
+/// table size or a malformed block.
+pub fn partition_by_predictor(table_size: u64, blk: &FPCCompressedBlock) -> Result<(Vec<f64>, Vec<f64>), FpcError> {
+    validate_table_size(table_size)?;
+    blk.check_table_size(table_size)?;
+    blk.check_encoding_len()?;
+    let mut fcm_values = Vec::new();
+    let mut dfcm_values = Vec::new();
+    for (code, value) in blk.full_codes().zip(blk.iter(table_size)?) {
+        if code & 0b1000 != 0 {
+            fcm_values.push(value?);
+        } else {
+            dfcm_values.push(value?);
+        }
+    }
+    Ok((fcm_values, dfcm_values))
+}
+
+/// How one value of a block was encoded, as read from its code by
+/// [`decode_encoding`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValueMeta {
+    /// Whether the FCM prediction was used rather than the DFCM one.
+    pub fcm_chosen: bool,
+    /// Leading zero bytes of the difference to the prediction that the
+    /// residual leaves out. The encoding has no code for exactly 4, so
+    /// such a difference counts 3 and keeps one zero byte.
+    pub leading_zero_bytes: u8,
+}
+
+/// Reads how every value of `blk` was encoded from the encoding stream
+/// alone, without touching the residual or decoding any values. A
+/// constant block has no codes, so the values before the predictors
+/// settle on its value are compressed again to read theirs.
+pub fn decode_encoding(blk: &FPCCompressedBlock) -> Vec<ValueMeta> {
+    blk.full_codes()
+        .map(|code| ValueMeta {
+            fcm_chosen: code & 0b1000 != 0,
+            leading_zero_bytes: (8 - residual_len_of_code(code)) as u8,
+        })
+        .collect()
+}
+
+/// Residual bytes `values` would take under each way of choosing a
+/// predictor, from [`estimate_mode_sizes`]. The encoding takes half a
+/// byte per value regardless of the mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModeSizes {
+    /// The closer prediction for every value, as [`compress`](crate::compress) does.
+    pub both: usize,
+    /// Always the FCM prediction.
+    pub fcm_only: usize,
+    /// Always the DFCM prediction.
+    pub dfcm_only: usize,
+}
+
+/// Estimates the residual size of `values` under each predictor mode in a
+/// single pass. The predictor tables do not depend on which prediction is
+/// chosen, so one set of tables serves all three modes and `both` is
+/// exactly the residual length [`compress`](crate::compress) produces with
+/// full codes, i.e. unless the values make a constant block. Fails like
+/// [`compress`](crate::compress) on an invalid table size.
+pub fn estimate_mode_sizes(table_size: u64, values: &[f64]) -> Result<ModeSizes, CompressError> {
+    validate_table_size(table_size)?;
+    let mut predictors = Predictors::new(table_size);
+    let mut sizes = ModeSizes { both: 0, fcm_only: 0, dfcm_only: 0 };
+    for value in values {
+        let true_value = value.to_bits();
+        let (fcm_prediction, dfcm_prediction) = predictors.predict();
+        predictors.update(true_value);
+        sizes.both += encode_value(fcm_prediction, dfcm_prediction, true_value).2;
+        sizes.fcm_only += encode_value(fcm_prediction, fcm_prediction, true_value).2;
+        sizes.dfcm_only += encode_value(dfcm_prediction, dfcm_prediction, true_value).2;
+    }
+    Ok(sizes)
+}
+
+/// Size of the block [`compress`](crate::compress) would produce, from
+/// [`estimate_compressed_size`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressedSize {
+    pub encoding_bytes: usize,
+    pub residual_bytes: usize,
+}
+
+impl CompressedSize {
+    /// Encoding and residual bytes together, as
+    /// [`FPCCompressedBlock::compressed_len`] would report.
+    pub fn total(&self) -> usize {
+        self.encoding_bytes + self.residual_bytes
+    }
+}
+
+/// Computes the exact size of compressing `values` with `table_size`
+/// without writing the encoding or residual, so sweeping table sizes costs
+/// only the predictor updates. Fails like [`compress`](crate::compress) on
+/// an invalid table size.
+pub fn estimate_compressed_size(table_size: u64, values: &[f64]) -> Result<CompressedSize, CompressError> {
+    validate_table_size(table_size)?;
+    if uniform_bits(values.iter().map(|v| v.to_bits())).is_some() {
+        return Ok(CompressedSize { encoding_bytes: 0, residual_bytes: size_of::<u64>() });
+    }
+    Ok(CompressedSize {
+        encoding_bytes: values.len().div_ceil(2),
+        residual_bytes: residual_len(table_size, HashParams::DEFAULT, values.iter().map(|v| v.to_bits())),
+    })
+}
+
+/// How the values of a block were encoded, from [`compress_with_report`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CompressionReport {
+    /// Values encoded against the FCM prediction.
+    pub fcm_wins: u64,
+    /// Values encoded against the DFCM prediction, including ties.
+    pub dfcm_wins: u64,
+    /// Values by the 3-bit leading-zero byte count stored in their code.
+    /// Bucket `i` holds values with `i` leading zero bytes below 4 and
+    /// `i + 1` from 4 up; a count of 4 is stored as 3, so bucket 3 holds
+    /// both 3 and 4 leading zero bytes.
+    pub lzb_histogram: [u64; 8],
+}
+
+/// Compresses `values` like [`compress`](crate::compress) and tallies how
+/// each value was encoded. The tally reads the codes back from the
+/// finished encoding, which costs one pass over half a byte per value; a
+/// constant block is tallied by the codes it would have had.
+pub fn compress_with_report(
+    table_size: u64,
+    values: &[f64],
+) -> Result<(FPCCompressedBlock, CompressionReport), CompressError> {
+    let blk = crate::compress(table_size, values)?;
+    let mut report = CompressionReport::default();
+    for code in blk.full_codes() {
+        if code & 0b1000 != 0 {
+            report.fcm_wins += 1;
+        } else {
+            report.dfcm_wins += 1;
+        }
+        report.lzb_histogram[(code & 0b0111) as usize] += 1;
+    }
+    Ok((blk, report))
+}
+
+/// Shannon entropy of the leading-zero byte counts of a block, from
+/// [`lzb_entropy`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LzbEntropy {
+    /// Entropy of a count in bits, at most 3, which is what the codes
+    /// spend on it.
+    pub bits_per_value: f64,
+    /// Bytes the counts of all values would take if coded at exactly
+    /// their entropy.
+    pub ideal_bytes: usize,
+}
+
+/// Computes the entropy of the leading-zero byte counts tallied in
+/// `lzb_histogram`, such as [`CompressionReport::lzb_histogram`]. Set
+/// against the 3 bits per value the codes spend on the counts, this shows
+/// how much an entropy-coded encoding stream could save. An empty
+/// histogram has zero entropy.
+#[cfg(feature = "std")]
+pub fn lzb_entropy(lzb_histogram: &[u64; 8]) -> LzbEntropy {
+    let num_values: u64 = lzb_histogram.iter().sum();
+    let bits_per_value: f64 = lzb_histogram
+        .iter()
+        .filter(|&&count| count != 0)
+        .map(|&count| {
+            let p = count as f64 / num_values as f64;
+            -p * p.log2()
+        })
+        .sum();
+    let ideal_bytes = (bits_per_value * num_values as f64 / 8.0).ceil() as usize;
+    LzbEntropy { bits_per_value, ideal_bytes }
+}
+
+#[cfg(test)]
+mod analysis_test {
+    use quickcheck_macros::quickcheck;
+
+    use crate::{compress, Predictor, DEFAULT_TABLE_SIZE};
+
+    use super::*;
+
+    #[test]
+    fn test_block_distance_of_identical_blocks_is_zero() {
+        let vals: Vec<f64> = (0..100).map(|x| (x as f64).sin()).collect();
+        let a = compress(DEFAULT_TABLE_SIZE, &vals).unwrap();
+        let b = compress(DEFAULT_TABLE_SIZE, &vals).unwrap();
+        assert_eq!(block_distance(&a, &b), 0.0);
+    }
+
+    #[test]
+    fn test_block_distance_of_different_blocks_is_positive() {
+        let constant = compress(DEFAULT_TABLE_SIZE, &vec![1.0; 100]).unwrap();
+        let noisy = compress(DEFAULT_TABLE_SIZE, &(0..100).map(|x| (x as f64).sin()).collect::<Vec<_>>()).unwrap();
+        let slightly_noisy = compress(
+            DEFAULT_TABLE_SIZE,
+            &(0..100).map(|x| if x % 10 == 0 { (x as f64).sin() } else { 1.0 }).collect::<Vec<_>>(),
+        ).unwrap();
+        let far = block_distance(&constant, &noisy);
+        let near = block_distance(&constant, &slightly_noisy);
+        assert!(near > 0.0);
+        assert!(far > near);
+        assert!(far <= 2.0);
+        assert_eq!(far, block_distance(&noisy, &constant));
+    }
+
+    #[quickcheck]
+    fn partitions_must_cover_every_value(to_compress: Vec<f64>) -> bool {
+        let blk = compress(DEFAULT_TABLE_SIZE, &to_compress).unwrap();
+        let (fcm_values, dfcm_values) = partition_by_predictor(DEFAULT_TABLE_SIZE, &blk).unwrap();
+        fcm_values.len() + dfcm_values.len() == to_compress.len()
+    }
+
+    #[test]
+    fn test_partition_by_predictor_keeps_order_within_each_part() {
+        let vals: Vec<f64> = (0..300).map(|x| if x % 7 < 3 { (x % 7) as f64 } else { x as f64 * 0.1 }).collect();
+        let blk = compress(DEFAULT_TABLE_SIZE, &vals).unwrap();
+        let (fcm_values, dfcm_values) = partition_by_predictor(DEFAULT_TABLE_SIZE, &blk).unwrap();
+        assert!(!fcm_values.is_empty() && !dfcm_values.is_empty());
+        // Merging the parts back by the selection bits restores the input.
+        let (mut fcm_values, mut dfcm_values) = (fcm_values.into_iter(), dfcm_values.into_iter());
+        let merged: Vec<f64> = blk
+            .codes()
+            .map(|code| if code & 0b1000 != 0 { fcm_values.next() } else { dfcm_values.next() }.unwrap())
+            .collect();
+        assert_eq!(merged, vals);
+    }
+
+    #[test]
+    fn test_partition_by_predictor_rejects_other_table_sizes() {
+        let blk = compress(32, &[1.0, 2.5, 3.0]).unwrap();
+        assert_eq!(partition_by_predictor(64, &blk), Err(FpcError::TableSizeMismatch { block: 32, given: 64 }));
+        assert_eq!(partition_by_predictor(0, &blk), Err(FpcError::InvalidTableSize { given: 0 }));
+    }
+
+    #[quickcheck]
+    fn decoded_encoding_must_account_for_the_residual(to_compress: Vec<f64>) -> bool {
+        let blk = compress(DEFAULT_TABLE_SIZE, &to_compress).unwrap();
+        let metas = decode_encoding(&blk);
+        let residual_len: usize = metas.iter().map(|meta| 8 - meta.leading_zero_bytes as usize).sum();
+        metas.len() == to_compress.len() && residual_len == blk.into_parts().2.len()
+    }
+
+    #[test]
+    fn test_decode_encoding_reads_an_odd_final_value() {
+        // 0.0 is predicted exactly; 1.5 has no zero bytes in common with 0.
+        let blk = compress(DEFAULT_TABLE_SIZE, &[0.0, 0.0, 1.5]).unwrap();
+        let metas = decode_encoding(&blk);
+        assert_eq!(metas.len(), 3);
+        assert_eq!(metas[0], ValueMeta { fcm_chosen: false, leading_zero_bytes: 8 });
+        assert_eq!(metas[2].leading_zero_bytes, 0);
+    }
+
+    /// Residual bytes of a full compression that always uses one predictor,
+    /// computed independently through the public [`Predictor`].
+    fn forced_residual_len(table_size: u64, values: &[f64], use_fcm: bool) -> usize {
+        let mut predictor = Predictor::new(table_size);
+        let mut total = 0;
+        for value in values {
+            let (fcm, dfcm) = predictor.predictions();
+            let diff = if use_fcm { fcm } else { dfcm } ^ value.to_bits();
+            let lzb = (diff.leading_zeros() / 8) as usize;
+            total += if lzb == 4 { 5 } else { 8 - lzb };
+            predictor.update(*value);
+        }
+        total
+    }
+
+    #[test]
+    fn test_estimate_mode_sizes_matches_full_compressions() {
+        let datasets: Vec<Vec<f64>> = vec![
+            vec![],
+            vec![1.0; 200],
+            (0..500).map(|x| x as f64 * 0.25).collect(),
+            (0..500).map(|x| (x as f64).sin()).collect(),
+            (0..500).map(|x| if x % 7 < 3 { (x % 7) as f64 } else { x as f64 * 0.1 }).collect(),
+            vec![f64::NAN, -0.0, f64::INFINITY, 1e-310, f64::MIN_POSITIVE, 42.0],
+        ];
+        for table_size in [1, DEFAULT_TABLE_SIZE, 1024] {
+            for values in &datasets {
+                let sizes = estimate_mode_sizes(table_size, values).unwrap();
+                assert_eq!(sizes.both, compress(table_size, values).unwrap().into_parts().2.len());
+                assert_eq!(sizes.fcm_only, forced_residual_len(table_size, values, true));
+                assert_eq!(sizes.dfcm_only, forced_residual_len(table_size, values, false));
+                assert!(sizes.both <= sizes.fcm_only.min(sizes.dfcm_only));
+            }
+        }
+        assert_eq!(estimate_mode_sizes(3, &[1.0]), Err(FpcError::InvalidTableSize { given: 3 }));
+    }
+
+    #[quickcheck]
+    fn estimated_size_must_match_compression(to_compress: Vec<f64>) -> bool {
+        [1, DEFAULT_TABLE_SIZE].iter().all(|&table_size| {
+            let size = estimate_compressed_size(table_size, &to_compress).unwrap();
+            let blk = compress(table_size, &to_compress).unwrap();
+            size.encoding_bytes == blk.encoding.len()
+                && size.residual_bytes == blk.residual.len()
+                && size.total() == blk.compressed_len()
+        })
+    }
+
+    #[test]
+    fn test_estimate_compressed_size_rejects_invalid_table_size() {
+        assert_eq!(estimate_compressed_size(12, &[1.0]), Err(FpcError::InvalidTableSize { given: 12 }));
+    }
+
+    #[test]
+    fn test_compression_report_counts_every_value() {
+        // The tables start empty, so 0.0 matches both predictions exactly
+        // and lands in the bucket for 8 zero bytes.
+        let values = [0.0, 1.0, 1.0, 1.0, 1.0];
+        let (blk, report) = compress_with_report(DEFAULT_TABLE_SIZE, &values).unwrap();
+        assert_eq!(blk, compress(DEFAULT_TABLE_SIZE, &values).unwrap());
+        assert_eq!(report.fcm_wins + report.dfcm_wins, values.len() as u64);
+        assert_eq!(report.lzb_histogram.iter().sum::<u64>(), values.len() as u64);
+        assert!(report.lzb_histogram[7] >= 1);
+        let decoded_fcm = partition_by_predictor(DEFAULT_TABLE_SIZE, &blk).unwrap().0.len();
+        assert_eq!(report.fcm_wins, decoded_fcm as u64);
+        assert_eq!(compress_with_report(5, &values), Err(FpcError::InvalidTableSize { given: 5 }));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_lzb_entropy_of_known_distributions() {
+        assert_eq!(lzb_entropy(&[0; 8]), LzbEntropy { bits_per_value: 0.0, ideal_bytes: 0 });
+        assert_eq!(lzb_entropy(&[0, 0, 0, 0, 0, 0, 0, 40]), LzbEntropy { bits_per_value: 0.0, ideal_bytes: 0 });
+        assert_eq!(lzb_entropy(&[5; 8]), LzbEntropy { bits_per_value: 3.0, ideal_bytes: 15 });
+        let half_and_quarters = lzb_entropy(&[2, 1, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(half_and_quarters, LzbEntropy { bits_per_value: 1.5, ideal_bytes: 1 });
+        let values: Vec<f64> = (0..1000).map(|x| (x as f64 * 0.01).sin()).collect();
+        let (_, report) = compress_with_report(DEFAULT_TABLE_SIZE, &values).unwrap();
+        assert!(lzb_entropy(&report.lzb_histogram).bits_per_value <= 3.0);
+    }
+}
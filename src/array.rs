@@ -0,0 +1,127 @@
+use ndarray::{Array1, Array2, ArrayView1, ArrayView2, ShapeBuilder};
+
+use crate::matrix::check_shape;
+use crate::{
+    compress, compress_iter, try_decompress, CompressError, DecompressError, FPCCompressedBlock, MatrixBlock, Order,
+};
+
+/// Compresses the values of `values` in order, giving the same block as
+/// [`compress`] on them collected into a slice. A view that is not
+/// contiguous, such as every other element of an array, is compressed by
+/// iterating its elements without copying them first.
+pub fn compress_array1(table_size: u64, values: &ArrayView1<f64>) -> Result<FPCCompressedBlock, CompressError> {
+    match values.as_slice() {
+        Some(slice) => compress(table_size, slice),
+        None => compress_iter(table_size, values.iter().copied()),
+    }
+}
+
+/// Decompresses `blk` into an array of [`FPCCompressedBlock::num_values`]
+/// values. Fails like [`try_decompress`].
+pub fn decompress_array1(table_size: u64, blk: &FPCCompressedBlock) -> Result<Array1<f64>, DecompressError> {
+    Ok(Array1::from(try_decompress(table_size, blk)?))
+}
+
+/// Compresses a matrix traversing it in `order`, as
+/// [`compress_2d`](crate::compress_2d) does for its values laid out
+/// row-major. The memory layout of `values` does not matter: transposed
+/// and sliced views give the same block as a standard layout array.
+pub fn compress_array2(table_size: u64, values: &ArrayView2<f64>, order: Order) -> Result<MatrixBlock, CompressError> {
+    // Iterating the transpose goes down each column.
+    let traversed = match order {
+        Order::RowMajor => values.view(),
+        Order::ColumnMajor => values.t(),
+    };
+    let block = match traversed.as_slice() {
+        Some(slice) => compress(table_size, slice)?,
+        None => compress_iter(table_size, traversed.iter().copied())?,
+    };
+    Ok(MatrixBlock { rows: values.nrows(), cols: values.ncols(), order, block })
+}
+
+/// Decompresses a matrix written by [`compress_array2`] or
+/// [`compress_2d`](crate::compress_2d) into an array of its shape. The
+/// values are not reordered: a matrix traversed in
+/// [`Order::ColumnMajor`] comes back as an array in column-major memory
+/// layout. Fails like [`decompress_2d`](crate::decompress_2d).
+pub fn decompress_array2(table_size: u64, matrix: &MatrixBlock) -> Result<Array2<f64>, DecompressError> {
+    let MatrixBlock { rows, cols, order, ref block } = *matrix;
+    check_shape(rows, cols, block.num_values())?;
+    let values = try_decompress(table_size, block)?;
+    let shape = match order {
+        Order::RowMajor => (rows, cols).into_shape_with_order(),
+        Order::ColumnMajor => (rows, cols).f(),
+    };
+    // The shape was checked against the number of values above.
+    Ok(Array2::from_shape_vec(shape, values).unwrap())
+}
+
+#[cfg(test)]
+mod array_test {
+    use ndarray::s;
+    use quickcheck_macros::quickcheck;
+
+    use crate::{bitwise_eq, compress_2d, decompress_2d, FpcError, DEFAULT_TABLE_SIZE};
+
+    use super::*;
+
+    fn grid(rows: usize, cols: usize) -> Array2<f64> {
+        Array2::from_shape_fn((rows, cols), |(row, col)| (row as f64).sqrt() + col as f64 * 0.25)
+    }
+
+    #[quickcheck]
+    fn arrays_must_round_trip(values: Vec<f64>) -> bool {
+        let array = Array1::from(values.clone());
+        let every_other = array.slice(s![..;2]);
+        let blk = compress_array1(DEFAULT_TABLE_SIZE, &array.view()).unwrap();
+        let strided = compress_array1(DEFAULT_TABLE_SIZE, &every_other).unwrap();
+        let expected: Vec<f64> = values.iter().step_by(2).copied().collect();
+        blk == compress(DEFAULT_TABLE_SIZE, &values).unwrap()
+            && decompress_array1(DEFAULT_TABLE_SIZE, &blk)
+                .is_ok_and(|decoded| bitwise_eq(decoded.as_slice().unwrap(), &values))
+            && strided == compress(DEFAULT_TABLE_SIZE, &expected).unwrap()
+    }
+
+    #[test]
+    fn test_matrices_match_compress_2d_in_either_order() {
+        let array = grid(30, 7);
+        let data = array.as_slice().unwrap();
+        for order in [Order::RowMajor, Order::ColumnMajor] {
+            let matrix = compress_array2(DEFAULT_TABLE_SIZE, &array.view(), order).unwrap();
+            assert_eq!(matrix, compress_2d(DEFAULT_TABLE_SIZE, data, 30, 7, order).unwrap());
+            let decoded = decompress_array2(DEFAULT_TABLE_SIZE, &matrix).unwrap();
+            assert_eq!(decoded, array);
+            assert_eq!(decoded.is_standard_layout(), order == Order::RowMajor);
+        }
+    }
+
+    #[test]
+    fn test_views_compress_by_their_logical_order() {
+        let array = grid(20, 12);
+        let views = [array.t(), array.slice(s![..;3, 1..;2]), array.slice(s![..;-1, ..])];
+        for view in views {
+            let owned = view.to_owned();
+            for order in [Order::RowMajor, Order::ColumnMajor] {
+                let matrix = compress_array2(DEFAULT_TABLE_SIZE, &view, order).unwrap();
+                assert_eq!(matrix, compress_array2(DEFAULT_TABLE_SIZE, &owned.view(), order).unwrap());
+                assert_eq!(decompress_array2(DEFAULT_TABLE_SIZE, &matrix).unwrap(), view);
+                let row_major: Vec<f64> = owned.iter().copied().collect();
+                assert_eq!(decompress_2d(DEFAULT_TABLE_SIZE, &matrix).unwrap(), row_major);
+            }
+        }
+        let empty = compress_array2(DEFAULT_TABLE_SIZE, &Array2::zeros((0, 5)).view(), Order::ColumnMajor).unwrap();
+        assert_eq!(decompress_array2(DEFAULT_TABLE_SIZE, &empty).unwrap().dim(), (0, 5));
+    }
+
+    #[test]
+    fn test_decompressing_reports_malformed_blocks() {
+        let column = ArrayView1::from(&[1.0, 2.0, 3.0]);
+        let blk = compress_array1(64, &column).unwrap();
+        assert_eq!(decompress_array1(32, &blk), Err(FpcError::TableSizeMismatch { block: 64, given: 32 }));
+        let matrix = MatrixBlock { rows: 2, cols: 2, order: Order::RowMajor, block: blk };
+        assert_eq!(
+            decompress_array2(64, &matrix),
+            Err(FpcError::ShapeMismatch { rows: 2, cols: 2, len: 3 })
+        );
+    }
+}
@@ -0,0 +1,294 @@
+use std::io::ErrorKind;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::format::{parse_header, HEADER_LEN};
+use crate::stream::{block_from_payload, into_values};
+use crate::writer::DEFAULT_BLOCK_LEN;
+use crate::{compress_unchecked, validate_table_size, CompressError, FpcError, StoredBlock};
+
+/// Compresses a stream of values into an inner [`AsyncWrite`], producing
+/// exactly the bytes [`FpcWriter`](crate::FpcWriter) would, so either
+/// reader, sync or async, can read them back.
+///
+/// Call [`AsyncFpcWriter::finish`] at the end: values still buffered when
+/// the writer is dropped are lost.
+pub struct AsyncFpcWriter<W: AsyncWrite + Unpin> {
+    inner: W,
+    table_size: u64,
+    block_len: usize,
+    buffer: Vec<f64>,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncFpcWriter<W> {
+    /// Writes blocks of 1024 values. Fails like [`compress`](crate::compress)
+    /// on an invalid table size.
+    pub fn new(inner: W, table_size: u64) -> Result<AsyncFpcWriter<W>, CompressError> {
+        AsyncFpcWriter::with_block_len(inner, table_size, DEFAULT_BLOCK_LEN)
+    }
+
+    /// Writes blocks of `block_len` values, which must be at least 1.
+    pub fn with_block_len(inner: W, table_size: u64, block_len: usize) -> Result<AsyncFpcWriter<W>, CompressError> {
+        validate_table_size(table_size)?;
+        if block_len == 0 {
+            return Err(FpcError::InvalidBlockLen { given: block_len });
+        }
+        Ok(AsyncFpcWriter { inner, table_size, block_len, buffer: Vec::with_capacity(block_len) })
+    }
+
+    pub async fn write(&mut self, value: f64) -> Result<(), FpcError> {
+        self.buffer.push(value);
+        if self.buffer.len() == self.block_len {
+            self.write_block().await?;
+        }
+        Ok(())
+    }
+
+    pub async fn write_all(&mut self, values: &[f64]) -> Result<(), FpcError> {
+        for &value in values {
+            self.write(value).await?;
+        }
+        Ok(())
+    }
+
+    /// Writes the buffered values as a block, even if it is not full, and
+    /// flushes the inner writer.
+    pub async fn flush(&mut self) -> Result<(), FpcError> {
+        self.write_block().await?;
+        self.inner.flush().await?;
+        Ok(())
+    }
+
+    /// Flushes the last partial block and returns the inner writer, which
+    /// is not shut down.
+    pub async fn finish(mut self) -> Result<W, FpcError> {
+        self.flush().await?;
+        Ok(self.inner)
+    }
+
+    async fn write_block(&mut self) -> Result<(), FpcError> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let blk = compress_unchecked(self.table_size, &self.buffer);
+        self.buffer.clear();
+        let bytes = blk.to_bytes();
+        #[cfg(feature = "tracing")]
+        tracing::trace!(num_values = blk.num_values(), block_len = bytes.len(), "wrote block");
+        self.inner.write_all(&bytes).await?;
+        Ok(())
+    }
+}
+
+/// Reads the values of a concatenation of serialized blocks from an inner
+/// [`AsyncRead`], one value at a time, as [`FpcReader`](crate::FpcReader)
+/// does. Only the block being read is held in memory.
+///
+/// The stream ends cleanly when the reader is exhausted at a block
+/// boundary. After the first error nothing more is read.
+pub struct AsyncFpcReader<R: AsyncRead + Unpin> {
+    inner: R,
+    block: Vec<f64>,
+    position: usize,
+    done: bool,
+}
+
+impl<R: AsyncRead + Unpin> AsyncFpcReader<R> {
+    pub fn new(inner: R) -> AsyncFpcReader<R> {
+        AsyncFpcReader { inner, block: Vec::new(), position: 0, done: false }
+    }
+
+    /// Returns the next value, or `None` once the stream has ended or
+    /// failed.
+    pub async fn next_value(&mut self) -> Option<Result<f64, FpcError>> {
+        if self.done {
+            return None;
+        }
+        match self.next_block().await {
+            Ok(true) => {
+                self.position += 1;
+                Some(Ok(self.block[self.position - 1]))
+            }
+            Ok(false) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+
+    /// Fills `out` with the next values and returns how many were written,
+    /// which is less than `out.len()` only at the end of the stream.
+    pub async fn read_values(&mut self, out: &mut [f64]) -> Result<usize, FpcError> {
+        for (written, slot) in out.iter_mut().enumerate() {
+            match self.next_value().await {
+                Some(value) => *slot = value?,
+                None => return Ok(written),
+            }
+        }
+        Ok(out.len())
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Reads blocks until one has values, returning `false` at the end of
+    /// the stream.
+    async fn next_block(&mut self) -> Result<bool, FpcError> {
+        while self.position == self.block.len() {
+            self.block = match read_block(&mut self.inner).await? {
+                None => return Ok(false),
+                Some(blk) => into_values(blk)?,
+            };
+            self.position = 0;
+        }
+        Ok(true)
+    }
+}
+
+async fn read_up_to<R: AsyncRead + Unpin>(r: &mut R, buf: &mut [u8]) -> Result<usize, FpcError> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match r.read(&mut buf[filled..]).await {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(filled)
+}
+
+/// The async counterpart of [`crate::stream::read_block`].
+async fn read_block<R: AsyncRead + Unpin>(r: &mut R) -> Result<Option<StoredBlock>, FpcError> {
+    let mut header_bytes = [0_u8; HEADER_LEN];
+    let header_read = read_up_to(r, &mut header_bytes).await?;
+    if header_read == 0 {
+        return Ok(None);
+    }
+    let header = parse_header(&header_bytes[..header_read])?;
+    let mut payload = Vec::new();
+    r.take(header.payload_len() as u64).read_to_end(&mut payload).await?;
+    block_from_payload(&header, payload).map(Some)
+}
+
+#[cfg(test)]
+mod async_io_test {
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    use tokio::io::ReadBuf;
+
+    use crate::{bitwise_eq, compress, decompress_stream, FpcWriter};
+
+    use super::*;
+
+    fn wave(len: usize) -> Vec<f64> {
+        (0..len).map(|x| (x as f64 * 0.01).sin()).collect()
+    }
+
+    async fn read_all<R: AsyncRead + Unpin>(reader: &mut AsyncFpcReader<R>) -> Result<Vec<f64>, FpcError> {
+        let mut values = vec![];
+        while let Some(value) = reader.next_value().await {
+            values.push(value?);
+        }
+        Ok(values)
+    }
+
+    #[tokio::test]
+    async fn test_async_writer_matches_the_sync_writer() {
+        let values = wave(2500);
+        let mut writer = AsyncFpcWriter::with_block_len(Vec::new(), 64, 1000).unwrap();
+        writer.write_all(&values).await.unwrap();
+        let bytes = writer.finish().await.unwrap();
+
+        let mut sync_writer = FpcWriter::with_block_len(Vec::new(), 64, 1000).unwrap();
+        sync_writer.write_all(&values).unwrap();
+        assert_eq!(bytes, sync_writer.finish().unwrap());
+
+        let blocks: Vec<Vec<f64>> = decompress_stream(&mut bytes.as_slice()).collect::<Result<_, _>>().unwrap();
+        assert_eq!(blocks.iter().map(Vec::len).collect::<Vec<_>>(), [1000, 1000, 500]);
+        assert!(bitwise_eq(&blocks.concat(), &values));
+    }
+
+    #[tokio::test]
+    async fn test_async_reader_reads_sync_output() {
+        let values = wave(50);
+        let mut writer = FpcWriter::with_block_len(Vec::new(), 32, 16).unwrap();
+        writer.write_all(&values).unwrap();
+        let mut bytes = writer.finish().unwrap();
+        bytes.extend(StoredBlock::Raw { table_size: 32, values: vec![] }.to_bytes());
+        bytes.extend(StoredBlock::Raw { table_size: 32, values: vec![-1.0] }.to_bytes());
+
+        let read = read_all(&mut AsyncFpcReader::new(bytes.as_slice())).await.unwrap();
+        assert!(bitwise_eq(&read, &[values.as_slice(), &[-1.0]].concat()));
+
+        let mut reader = AsyncFpcReader::new(bytes.as_slice());
+        let mut out = [0.0; 40];
+        assert_eq!(reader.read_values(&mut out).await, Ok(40));
+        assert!(bitwise_eq(&out, &values[..40]));
+        assert_eq!(reader.read_values(&mut out).await, Ok(11));
+        assert_eq!(out[10], -1.0);
+        assert_eq!(reader.read_values(&mut out).await, Ok(0));
+    }
+
+    #[tokio::test]
+    async fn test_values_cross_a_small_pipe() {
+        // The pipe holds less than a block, so the reader sees headers and
+        // payloads arrive in pieces.
+        let values = wave(3000);
+        let (client, server) = tokio::io::duplex(7);
+        let write = async {
+            let mut writer = AsyncFpcWriter::with_block_len(client, 256, 700).unwrap();
+            writer.write_all(&values).await.unwrap();
+            // Dropping the writer's end of the pipe ends the stream.
+            drop(writer.finish().await.unwrap());
+        };
+        let mut reader = AsyncFpcReader::new(server);
+        let ((), read) = tokio::join!(write, read_all(&mut reader));
+        assert!(bitwise_eq(&read.unwrap(), &values));
+    }
+
+    #[tokio::test]
+    async fn test_async_reader_stops_at_the_first_error() {
+        let mut bytes = compress(32, &[1.0, 2.0]).unwrap().to_bytes();
+        bytes.extend_from_slice(b"FPC");
+        let mut reader = AsyncFpcReader::new(bytes.as_slice());
+        assert_eq!(reader.next_value().await, Some(Ok(1.0)));
+        assert_eq!(reader.next_value().await, Some(Ok(2.0)));
+        assert_eq!(reader.next_value().await, Some(Err(FpcError::TruncatedHeader { len: 3 })));
+        assert_eq!(reader.next_value().await, None);
+
+        let first = compress(32, &[1.0, 2.0, 3.0]).unwrap().to_bytes();
+        let expected = first.len();
+        let mut reader = AsyncFpcReader::new(&first[..expected - 1]);
+        assert_eq!(
+            reader.next_value().await,
+            Some(Err(FpcError::TruncatedPayload { expected, available: expected - 1 }))
+        );
+
+        struct Failing;
+        impl AsyncRead for Failing {
+            fn poll_read(self: Pin<&mut Self>, _: &mut Context<'_>, _: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+                Poll::Ready(Err(ErrorKind::ConnectionReset.into()))
+            }
+        }
+        assert!(matches!(
+            AsyncFpcReader::new(Failing).next_value().await,
+            Some(Err(FpcError::Io { kind: ErrorKind::ConnectionReset, .. }))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_async_writer_rejects_bad_configuration() {
+        assert!(matches!(AsyncFpcWriter::new(Vec::new(), 3), Err(FpcError::InvalidTableSize { given: 3 })));
+        assert!(matches!(
+            AsyncFpcWriter::with_block_len(Vec::new(), 32, 0),
+            Err(FpcError::InvalidBlockLen { given: 0 })
+        ));
+    }
+}
@@ -0,0 +1,226 @@
+//! Files in the format of the reference FPC tool by Burtscher and
+//! Ratanaworabhan, for exchanging data with it. Its predictors are the
+//! ones of this crate with the default [`HashParams`], and they carry
+//! over from one block to the next.
+//!
+//! A file starts with one byte holding the base-2 logarithm of the table
+//! size, followed by blocks of at most 32768 values. Each block is laid
+//! out as follows, with little-endian integers:
+//!
+//! | Offset | Size | Field                                       |
+//! |--------|------|---------------------------------------------|
+//! | 0      | 3    | number of values `n`                        |
+//! | 3      | 3    | length of the block, these 6 bytes included |
+//! | 6      | `E`  | codes, `(n + 1) / 2` bytes                  |
+//! | 6 + E  | rest | residual                                    |
+//!
+//! Every code byte holds the codes of two values, the first in the high
+//! nibble. A code's top bit is set if the value was XORed with the DFCM
+//! prediction rather than the FCM one, and its low 3 bits give the number
+//! of residual bytes: 0 to 3 stand for as many bytes and 4 to 7 for one
+//! more, so a value never takes 4. The residual bytes of a value are the
+//! low bytes of its XOR difference, least significant first.
+//!
+//! The tool always codes values in pairs. The second half of the last pair
+//! of a block of odd length is still decoded, updating the predictors, and
+//! then dropped; this module pads with 0.0.
+
+use std::io::{Read, Write};
+
+use crate::stream::read_up_to;
+use crate::{encode_value, is_valid_table_size, validate_table_size, FpcError, HashParams, Predictors};
+
+const BLOCK_VALUES: usize = 32768;
+const BLOCK_HEADER_LEN: usize = 6;
+
+/// Turns a code of this crate into one of the reference format.
+fn reference_code(crate_code: u8, dfcm_chosen: bool) -> u8 {
+    (if dfcm_chosen { 0b1000 } else { 0 }) | (7 - (crate_code & 0b0111))
+}
+
+fn reference_residual_len(code: u8) -> usize {
+    let len = (code & 0b0111) as usize;
+    len + (len >> 2)
+}
+
+/// Writes `values` as a file of the reference FPC tool using predictor
+/// tables of `table_size` entries. Fails with [`FpcError::InvalidTableSize`]
+/// unless `table_size` is a power of two, and with [`FpcError::Io`] if
+/// writing fails.
+pub fn write_fpc_file<W: Write>(w: &mut W, table_size: u64, values: &[f64]) -> Result<(), FpcError> {
+    validate_table_size(table_size)?;
+    w.write_all(&[table_size.trailing_zeros() as u8])?;
+    let mut predictors = Predictors::with_hash_params(table_size, HashParams::DEFAULT);
+    let mut block = Vec::new();
+    for chunk in values.chunks(BLOCK_VALUES) {
+        let encoding_len = chunk.len().div_ceil(2);
+        block.clear();
+        block.resize(BLOCK_HEADER_LEN + encoding_len, 0);
+        let padding = if chunk.len() % 2 == 1 { Some(0.0) } else { None };
+        for (index, value) in chunk.iter().copied().chain(padding).enumerate() {
+            let bits = value.to_bits();
+            let (fcm_prediction, dfcm_prediction) = predictors.predict();
+            predictors.update(bits);
+            let (code, diff, residual_len) = encode_value(fcm_prediction, dfcm_prediction, bits);
+            // The tool takes the FCM prediction on a tie, unlike encode_value.
+            let dfcm_chosen = fcm_prediction ^ bits > dfcm_prediction ^ bits;
+            let code = reference_code(code, dfcm_chosen);
+            block[BLOCK_HEADER_LEN + index / 2] |= if index % 2 == 0 { code << 4 } else { code };
+            block.extend_from_slice(&diff.to_le_bytes()[..residual_len]);
+        }
+        block[..3].copy_from_slice(&(chunk.len() as u32).to_le_bytes()[..3]);
+        let block_len = block.len() as u32;
+        block[3..6].copy_from_slice(&block_len.to_le_bytes()[..3]);
+        w.write_all(&block)?;
+    }
+    Ok(())
+}
+
+fn read_u24(bytes: &[u8]) -> usize {
+    bytes[0] as usize | (bytes[1] as usize) << 8 | (bytes[2] as usize) << 16
+}
+
+/// Reads all values of a file of the reference FPC tool, such as one
+/// written by [`write_fpc_file`]. Fails with [`FpcError::TruncatedHeader`]
+/// or [`FpcError::TruncatedPayload`] if the file ends within a block,
+/// with [`FpcError::InconsistentBlock`] or [`FpcError::TruncatedResidual`]
+/// if a block does not hold the values it declares, and with
+/// [`FpcError::InvalidTableSizeLog`] if its table would exceed
+/// [`MAX_TABLE_SIZE`](crate::MAX_TABLE_SIZE).
+pub fn read_fpc_file<R: Read>(r: &mut R) -> Result<Vec<f64>, FpcError> {
+    let mut log_table_size = [0_u8; 1];
+    if read_up_to(r, &mut log_table_size)? == 0 {
+        return Err(FpcError::TruncatedHeader { len: 0 });
+    }
+    let table_size = 1_u64
+        .checked_shl(log_table_size[0] as u32)
+        .filter(|&table_size| is_valid_table_size(table_size))
+        .ok_or(FpcError::InvalidTableSizeLog { given: log_table_size[0] })?;
+    let mut predictors = Predictors::with_hash_params(table_size, HashParams::DEFAULT);
+    let mut values = Vec::new();
+    let mut header = [0_u8; BLOCK_HEADER_LEN];
+    let mut block = Vec::new();
+    loop {
+        match read_up_to(r, &mut header)? {
+            0 => return Ok(values),
+            BLOCK_HEADER_LEN => {}
+            len => return Err(FpcError::TruncatedHeader { len }),
+        }
+        let (num_values, block_len) = (read_u24(&header[..3]), read_u24(&header[3..]));
+        let encoding_len = num_values.div_ceil(2);
+        if block_len < BLOCK_HEADER_LEN + encoding_len {
+            return Err(FpcError::InconsistentBlock {
+                num_values,
+                encoding_len: block_len.saturating_sub(BLOCK_HEADER_LEN),
+            });
+        }
+        block.clear();
+        r.take((block_len - BLOCK_HEADER_LEN) as u64).read_to_end(&mut block)?;
+        if block.len() < block_len - BLOCK_HEADER_LEN {
+            return Err(FpcError::TruncatedPayload { expected: block_len, available: BLOCK_HEADER_LEN + block.len() });
+        }
+        let (encoding, residual) = block.split_at(encoding_len);
+        let mut residual_index = 0;
+        for index in 0..2 * encoding_len {
+            let code = if index % 2 == 0 { encoding[index / 2] >> 4 } else { encoding[index / 2] & 0x0f };
+            let len = reference_residual_len(code);
+            let truncated = FpcError::TruncatedResidual { at_value: values.len(), residual_len: residual.len() };
+            let bytes = residual.get(residual_index..residual_index + len).ok_or(truncated)?;
+            residual_index += len;
+            let diff = bytes.iter().rev().fold(0_u64, |acc, &b| (acc << 8) | b as u64);
+            let (fcm_prediction, dfcm_prediction) = predictors.predict();
+            let bits = diff ^ if code & 0b1000 != 0 { dfcm_prediction } else { fcm_prediction };
+            predictors.update(bits);
+            if index < num_values {
+                values.push(f64::from_bits(bits));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod burtscher_test {
+    use quickcheck_macros::quickcheck;
+
+    use crate::{bitwise_eq, compress, DEFAULT_TABLE_SIZE};
+
+    use super::*;
+
+    fn write_to_vec(table_size: u64, values: &[f64]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_fpc_file(&mut bytes, table_size, values).unwrap();
+        bytes
+    }
+
+    #[quickcheck]
+    fn fpc_files_must_round_trip(to_compress: Vec<f64>, log_size: u8) -> bool {
+        let table_size = 1 << (log_size % 16);
+        let bytes = write_to_vec(table_size, &to_compress);
+        bytes[0] as u64 == table_size.trailing_zeros() as u64
+            && bitwise_eq(&read_fpc_file(&mut bytes.as_slice()).unwrap(), &to_compress)
+    }
+
+    #[quickcheck]
+    fn fpc_files_must_hold_the_residual_of_compress(to_compress: Vec<f64>) -> bool {
+        // An even count keeps padding out of the comparison.
+        let to_compress = &to_compress[..to_compress.len() & !1];
+        let bytes = write_to_vec(DEFAULT_TABLE_SIZE, to_compress);
+        let blk = compress(DEFAULT_TABLE_SIZE, to_compress).unwrap().with_full_codes().into_owned();
+        let mut expected: Vec<u8> = Vec::new();
+        let mut residual_index = 0;
+        for code in blk.codes() {
+            let len = crate::residual_len_of_code(code);
+            expected.extend(blk.residual()[residual_index..residual_index + len].iter().rev());
+            residual_index += len;
+        }
+        let residual_start = 1 + if to_compress.is_empty() { 0 } else { BLOCK_HEADER_LEN + blk.encoding().len() };
+        to_compress.len() > BLOCK_VALUES || bytes[residual_start..] == expected
+    }
+
+    #[test]
+    fn test_hand_assembled_reference_vector() {
+        // Table size 16. 0.0 and the second 1.0 match the FCM prediction
+        // exactly. For 1.0 and 2.0 both predictors agree, on 0.0 and 1.0,
+        // so the tie goes to FCM and all 8 bytes of the XOR are kept.
+        let bytes = [
+            0x04, // log2 of the table size
+            0x04, 0x00, 0x00, 0x18, 0x00, 0x00, // 4 values, 24 bytes
+            0x07, 0x07, // codes
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xf0, 0x3f, // 1.0 ^ 0
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xf0, 0x7f, // 2.0 ^ 1.0
+        ];
+        let values = [0.0, 1.0, 1.0, 2.0];
+        assert_eq!(read_fpc_file(&mut bytes.as_slice()).unwrap(), values);
+        assert_eq!(write_to_vec(16, &values), bytes);
+    }
+
+    #[test]
+    fn test_fpc_files_span_blocks_and_odd_counts() {
+        let values: Vec<f64> = (0..2 * BLOCK_VALUES + 3).map(|x| (x as f64 * 0.001).sin()).collect();
+        let bytes = write_to_vec(DEFAULT_TABLE_SIZE, &values);
+        assert_eq!(read_fpc_file(&mut bytes.as_slice()).unwrap(), values);
+        assert!(read_fpc_file(&mut [20_u8].as_slice()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_read_fpc_file_rejects_malformed_files() {
+        let bytes = write_to_vec(DEFAULT_TABLE_SIZE, &[1.0, 2.5, 3.0]);
+        assert_eq!(read_fpc_file(&mut [].as_slice()), Err(FpcError::TruncatedHeader { len: 0 }));
+        assert_eq!(read_fpc_file(&mut &bytes[..4]), Err(FpcError::TruncatedHeader { len: 3 }));
+        assert!(matches!(read_fpc_file(&mut &bytes[..bytes.len() - 1]), Err(FpcError::TruncatedPayload { .. })));
+        for log in [25, 40, 64] {
+            assert_eq!(read_fpc_file(&mut [log].as_slice()), Err(FpcError::InvalidTableSizeLog { given: log }));
+        }
+        let mut short_block = bytes.clone();
+        short_block[4] = 6;
+        assert_eq!(
+            read_fpc_file(&mut short_block.as_slice()),
+            Err(FpcError::InconsistentBlock { num_values: 3, encoding_len: 0 })
+        );
+        let mut short_residual = bytes.clone();
+        short_residual[4] = 10;
+        short_residual.truncate(1 + 10);
+        assert!(matches!(read_fpc_file(&mut short_residual.as_slice()), Err(FpcError::TruncatedResidual { .. })));
+        assert_eq!(write_fpc_file(&mut Vec::new(), 3, &[1.0]), Err(FpcError::InvalidTableSize { given: 3 }));
+    }
+}
@@ -0,0 +1,265 @@
+//! Compression in independently decodable chunks, for random access.
+//!
+//! Every chunk starts from zeroed predictor tables, so any chunk can be
+//! decoded without the ones before it. The price is compression ratio:
+//! the tables have to warm up again at each chunk boundary, and a chunk of
+//! odd length pads its last encoding byte. Both costs shrink as chunks get
+//! longer, so pick the longest chunks the access pattern allows; chunks of
+//! a few times the table size or more lose little.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::{
+    decode_value, encode_bits, validate_table_size, CompressError, DecompressError, EncodingSink, FpcError, Predictors,
+};
+
+/// Values compressed in chunks of `chunk_len` values by [`compress_chunked`].
+/// Chunk `i` holds `values[i * chunk_len..]` up to the next chunk, stored as
+/// its encoding followed by its residual.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChunkedBlock {
+    table_size: u64,
+    chunk_len: usize,
+    num_values: usize,
+    /// Where each chunk starts in `data`.
+    chunk_offsets: Vec<usize>,
+    data: Vec<u8>,
+}
+
+impl ChunkedBlock {
+    pub fn num_values(&self) -> usize {
+        self.num_values
+    }
+
+    pub fn chunk_len(&self) -> usize {
+        self.chunk_len
+    }
+
+    pub fn table_size(&self) -> u64 {
+        self.table_size
+    }
+
+    /// Byte offset of every chunk in the compressed data.
+    pub fn chunk_offsets(&self) -> &[usize] {
+        &self.chunk_offsets
+    }
+
+    /// Size of the compressed data of all chunks in bytes.
+    pub fn compressed_len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Indices of the values in chunk `chunk` and its bytes in `data`.
+    fn chunk(&self, chunk: usize) -> (Range<usize>, &[u8]) {
+        let first = chunk * self.chunk_len;
+        let values = first..(first + self.chunk_len).min(self.num_values);
+        let end = self.chunk_offsets.get(chunk + 1).copied().unwrap_or(self.data.len());
+        (values, &self.data[self.chunk_offsets[chunk]..end])
+    }
+}
+
+/// Writes the encoding of one chunk into space reserved ahead of its
+/// residual, so the chunk's bytes come out contiguous in one buffer.
+struct ChunkSink<'a> {
+    data: &'a mut Vec<u8>,
+    next_encoding: usize,
+}
+
+impl EncodingSink for ChunkSink<'_> {
+    fn push_encoding(&mut self, byte: u8) {
+        self.data[self.next_encoding] = byte;
+        self.next_encoding += 1;
+    }
+
+    fn push_residual(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+}
+
+/// Appends the encoding and residual of `chunk`, compressed from zeroed
+/// tables, to `data`.
+pub(crate) fn append_chunk(predictors: &mut Predictors, chunk: &[f64], data: &mut Vec<u8>) {
+    predictors.reset(predictors.fcm.len() as u64);
+    let next_encoding = data.len();
+    data.resize(next_encoding + chunk.len().div_ceil(2), 0);
+    encode_bits(predictors, chunk.iter().map(|v| v.to_bits()), ChunkSink { data, next_encoding }, None);
+}
+
+pub(crate) fn validate_chunk_len(chunk_len: usize) -> Result<(), FpcError> {
+    if chunk_len == 0 {
+        return Err(FpcError::InvalidBlockLen { given: chunk_len });
+    }
+    Ok(())
+}
+
+/// Compresses `values` in chunks of `chunk_len` values (the last one may
+/// be shorter), each decodable on its own with [`decompress_range`].
+/// Fails on an invalid table size or a `chunk_len` of 0.
+pub fn compress_chunked(table_size: u64, values: &[f64], chunk_len: usize) -> Result<ChunkedBlock, CompressError> {
+    validate_table_size(table_size)?;
+    validate_chunk_len(chunk_len)?;
+    let mut predictors = Predictors::new(table_size);
+    let mut chunk_offsets = Vec::with_capacity(values.len().div_ceil(chunk_len));
+    let mut data = Vec::new();
+    for chunk in values.chunks(chunk_len) {
+        chunk_offsets.push(data.len());
+        append_chunk(&mut predictors, chunk, &mut data);
+    }
+    Ok(ChunkedBlock { table_size, chunk_len, num_values: values.len(), chunk_offsets, data })
+}
+
+/// Compresses like [`compress_chunked`], spreading the chunks over rayon's
+/// thread pool. Every chunk starts from zeroed tables, so the result is
+/// identical to [`compress_chunked`] for any number of threads.
+#[cfg(feature = "rayon")]
+pub fn compress_parallel(table_size: u64, values: &[f64], chunk_len: usize) -> Result<ChunkedBlock, CompressError> {
+    use rayon::prelude::*;
+
+    validate_table_size(table_size)?;
+    validate_chunk_len(chunk_len)?;
+    let chunks: Vec<Vec<u8>> = values
+        .par_chunks(chunk_len)
+        .map_init(
+            || Predictors::new(table_size),
+            |predictors, chunk| {
+                let mut data = Vec::new();
+                append_chunk(predictors, chunk, &mut data);
+                data
+            },
+        )
+        .collect();
+    let mut chunk_offsets = Vec::with_capacity(chunks.len());
+    let mut data = Vec::with_capacity(chunks.iter().map(Vec::len).sum());
+    for chunk in chunks {
+        chunk_offsets.push(data.len());
+        data.extend_from_slice(&chunk);
+    }
+    Ok(ChunkedBlock { table_size, chunk_len, num_values: values.len(), chunk_offsets, data })
+}
+
+/// Decodes `values[start..end]` of a chunked block. Only the chunks
+/// overlapping the range are decoded, each from its first value up to the
+/// end of the range.
+pub fn decompress_range(blk: &ChunkedBlock, start: usize, end: usize) -> Result<Vec<f64>, DecompressError> {
+    if start > end || end > blk.num_values {
+        return Err(FpcError::RangeOutOfBounds { start, end, num_values: blk.num_values });
+    }
+    if start == end {
+        return Ok(Vec::new());
+    }
+    let first_chunk = start / blk.chunk_len;
+    let base = first_chunk * blk.chunk_len;
+    let mut out = vec![0.0; end - base];
+    let mut predictors = Predictors::new(blk.table_size);
+    for chunk in first_chunk..=(end - 1) / blk.chunk_len {
+        let (values, _) = blk.chunk(chunk);
+        blk.decode_chunk(chunk, &mut predictors, &mut out[values.start - base..values.end.min(end) - base])?;
+    }
+    out.drain(..start - base);
+    Ok(out)
+}
+
+/// Decodes every chunk of `blk` on rayon's thread pool, each straight into
+/// its place in the result. The values are identical to decoding the whole
+/// block with [`decompress_range`].
+#[cfg(feature = "rayon")]
+pub fn decompress_parallel(blk: &ChunkedBlock) -> Result<Vec<f64>, DecompressError> {
+    use rayon::prelude::*;
+
+    let mut out = vec![0.0; blk.num_values];
+    out.par_chunks_mut(blk.chunk_len).enumerate().try_for_each_init(
+        || Predictors::new(blk.table_size),
+        |predictors, (chunk, out)| blk.decode_chunk(chunk, predictors, out),
+    )?;
+    Ok(out)
+}
+
+impl ChunkedBlock {
+    /// Decodes the first `out.len()` values of chunk `chunk` into `out`.
+    fn decode_chunk(&self, chunk: usize, predictors: &mut Predictors, out: &mut [f64]) -> Result<(), FpcError> {
+        let (values, bytes) = self.chunk(chunk);
+        let (encoding, residual) = bytes.split_at(values.len().div_ceil(2));
+        predictors.reset(self.table_size);
+        let mut residual_index = 0;
+        let codes = encoding.iter().flat_map(|&byte| [byte >> 4, byte & 0xf]);
+        for ((index, code), slot) in values.zip(codes).zip(out) {
+            let decoded = decode_value(predictors, code, residual, &mut residual_index)
+                .ok_or(FpcError::TruncatedResidual { at_value: index, residual_len: residual.len() })?;
+            *slot = f64::from_bits(decoded);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod chunked_test {
+    use quickcheck_macros::quickcheck;
+
+    use crate::{bitwise_eq, compress, DEFAULT_TABLE_SIZE};
+
+    use super::*;
+
+    #[quickcheck]
+    fn every_range_must_decode_to_its_values(to_compress: Vec<f64>, chunk_len: u8, start: usize, len: usize) -> bool {
+        let chunk_len = chunk_len as usize + 1;
+        let blk = compress_chunked(DEFAULT_TABLE_SIZE, &to_compress, chunk_len).unwrap();
+        let start = start % (to_compress.len() + 1);
+        let end = start + len % (to_compress.len() - start + 1);
+        decompress_range(&blk, start, end).is_ok_and(|values| bitwise_eq(&values, &to_compress[start..end]))
+    }
+
+    #[test]
+    fn test_chunks_match_standalone_blocks() {
+        let values: Vec<f64> = (0..250).map(|x| (x as f64 * 0.1).cos()).collect();
+        let blk = compress_chunked(DEFAULT_TABLE_SIZE, &values, 100).unwrap();
+        assert_eq!(blk.chunk_offsets().len(), 3);
+        for (chunk, expected) in values.chunks(100).enumerate() {
+            let expected = compress(DEFAULT_TABLE_SIZE, expected).unwrap();
+            let (_, bytes) = blk.chunk(chunk);
+            assert_eq!(bytes, [expected.encoding(), expected.residual()].concat());
+        }
+        let whole = compress(DEFAULT_TABLE_SIZE, &values).unwrap();
+        assert!(blk.compressed_len() >= whole.compressed_len());
+    }
+
+    #[test]
+    fn test_chunked_rejects_bad_arguments() {
+        assert_eq!(compress_chunked(DEFAULT_TABLE_SIZE, &[1.0], 0), Err(FpcError::InvalidBlockLen { given: 0 }));
+        assert_eq!(compress_chunked(7, &[1.0], 4), Err(FpcError::InvalidTableSize { given: 7 }));
+        let blk = compress_chunked(DEFAULT_TABLE_SIZE, &[1.0, 2.0, 3.0], 2).unwrap();
+        assert_eq!(decompress_range(&blk, 1, 4), Err(FpcError::RangeOutOfBounds { start: 1, end: 4, num_values: 3 }));
+        assert_eq!(decompress_range(&blk, 2, 1), Err(FpcError::RangeOutOfBounds { start: 2, end: 1, num_values: 3 }));
+        assert_eq!(decompress_range(&blk, 3, 3), Ok(vec![]));
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_parallel_matches_sequential_for_any_thread_count() {
+        let values: Vec<f64> = (0..100_000).map(|x| (x as f64 * 0.001).sin() * 100.0).collect();
+        for chunk_len in [1, 999, 4096, 200_000] {
+            let expected = compress_chunked(DEFAULT_TABLE_SIZE, &values, chunk_len).unwrap();
+            for threads in [1, 2, 7] {
+                let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+                let parallel = pool.install(|| compress_parallel(DEFAULT_TABLE_SIZE, &values, chunk_len)).unwrap();
+                assert_eq!(parallel, expected, "chunk_len {chunk_len}, {threads} threads");
+            }
+        }
+        assert_eq!(compress_parallel(DEFAULT_TABLE_SIZE, &values, 0), Err(FpcError::InvalidBlockLen { given: 0 }));
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_parallel_decompression_matches_sequential() {
+        let values: Vec<f64> = (0..100_000).map(|x| if x % 13 == 0 { f64::NAN } else { (x as f64).sqrt() }).collect();
+        for chunk_len in [1, 999, 4096, 200_000] {
+            let blk = compress_parallel(DEFAULT_TABLE_SIZE, &values, chunk_len).unwrap();
+            let decoded = decompress_parallel(&blk).unwrap();
+            assert!(bitwise_eq(&decoded, &decompress_range(&blk, 0, values.len()).unwrap()));
+            assert!(bitwise_eq(&decoded, &values));
+        }
+        let empty = compress_parallel(DEFAULT_TABLE_SIZE, &[], 16).unwrap();
+        assert_eq!(decompress_parallel(&empty), Ok(vec![]));
+    }
+}
@@ -0,0 +1,68 @@
+use alloc::vec::Vec;
+
+use crate::{compress, try_decompress, CompressError, DecompressError, FPCCompressedBlock, FpcError};
+
+/// Fails with [`FpcError::ColumnLengthMismatch`] at the first length that
+/// differs from the first one.
+pub(crate) fn check_column_lens(lens: impl Iterator<Item = usize>) -> Result<(), FpcError> {
+    let mut lens = lens.enumerate();
+    let Some((_, expected)) = lens.next() else {
+        return Ok(());
+    };
+    match lens.find(|&(_, len)| len != expected) {
+        Some((column, actual)) => Err(FpcError::ColumnLengthMismatch { column, expected, actual }),
+        None => Ok(()),
+    }
+}
+
+/// Compresses every column of a table into a block of its own, in order.
+/// Values of one column tend to resemble each other far more than the
+/// fields of a row do, so this usually beats compressing the rows. All
+/// columns must hold the same number of values.
+pub fn compress_columns(table_size: u64, columns: &[&[f64]]) -> Result<Vec<FPCCompressedBlock>, CompressError> {
+    check_column_lens(columns.iter().map(|column| column.len()))?;
+    columns.iter().map(|column| compress(table_size, column)).collect()
+}
+
+/// Decompresses blocks written by [`compress_columns`] back into their
+/// columns. Fails like [`try_decompress`] on a malformed block, and with
+/// [`FpcError::ColumnLengthMismatch`] unless all blocks hold the same
+/// number of values.
+pub fn decompress_columns(table_size: u64, blocks: &[FPCCompressedBlock]) -> Result<Vec<Vec<f64>>, DecompressError> {
+    check_column_lens(blocks.iter().map(FPCCompressedBlock::num_values))?;
+    blocks.iter().map(|blk| try_decompress(table_size, blk)).collect()
+}
+
+#[cfg(test)]
+mod columns_test {
+    use quickcheck_macros::quickcheck;
+
+    use crate::{bitwise_eq, DEFAULT_TABLE_SIZE};
+
+    use super::*;
+
+    #[quickcheck]
+    fn columns_must_round_trip(rows: Vec<(f64, f64, f64)>) -> bool {
+        let a: Vec<f64> = rows.iter().map(|row| row.0).collect();
+        let b: Vec<f64> = rows.iter().map(|row| row.1).collect();
+        let c: Vec<f64> = rows.iter().map(|row| row.2).collect();
+        let blocks = compress_columns(DEFAULT_TABLE_SIZE, &[&a, &b, &c]).unwrap();
+        let columns = decompress_columns(DEFAULT_TABLE_SIZE, &blocks).unwrap();
+        columns.len() == 3 && [&a, &b, &c].iter().zip(&columns).all(|(original, decoded)| bitwise_eq(original, decoded))
+    }
+
+    #[test]
+    fn test_columns_must_have_equal_lengths() {
+        let (a, b) = ([1.0, 2.0, 3.0], [4.0, 5.0]);
+        assert_eq!(
+            compress_columns(DEFAULT_TABLE_SIZE, &[&a, &a, &b]),
+            Err(FpcError::ColumnLengthMismatch { column: 2, expected: 3, actual: 2 })
+        );
+        assert_eq!(compress_columns(DEFAULT_TABLE_SIZE, &[]), Ok(Vec::new()));
+        let blocks = [compress(DEFAULT_TABLE_SIZE, &b).unwrap(), compress(DEFAULT_TABLE_SIZE, &a).unwrap()];
+        assert_eq!(
+            decompress_columns(DEFAULT_TABLE_SIZE, &blocks),
+            Err(FpcError::ColumnLengthMismatch { column: 1, expected: 2, actual: 3 })
+        );
+    }
+}
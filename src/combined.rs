@@ -0,0 +1,71 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::{compress_into, decompress_ref, CompressError, CompressedBlockRef, DecompressError, EncodingSink};
+
+/// Writes the encoding into the reserved front of a buffer and appends
+/// the residual after it.
+struct CombinedSink<'a> {
+    bytes: &'a mut Vec<u8>,
+    encoding_pos: usize,
+}
+
+impl EncodingSink for CombinedSink<'_> {
+    fn push_encoding(&mut self, byte: u8) {
+        self.bytes[self.encoding_pos] = byte;
+        self.encoding_pos += 1;
+    }
+
+    fn push_residual(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+}
+
+/// Compresses `fp_values` into a single buffer holding the encoding
+/// followed by the residual, returned with the number of values. The
+/// encoding takes the first `(num_values + 1) / 2` bytes, so the number
+/// of values is all [`decompress_combined`] needs to split the buffer
+/// again. The bytes are the streams of [`compress`](crate::compress) with
+/// full codes, as returned by
+/// [`FPCCompressedBlock::into_parts`](crate::FPCCompressedBlock::into_parts).
+pub fn compress_combined(table_size: u64, fp_values: &[f64]) -> Result<(usize, Vec<u8>), CompressError> {
+    let encoding_len = fp_values.len().div_ceil(2);
+    let mut bytes = vec![0; encoding_len];
+    bytes.reserve(size_of_val(fp_values) / 4);
+    compress_into(table_size, fp_values, CombinedSink { bytes: &mut bytes, encoding_pos: 0 })?;
+    Ok((fp_values.len(), bytes))
+}
+
+/// Decompresses `num_values` values from a buffer written by
+/// [`compress_combined`] with the same `table_size`. Fails like
+/// [`decompress_ref`] if the buffer does not hold that many values.
+pub fn decompress_combined(table_size: u64, num_values: usize, bytes: &[u8]) -> Result<Vec<f64>, DecompressError> {
+    let (encoding, residual) = bytes.split_at(num_values.div_ceil(2).min(bytes.len()));
+    decompress_ref(table_size, &CompressedBlockRef { num_bytes_encoded: num_values, encoding, residual })
+}
+
+#[cfg(test)]
+mod combined_test {
+    use quickcheck_macros::quickcheck;
+
+    use crate::{bitwise_eq, compress, FpcError, DEFAULT_TABLE_SIZE};
+
+    use super::*;
+
+    #[quickcheck]
+    fn combined_buffer_must_round_trip(to_compress: Vec<f64>) -> bool {
+        let (num_values, bytes) = compress_combined(DEFAULT_TABLE_SIZE, &to_compress).unwrap();
+        let (_, encoding, residual) = compress(DEFAULT_TABLE_SIZE, &to_compress).unwrap().into_parts();
+        num_values == to_compress.len()
+            && bytes == [encoding, residual].concat()
+            && bitwise_eq(&decompress_combined(DEFAULT_TABLE_SIZE, num_values, &bytes).unwrap(), &to_compress)
+    }
+
+    #[test]
+    fn test_decompress_combined_rejects_short_buffers() {
+        let (num_values, bytes) = compress_combined(DEFAULT_TABLE_SIZE, &[1.0, 2.5, 3.0]).unwrap();
+        assert!(decompress_combined(DEFAULT_TABLE_SIZE, num_values, &bytes[..bytes.len() - 1]).is_err());
+        assert!(decompress_combined(DEFAULT_TABLE_SIZE, num_values, &bytes[..1]).is_err());
+        assert_eq!(compress_combined(3, &[1.0]), Err(FpcError::InvalidTableSize { given: 3 }));
+    }
+}
@@ -0,0 +1,455 @@
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::encoding_mode::{uniform_bits, UniformScan};
+use crate::{
+    decode_value, encode_bits, encode_bits_pending, residual_capacity, try_reserve, validate_table_size, CompressError,
+    DecompressError, EncodingMode, FPCCompressedBlock, FpcError, HashParams, Predictors,
+};
+
+/// Compresses values one at a time, keeping the predictor state between
+/// calls, for streams too large to collect up front. The finished block
+/// is identical to compressing all pushed values with [`compress`](crate::compress).
+pub struct Compressor {
+    table_size: u64,
+    predictors: Predictors,
+    num_values: usize,
+    encoding: Vec<u8>,
+    residual: Vec<u8>,
+    // Code of the last value while it waits for the second half of its
+    // encoding byte.
+    pending_code: Option<u8>,
+    // Whether the values so far make a constant block.
+    scan: UniformScan,
+    // Interval and callback set by on_progress.
+    progress: Option<(usize, ProgressCallback)>,
+}
+
+type ProgressCallback = Box<dyn FnMut(CompressorProgress) + Send>;
+
+/// How far a [`Compressor`] has got, as passed to the callback of
+/// [`Compressor::on_progress`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CompressorProgress {
+    pub values_seen: usize,
+    /// Bytes of the encoding written so far, not counting the code of a
+    /// value still waiting for the second half of its byte.
+    pub encoding_bytes: usize,
+    pub residual_bytes: usize,
+}
+
+impl Compressor {
+    /// Fails like [`compress`](crate::compress) on an invalid table size.
+    pub fn new(table_size: u64) -> Result<Compressor, CompressError> {
+        validate_table_size(table_size)?;
+        Ok(Compressor::with_valid_table_size(table_size))
+    }
+
+    /// Like [`Compressor::new`], with room reserved for about
+    /// `expected_values` values, using the same estimate as
+    /// [`compress`](crate::compress).
+    pub fn with_capacity(table_size: u64, expected_values: usize) -> Result<Compressor, CompressError> {
+        let mut compressor = Compressor::new(table_size)?;
+        compressor.reserve(expected_values);
+        Ok(compressor)
+    }
+
+    /// Reserves room for at least `additional` more values in the
+    /// encoding, and for 2 residual bytes for each of them, which
+    /// compressible data rarely exceeds. Room the allocator cannot grant
+    /// is left to be allocated as values arrive.
+    pub fn reserve(&mut self, additional: usize) {
+        let codes = self.num_values.saturating_add(additional);
+        let encoding_len = self.encoding.len();
+        try_reserve(&mut self.encoding, codes.div_ceil(2).saturating_sub(encoding_len));
+        try_reserve(&mut self.residual, residual_capacity(additional));
+    }
+
+    fn with_valid_table_size(table_size: u64) -> Compressor {
+        Compressor {
+            table_size,
+            predictors: Predictors::new(table_size),
+            num_values: 0,
+            encoding: Vec::new(),
+            residual: Vec::new(),
+            pending_code: None,
+            scan: UniformScan::default(),
+            progress: None,
+        }
+    }
+
+    /// Calls `callback` with the progress so far after every `every`
+    /// values pushed, e.g. to export the compression ratio as a metric.
+    /// The block is unaffected. Replaces any callback set before.
+    ///
+    /// Panics if `every` is 0.
+    pub fn on_progress(&mut self, every: usize, callback: impl FnMut(CompressorProgress) + Send + 'static) {
+        assert!(every > 0, "progress interval must be positive");
+        self.progress = Some((every, Box::new(callback)));
+    }
+
+    pub fn push(&mut self, value: f64) {
+        self.pending_code = encode_bits_pending(
+            &mut self.predictors,
+            core::iter::once(value.to_bits()),
+            (&mut self.encoding, &mut self.residual),
+            self.pending_code,
+        );
+        self.num_values += 1;
+        self.scan.push(value.to_bits());
+        if let Some((every, callback)) = &mut self.progress {
+            if self.num_values.is_multiple_of(*every) {
+                callback(CompressorProgress {
+                    values_seen: self.num_values,
+                    encoding_bytes: self.encoding.len(),
+                    residual_bytes: self.residual.len(),
+                });
+            }
+        }
+    }
+
+    /// Number of values pushed so far.
+    pub fn num_values(&self) -> usize {
+        self.num_values
+    }
+
+    pub fn finish(mut self) -> FPCCompressedBlock {
+        if let Some(bits) = self.scan.bits() {
+            return FPCCompressedBlock::constant(self.table_size, self.num_values, bits);
+        }
+        if let Some(last) = self.pending_code {
+            self.encoding.push(last << 4);
+        }
+        FPCCompressedBlock {
+            table_size: self.table_size,
+            num_bytes_encoded: self.num_values,
+            mode: EncodingMode::Full,
+            hash_params: HashParams::DEFAULT,
+            dropped_mantissa_bits: 0,
+            delta_of_delta: false,
+            seed: 0,
+            canonicalize_nan: false,
+            encoding: self.encoding,
+            residual: self.residual,
+        }
+    }
+}
+
+/// Cuts a stream of values into blocks of `block_len` values each, every
+/// one compressed from fresh predictors so that it decodes on its own.
+pub struct BlockBuilder {
+    block_len: usize,
+    compressor: Compressor,
+}
+
+impl BlockBuilder {
+    /// Fails with [`FpcError::InvalidBlockLen`] if `block_len` is 0, and
+    /// like [`compress`](crate::compress) on an invalid table size.
+    pub fn new(table_size: u64, block_len: usize) -> Result<BlockBuilder, CompressError> {
+        if block_len == 0 {
+            return Err(FpcError::InvalidBlockLen { given: block_len });
+        }
+        Ok(BlockBuilder { block_len, compressor: Compressor::new(table_size)? })
+    }
+
+    /// Adds `value` to the current block, returning the block once it
+    /// holds `block_len` values.
+    pub fn push(&mut self, value: f64) -> Option<FPCCompressedBlock> {
+        self.compressor.push(value);
+        if self.compressor.num_values() < self.block_len {
+            return None;
+        }
+        let next = Compressor::with_valid_table_size(self.compressor.table_size);
+        Some(core::mem::replace(&mut self.compressor, next).finish())
+    }
+
+    /// Returns the last, partly filled block, or `None` if every value
+    /// pushed already went into a full one.
+    pub fn finish(self) -> Option<FPCCompressedBlock> {
+        (self.compressor.num_values() != 0).then(|| self.compressor.finish())
+    }
+}
+
+/// Predictor tables reused across calls to [`compress_with_context`], so
+/// compressing many small blocks does not allocate fresh tables for each.
+pub struct FpcContext {
+    predictors: Predictors,
+}
+
+impl FpcContext {
+    /// Fails like [`compress`](crate::compress) on an invalid table size.
+    pub fn new(table_size: u64) -> Result<FpcContext, CompressError> {
+        validate_table_size(table_size)?;
+        Ok(FpcContext { predictors: Predictors::new(table_size) })
+    }
+
+    /// Zeroes the tables in place, as for a fresh context.
+    pub fn reset(&mut self) {
+        let table_size = self.predictors.fcm.len() as u64;
+        self.predictors.reset(table_size);
+    }
+}
+
+/// Like [`compress_into`](crate::compress_into), but uses the tables of
+/// `ctx` instead of allocating new ones. The tables are reset first, so
+/// the output does not depend on what `ctx` compressed before. A
+/// `table_size` different from the last one resizes the tables.
+pub fn compress_with_context(
+    ctx: &mut FpcContext,
+    table_size: u64,
+    values: &[f64],
+    encoding: &mut Vec<u8>,
+    residual: &mut Vec<u8>,
+) -> Result<(), CompressError> {
+    validate_table_size(table_size)?;
+    ctx.predictors.reset(table_size);
+    encode_bits(&mut ctx.predictors, values.iter().map(|v| v.to_bits()), (encoding, residual), None);
+    Ok(())
+}
+
+/// Predictor state carried from one block to the next, so that a series
+/// split into many blocks compresses as well as if it were one. Each call
+/// to [`compress_continue`] or [`decompress_continue`] resumes from the
+/// state and leaves it after its last value.
+#[derive(Clone)]
+pub struct FpcState {
+    predictors: Predictors,
+}
+
+impl FpcState {
+    /// State before the first value of a series. Fails like
+    /// [`compress`](crate::compress) on an invalid table size.
+    pub fn new(table_size: u64) -> Result<FpcState, CompressError> {
+        validate_table_size(table_size)?;
+        Ok(FpcState { predictors: Predictors::new(table_size) })
+    }
+
+    pub fn table_size(&self) -> u64 {
+        self.predictors.fcm.len() as u64
+    }
+
+    fn check_table_size(&self, table_size: u64) -> Result<(), FpcError> {
+        if table_size != self.table_size() {
+            return Err(FpcError::TableSizeMismatch { block: self.table_size(), given: table_size });
+        }
+        Ok(())
+    }
+}
+
+/// Compresses `new_values` as the continuation of whatever `state` has
+/// seen so far. Starting from a fresh [`FpcState`] this produces the same
+/// block as [`compress`](crate::compress), but later blocks can only be
+/// decoded by [`decompress_continue`] with the state reached after all
+/// blocks before them. Fails with [`FpcError::TableSizeMismatch`] unless
+/// `table_size` is the state's.
+pub fn compress_continue(
+    state: &mut FpcState,
+    table_size: u64,
+    new_values: &[f64],
+) -> Result<FPCCompressedBlock, CompressError> {
+    state.check_table_size(table_size)?;
+    if let Some(bits) = uniform_bits(new_values.iter().map(|v| v.to_bits())) {
+        for _ in new_values {
+            state.predictors.update(bits);
+        }
+        return Ok(FPCCompressedBlock::constant(table_size, new_values.len(), bits));
+    }
+    let mut encoding = Vec::with_capacity(new_values.len().div_ceil(2));
+    let mut residual = Vec::with_capacity(size_of_val(new_values) / 4);
+    let bits = new_values.iter().map(|v| v.to_bits());
+    encode_bits(&mut state.predictors, bits, (&mut encoding, &mut residual), None);
+    Ok(FPCCompressedBlock {
+        table_size,
+        num_bytes_encoded: new_values.len(),
+        mode: EncodingMode::Full,
+        hash_params: HashParams::DEFAULT,
+        dropped_mantissa_bits: 0,
+        delta_of_delta: false,
+        seed: 0,
+        canonicalize_nan: false,
+        encoding,
+        residual,
+    })
+}
+
+/// Decodes a block written by [`compress_continue`], given the state the
+/// compressor was in when it started the block, and advances `state` past
+/// it. Fails like [`try_decompress`](crate::try_decompress) on a
+/// malformed block or a mismatched table size, leaving `state` as it was.
+pub fn decompress_continue(
+    state: &mut FpcState,
+    table_size: u64,
+    blk: &FPCCompressedBlock,
+) -> Result<Vec<f64>, DecompressError> {
+    state.check_table_size(table_size)?;
+    blk.check_table_size(table_size)?;
+    blk.check_encoding_len()?;
+    if let Some(bits) = blk.constant_bits() {
+        for _ in 0..blk.num_bytes_encoded {
+            state.predictors.update(bits);
+        }
+        return Ok(vec![f64::from_bits(bits); blk.num_bytes_encoded]);
+    }
+    let mut predictors = state.predictors.clone();
+    let mut residual_index = 0;
+    let mut values = Vec::with_capacity(blk.num_bytes_encoded);
+    for (index, code) in blk.codes().enumerate() {
+        let decoded = decode_value(&mut predictors, code, &blk.residual, &mut residual_index)
+            .ok_or(FpcError::TruncatedResidual { at_value: index, residual_len: blk.residual.len() })?;
+        values.push(f64::from_bits(decoded));
+    }
+    state.predictors = predictors;
+    Ok(values)
+}
+
+#[cfg(test)]
+mod compressor_test {
+    use quickcheck_macros::quickcheck;
+
+    use crate::{bitwise_eq, compress, DEFAULT_TABLE_SIZE};
+
+    use super::*;
+
+    #[quickcheck]
+    fn pushing_values_must_match_one_shot_compression(to_compress: Vec<f64>) -> bool {
+        let mut compressor = Compressor::new(DEFAULT_TABLE_SIZE).unwrap();
+        for &value in &to_compress {
+            compressor.push(value);
+        }
+        compressor.num_values() == to_compress.len()
+            && compressor.finish() == compress(DEFAULT_TABLE_SIZE, &to_compress).unwrap()
+    }
+
+    #[quickcheck]
+    fn reused_context_must_match_fresh_compression(blocks: Vec<Vec<f64>>) -> bool {
+        let mut ctx = FpcContext::new(DEFAULT_TABLE_SIZE).unwrap();
+        blocks.iter().enumerate().all(|(i, values)| {
+            // Alternate between table sizes to exercise resizing as well.
+            let table_size = if i % 2 == 0 { DEFAULT_TABLE_SIZE } else { 8 };
+            let (mut encoding, mut residual) = (Vec::new(), Vec::new());
+            compress_with_context(&mut ctx, table_size, values, &mut encoding, &mut residual).unwrap();
+            let (_, expected_encoding, expected_residual) = compress(table_size, values).unwrap().into_parts();
+            encoding == expected_encoding && residual == expected_residual
+        })
+    }
+
+    #[test]
+    fn test_with_capacity_reserves_like_compress() {
+        let mut compressor = Compressor::with_capacity(DEFAULT_TABLE_SIZE, 1001).unwrap();
+        assert!(compressor.encoding.capacity() >= 501);
+        assert!(compressor.residual.capacity() >= 2002);
+        let series: Vec<f64> = (0..1001).map(|x| x as f64 * 0.5).collect();
+        let (encoding_ptr, residual_ptr) = (compressor.encoding.as_ptr(), compressor.residual.as_ptr());
+        for &value in &series {
+            compressor.push(value);
+        }
+        assert_eq!((compressor.encoding.as_ptr(), compressor.residual.as_ptr()), (encoding_ptr, residual_ptr));
+        compressor.push(1.0);
+        compressor.reserve(3);
+        assert!(compressor.encoding.capacity() >= 502);
+        assert!(compressor.residual.capacity() >= compressor.residual.len() + 6);
+        assert!(matches!(Compressor::with_capacity(6, 10), Err(FpcError::InvalidTableSize { given: 6 })));
+        // Counts no allocator can serve are ignored rather than fatal.
+        compressor.reserve(usize::MAX);
+        compressor.push(2.0);
+        let pushed = [series.as_slice(), &[1.0, 2.0]].concat();
+        assert_eq!(compressor.finish(), compress(DEFAULT_TABLE_SIZE, &pushed).unwrap());
+    }
+
+    #[test]
+    fn test_progress_is_reported_every_interval() {
+        let reports = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut compressor = Compressor::new(DEFAULT_TABLE_SIZE).unwrap();
+        let sink = reports.clone();
+        compressor.on_progress(100, move |progress| sink.lock().unwrap().push(progress));
+        let series: Vec<f64> = (0..1050).map(|x| (x as f64 * 0.01).sin()).collect();
+        for (index, &value) in series.iter().enumerate() {
+            compressor.push(value);
+            if index == 299 {
+                let (encoding_bytes, residual_bytes) = (compressor.encoding.len(), compressor.residual.len());
+                let expected = CompressorProgress { values_seen: 300, encoding_bytes, residual_bytes };
+                assert_eq!(reports.lock().unwrap().last(), Some(&expected));
+            }
+        }
+        assert_eq!(reports.lock().unwrap().len(), 10);
+        assert_eq!(compressor.finish(), compress(DEFAULT_TABLE_SIZE, &series).unwrap());
+    }
+
+    #[test]
+    fn test_reset_zeroes_tables_in_place() {
+        let mut ctx = FpcContext::new(DEFAULT_TABLE_SIZE).unwrap();
+        let (mut encoding, mut residual) = (Vec::new(), Vec::new());
+        compress_with_context(&mut ctx, DEFAULT_TABLE_SIZE, &[1.0, 2.0, 3.0], &mut encoding, &mut residual).unwrap();
+        let tables = ctx.predictors.fcm.as_ptr();
+        ctx.reset();
+        assert_eq!(ctx.predictors.fcm.as_ptr(), tables);
+        assert!(ctx.predictors.fcm.iter().chain(&ctx.predictors.dfcm).all(|&v| v == 0));
+    }
+
+    #[quickcheck]
+    fn continued_blocks_must_round_trip(batches: Vec<Vec<f64>>) -> bool {
+        let mut state = FpcState::new(DEFAULT_TABLE_SIZE).unwrap();
+        let blocks: Vec<FPCCompressedBlock> =
+            batches.iter().map(|batch| compress_continue(&mut state, DEFAULT_TABLE_SIZE, batch).unwrap()).collect();
+        let mut state = FpcState::new(DEFAULT_TABLE_SIZE).unwrap();
+        batches.iter().zip(&blocks).all(|(batch, blk)| {
+            decompress_continue(&mut state, DEFAULT_TABLE_SIZE, blk).is_ok_and(|values| bitwise_eq(&values, batch))
+        })
+    }
+
+    #[test]
+    fn test_continuing_beats_fresh_blocks_at_the_boundary() {
+        let series: Vec<f64> = (0..64).map(|x| ((x % 8) as f64 * 0.25).exp()).collect();
+        let mut state = FpcState::new(DEFAULT_TABLE_SIZE).unwrap();
+        let first = compress_continue(&mut state, DEFAULT_TABLE_SIZE, &series[..32]).unwrap();
+        assert_eq!(first, compress(DEFAULT_TABLE_SIZE, &series[..32]).unwrap());
+        let continued = compress_continue(&mut state, DEFAULT_TABLE_SIZE, &series[32..]).unwrap();
+        let fresh = compress(DEFAULT_TABLE_SIZE, &series[32..]).unwrap();
+        assert!(continued.compressed_len() < fresh.compressed_len());
+        assert_eq!(
+            compress_continue(&mut state, 64, &series),
+            Err(FpcError::TableSizeMismatch { block: DEFAULT_TABLE_SIZE, given: 64 })
+        );
+    }
+
+    #[test]
+    fn test_decompress_continue_leaves_state_on_error() {
+        let mut state = FpcState::new(DEFAULT_TABLE_SIZE).unwrap();
+        let blk = compress_continue(&mut state, DEFAULT_TABLE_SIZE, &[1.0, 2.5, 3.0]).unwrap();
+        let mut truncated = compress_continue(&mut state.clone(), DEFAULT_TABLE_SIZE, &[4.5, 6.0]).unwrap();
+        truncated.residual.truncate(1);
+        let mut state = FpcState::new(DEFAULT_TABLE_SIZE).unwrap();
+        assert_eq!(decompress_continue(&mut state, DEFAULT_TABLE_SIZE, &blk), Ok(vec![1.0, 2.5, 3.0]));
+        let saved = state.clone();
+        assert!(decompress_continue(&mut state, DEFAULT_TABLE_SIZE, &truncated).is_err());
+        assert_eq!(state.predictors.fcm, saved.predictors.fcm);
+        assert_eq!(state.predictors.last_value, saved.predictors.last_value);
+    }
+
+    #[quickcheck]
+    fn built_blocks_must_match_compressed_chunks(to_compress: Vec<f64>, block_len: usize) -> bool {
+        let block_len = 1 + block_len % 16;
+        let mut builder = BlockBuilder::new(DEFAULT_TABLE_SIZE, block_len).unwrap();
+        let mut blocks: Vec<FPCCompressedBlock> = to_compress.iter().filter_map(|&value| builder.push(value)).collect();
+        blocks.extend(builder.finish());
+        let expected: Vec<FPCCompressedBlock> =
+            to_compress.chunks(block_len).map(|chunk| compress(DEFAULT_TABLE_SIZE, chunk).unwrap()).collect();
+        blocks == expected
+    }
+
+    #[test]
+    fn test_block_builder_rejects_empty_blocks() {
+        assert!(matches!(BlockBuilder::new(DEFAULT_TABLE_SIZE, 0), Err(FpcError::InvalidBlockLen { given: 0 })));
+        assert!(matches!(BlockBuilder::new(6, 8), Err(FpcError::InvalidTableSize { given: 6 })));
+        let mut builder = BlockBuilder::new(DEFAULT_TABLE_SIZE, 2).unwrap();
+        assert_eq!(builder.push(1.0), None);
+        assert_eq!(builder.push(2.0).map(|blk| blk.num_values()), Some(2));
+        assert_eq!(builder.finish(), None);
+    }
+
+    #[test]
+    fn test_compressor_rejects_invalid_table_size() {
+        assert!(matches!(Compressor::new(6), Err(FpcError::InvalidTableSize { given: 6 })));
+    }
+}
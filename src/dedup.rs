@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+use crate::stream::{read_block, read_up_to};
+use crate::{decompress_stored, FPCCompressedBlock, FpcError};
+
+const REFERENCE_MARKER: [u8; 4] = *b"FPCD";
+// marker and the index of the referenced block
+const REFERENCE_LEN: usize = 4 + 8;
+
+/// 64-bit FNV-1a hash of `bytes`.
+pub fn content_hash(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+/// Writes serialized blocks to an inner writer, replacing every block
+/// that is byte-identical to an earlier one with a 12-byte reference to
+/// it. Read the result back with [`decompress_dedup`].
+///
+/// The serialized bytes of every distinct block are kept in memory so that
+/// hash collisions never produce a wrong reference.
+pub struct FpcDedupWriter<W: Write> {
+    inner: W,
+    blocks_written: usize,
+    seen: HashMap<u64, Vec<(usize, Vec<u8>)>>,
+}
+
+impl<W: Write> FpcDedupWriter<W> {
+    pub fn new(inner: W) -> FpcDedupWriter<W> {
+        FpcDedupWriter { inner, blocks_written: 0, seen: HashMap::new() }
+    }
+
+    /// Writes `blk`, or a reference to an identical block written earlier.
+    pub fn write_block(&mut self, blk: &FPCCompressedBlock) -> Result<(), FpcError> {
+        let bytes = blk.to_bytes();
+        let candidates = self.seen.entry(content_hash(&bytes)).or_default();
+        match candidates.iter().find(|(_, prior)| *prior == bytes) {
+            Some(&(index, _)) => {
+                self.inner.write_all(&REFERENCE_MARKER)?;
+                self.inner.write_all(&(index as u64).to_le_bytes())?;
+            }
+            None => {
+                self.inner.write_all(&bytes)?;
+                candidates.push((self.blocks_written, bytes));
+            }
+        }
+        self.blocks_written += 1;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+/// Decompresses the output of [`FpcDedupWriter`], yielding the values of
+/// one block at a time with references resolved. The iterator ends at the
+/// first error.
+pub fn decompress_dedup<R: Read>(r: &mut R) -> impl Iterator<Item = Result<Vec<f64>, FpcError>> + '_ {
+    // For every block read so far, where its values are in `distinct`.
+    let mut occurrences: Vec<usize> = vec![];
+    let mut distinct: Vec<Vec<f64>> = vec![];
+    let mut failed = false;
+    std::iter::from_fn(move || {
+        if failed {
+            return None;
+        }
+        match read_entry(r, &occurrences) {
+            Ok(None) => None,
+            Ok(Some(Entry::Reference(index))) => {
+                let values = distinct[occurrences[index]].clone();
+                occurrences.push(occurrences[index]);
+                Some(Ok(values))
+            }
+            Ok(Some(Entry::Block(values))) => {
+                occurrences.push(distinct.len());
+                distinct.push(values.clone());
+                Some(Ok(values))
+            }
+            Err(e) => {
+                failed = true;
+                Some(Err(e))
+            }
+        }
+    })
+}
+
+enum Entry {
+    Reference(usize),
+    Block(Vec<f64>),
+}
+
+fn read_entry<R: Read>(r: &mut R, occurrences: &[usize]) -> Result<Option<Entry>, FpcError> {
+    let mut marker = [0_u8; 4];
+    let marker_read = read_up_to(r, &mut marker)?;
+    if marker_read == 0 {
+        return Ok(None);
+    }
+    if marker_read == marker.len() && marker == REFERENCE_MARKER {
+        let mut index = [0_u8; REFERENCE_LEN - REFERENCE_MARKER.len()];
+        let index_read = read_up_to(r, &mut index)?;
+        if index_read < index.len() {
+            return Err(FpcError::TruncatedHeader { len: marker.len() + index_read });
+        }
+        let index = u64::from_le_bytes(index);
+        if index >= occurrences.len() as u64 {
+            return Err(FpcError::DanglingReference { index, blocks_read: occurrences.len() });
+        }
+        return Ok(Some(Entry::Reference(index as usize)));
+    }
+    // The marker bytes start the block, so it is never missing outright.
+    let blk = read_block(&mut marker[..marker_read].chain(r))?.ok_or(FpcError::TruncatedHeader { len: marker_read })?;
+    Ok(Some(Entry::Block(decompress_stored(&blk)?)))
+}
+
+#[cfg(test)]
+mod dedup_test {
+    use crate::compress;
+
+    use super::*;
+
+    #[test]
+    fn test_content_hash_matches_reference_vectors() {
+        assert_eq!(content_hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(content_hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn test_duplicate_block_is_stored_once() {
+        let blk = compress(32, &vec![7.0; 100]).unwrap();
+        let mut writer = FpcDedupWriter::new(vec![]);
+        writer.write_block(&blk).unwrap();
+        writer.write_block(&blk).unwrap();
+        let bytes = writer.into_inner();
+        assert_eq!(bytes.len(), blk.to_bytes().len() + REFERENCE_LEN);
+
+        let decoded: Vec<Vec<f64>> = decompress_dedup(&mut bytes.as_slice()).collect::<Result<_, _>>().unwrap();
+        assert_eq!(decoded, vec![vec![7.0; 100], vec![7.0; 100]]);
+    }
+
+    #[test]
+    fn test_references_resolve_across_distinct_blocks() {
+        let a = compress(32, &[1.0, 2.0, 3.0]).unwrap();
+        let b = compress(32, &[4.0, 5.0]).unwrap();
+        let mut writer = FpcDedupWriter::new(vec![]);
+        for blk in [&a, &b, &b, &a, &b] {
+            writer.write_block(blk).unwrap();
+        }
+        let bytes = writer.into_inner();
+        assert_eq!(bytes.len(), a.to_bytes().len() + b.to_bytes().len() + 3 * REFERENCE_LEN);
+
+        let decoded: Vec<Vec<f64>> = decompress_dedup(&mut bytes.as_slice()).collect::<Result<_, _>>().unwrap();
+        let (a, b) = (vec![1.0, 2.0, 3.0], vec![4.0, 5.0]);
+        assert_eq!(decoded, vec![a.clone(), b.clone(), b.clone(), a, b]);
+    }
+
+    #[test]
+    fn test_dangling_reference_is_rejected() {
+        let mut bytes = compress(32, &[1.0]).unwrap().to_bytes();
+        bytes.extend_from_slice(&REFERENCE_MARKER);
+        bytes.extend_from_slice(&1_u64.to_le_bytes());
+        let mut reader = bytes.as_slice();
+        let mut stream = decompress_dedup(&mut reader);
+        assert_eq!(stream.next(), Some(Ok(vec![1.0])));
+        assert_eq!(stream.next(), Some(Err(FpcError::DanglingReference { index: 1, blocks_read: 1 })));
+        assert_eq!(stream.next(), None);
+    }
+
+    #[test]
+    fn test_malformed_blocks_end_the_stream_with_an_error() {
+        let blk = compress(32, &[1.0, 2.0]).unwrap().to_bytes();
+        for cut in [3, 10, blk.len() - 1] {
+            let bytes = [&blk[..], &blk[..cut]].concat();
+            let mut reader = bytes.as_slice();
+            let mut stream = decompress_dedup(&mut reader);
+            assert_eq!(stream.next(), Some(Ok(vec![1.0, 2.0])));
+            assert!(matches!(stream.next(), Some(Err(_))));
+            assert_eq!(stream.next(), None);
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use alloc::vec::Vec;
+
+use crate::{
+    encode_bits, validate_table_size, CompressError, DecompressError, EncodingMode, FPCCompressedBlock, FpcError,
+    HashParams, Predictors,
+};
+
+/// Predictor tables warmed up on a representative sample, for starting
+/// many small blocks of similar data from the same state instead of from
+/// zeros. This is the warm start of
+/// [`compress_skip_warmup`](crate::compress_skip_warmup), trained once
+/// and shared.
+///
+/// The dictionary carries an id chosen by the caller, which every block
+/// compressed with it records, so that decompressing checks it is handed
+/// the right one. Training is deterministic, so a dictionary can be
+/// rebuilt from its sample instead of being stored.
+#[derive(Clone)]
+pub struct FpcDictionary {
+    id: u32,
+    table_size: u64,
+    predictors: Predictors,
+}
+
+impl FpcDictionary {
+    /// Trains a dictionary of tables of `table_size` entries on `sample`.
+    /// Fails with [`FpcError::InvalidTableSize`] unless `table_size` is a
+    /// power of two.
+    pub fn train(id: u32, table_size: u64, sample: &[f64]) -> Result<FpcDictionary, CompressError> {
+        validate_table_size(table_size)?;
+        let mut predictors = Predictors::new(table_size);
+        for value in sample {
+            predictors.update(value.to_bits());
+        }
+        Ok(FpcDictionary { id, table_size, predictors })
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn table_size(&self) -> u64 {
+        self.table_size
+    }
+}
+
+/// A block compressed with an [`FpcDictionary`], as returned by
+/// [`compress_with_dictionary`], along with the id of that dictionary.
+/// The block inside only decodes with the dictionary's tables, so it is
+/// not handed out as an [`FPCCompressedBlock`], whose header has no room
+/// for the id.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DictionaryBlock {
+    dictionary_id: u32,
+    block: FPCCompressedBlock,
+}
+
+impl DictionaryBlock {
+    /// Id of the dictionary needed to decompress the block.
+    pub fn dictionary_id(&self) -> u32 {
+        self.dictionary_id
+    }
+
+    pub fn num_values(&self) -> usize {
+        self.block.num_values()
+    }
+
+    /// Number of bytes of the encoding and residual, like
+    /// [`FPCCompressedBlock::compressed_len`].
+    pub fn compressed_len(&self) -> usize {
+        self.block.compressed_len()
+    }
+}
+
+/// Compresses `values` with predictors starting from the state of
+/// `dictionary`, with its table size. Fails with
+/// [`FpcError::InvalidTableSize`] unless that is a power of two, which
+/// [`FpcDictionary::train`] already ensures.
+pub fn compress_with_dictionary(dictionary: &FpcDictionary, values: &[f64]) -> Result<DictionaryBlock, CompressError> {
+    validate_table_size(dictionary.table_size)?;
+    let mut predictors = dictionary.predictors.clone();
+    let mut encoding = Vec::with_capacity(values.len().div_ceil(2));
+    let mut residual = Vec::with_capacity(size_of_val(values) / 4);
+    encode_bits(&mut predictors, values.iter().map(|v| v.to_bits()), (&mut encoding, &mut residual), None);
+    let block = FPCCompressedBlock {
+        table_size: dictionary.table_size,
+        num_bytes_encoded: values.len(),
+        mode: EncodingMode::Full,
+        hash_params: HashParams::DEFAULT,
+        dropped_mantissa_bits: 0,
+        delta_of_delta: false,
+        seed: 0,
+        canonicalize_nan: false,
+        encoding,
+        residual,
+    };
+    Ok(DictionaryBlock { dictionary_id: dictionary.id, block })
+}
+
+/// Decompresses a block written by [`compress_with_dictionary`]. Fails
+/// with [`FpcError::DictionaryMismatch`] unless `dictionary` has the id
+/// the block records, with [`FpcError::TableSizeMismatch`] unless it has
+/// the block's table size, and like [`try_decompress`](crate::try_decompress)
+/// on a malformed block.
+pub fn decompress_with_dictionary(
+    dictionary: &FpcDictionary,
+    blk: &DictionaryBlock,
+) -> Result<Vec<f64>, DecompressError> {
+    if blk.dictionary_id != dictionary.id {
+        return Err(FpcError::DictionaryMismatch { expected: blk.dictionary_id, given: dictionary.id });
+    }
+    blk.block.check_table_size(dictionary.table_size)?;
+    blk.block.check_encoding_len()?;
+    blk.block.iter_from(dictionary.predictors.clone()).collect()
+}
+
+#[cfg(test)]
+mod dictionary_test {
+    use quickcheck_macros::quickcheck;
+
+    use crate::{bitwise_eq, compress, DEFAULT_TABLE_SIZE};
+
+    use super::*;
+
+    fn column(offset: usize, len: usize) -> Vec<f64> {
+        (offset..offset + len).map(|x| ((x % 32) as f64 * 0.125).exp()).collect()
+    }
+
+    #[quickcheck]
+    fn dictionary_blocks_must_round_trip(sample: Vec<f64>, values: Vec<f64>) -> bool {
+        let dictionary = FpcDictionary::train(7, DEFAULT_TABLE_SIZE, &sample).unwrap();
+        let blk = compress_with_dictionary(&dictionary, &values).unwrap();
+        blk.dictionary_id() == 7 && bitwise_eq(&decompress_with_dictionary(&dictionary, &blk).unwrap(), &values)
+    }
+
+    #[test]
+    fn test_dictionaries_shrink_small_blocks() {
+        let dictionary = FpcDictionary::train(1, DEFAULT_TABLE_SIZE, &column(0, 256)).unwrap();
+        let (mut cold, mut warm) = (0, 0);
+        for offset in (1000..2000).step_by(40) {
+            let values = column(offset, 40);
+            cold += compress(DEFAULT_TABLE_SIZE, &values).unwrap().compressed_len();
+            warm += compress_with_dictionary(&dictionary, &values).unwrap().compressed_len();
+        }
+        assert!(warm < cold, "warm {warm}, cold {cold}");
+        let empty = FpcDictionary::train(2, DEFAULT_TABLE_SIZE, &[]).unwrap();
+        let values = column(0, 40);
+        let blk = compress_with_dictionary(&empty, &values).unwrap();
+        assert_eq!(blk.block, compress(DEFAULT_TABLE_SIZE, &values).unwrap());
+    }
+
+    #[test]
+    fn test_dictionary_ids_must_match() {
+        assert!(matches!(FpcDictionary::train(1, 3, &[1.0]), Err(FpcError::InvalidTableSize { given: 3 })));
+        let dictionary = FpcDictionary::train(1, DEFAULT_TABLE_SIZE, &column(0, 64)).unwrap();
+        let other = FpcDictionary::train(2, DEFAULT_TABLE_SIZE, &column(0, 64)).unwrap();
+        let blk = compress_with_dictionary(&dictionary, &column(64, 10)).unwrap();
+        assert_eq!(
+            decompress_with_dictionary(&other, &blk),
+            Err(FpcError::DictionaryMismatch { expected: 1, given: 2 })
+        );
+        let resized = FpcDictionary::train(1, 64, &column(0, 64)).unwrap();
+        assert_eq!(
+            decompress_with_dictionary(&resized, &blk),
+            Err(FpcError::TableSizeMismatch { block: DEFAULT_TABLE_SIZE, given: 64 })
+        );
+    }
+}
@@ -0,0 +1,414 @@
+//! The ways of storing the per-value codes in the encoding stream.
+//!
+//! [`EncodingMode::Full`] spends 4 bits per value and can express every
+//! leading zero byte count. [`EncodingMode::Compact`] spends 3, so the
+//! encoding shrinks by a quarter, but stores at most 3 leading zero bytes:
+//! a value with more keeps the extra zero bytes in its residual. Compact
+//! wins whenever those extra bytes add up to less than one byte per 8
+//! values, which is typical of noisy series where predictions are rarely
+//! close. Series with many exact or near exact predictions, such as
+//! constants and slow ramps, are better off with the full codes.
+//!
+//! [`EncodingMode::Constant`] stores no codes at all, for blocks whose
+//! values are all the same; [`compress`] picks it by itself for up to
+//! [`MAX_CONSTANT_VALUES`] values.
+
+#[cfg(test)]
+use alloc::borrow::Cow;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::{compress, encode_block, residual_len_of_code, CompressError, FPCCompressedBlock, FpcOptions, HashParams};
+
+/// Most values a constant block may hold. A constant block decompresses
+/// to 8 bytes per value from a header alone, so readers reject a larger
+/// count rather than trust it with the allocation; longer runs of one
+/// value are stored with full codes, at half a byte per value.
+pub const MAX_CONSTANT_VALUES: usize = 1 << 24;
+
+/// How the code of every value is stored in the encoding stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EncodingMode {
+    /// 4 bits per value, two values per byte with the first in the high
+    /// nibble: the predictor bit and a 3-bit leading zero byte count.
+    #[default]
+    Full,
+    /// 3 bits per value, packed most significant bit first: the predictor
+    /// bit and a 2-bit leading zero byte count of at most 3.
+    Compact,
+    /// No codes: every value of the block is the same, and the residual
+    /// holds its 8 bytes, most significant first. Only blocks of at least
+    /// two and at most [`MAX_CONSTANT_VALUES`] values are stored this way.
+    Constant,
+}
+
+impl EncodingMode {
+    /// Number of encoding bytes a block of `num_values` values takes.
+    pub fn encoding_len(self, num_values: usize) -> usize {
+        match self {
+            EncodingMode::Full => num_values.div_ceil(2),
+            EncodingMode::Compact => (3 * num_values).div_ceil(8),
+            EncodingMode::Constant => 0,
+        }
+    }
+
+    /// The code of value `index` as a 4-bit code of [`EncodingMode::Full`],
+    /// or `None` if the encoding ends before it. A constant block has no
+    /// codes to read.
+    pub(crate) fn code_at(self, encoding: &[u8], index: usize) -> Option<u8> {
+        match self {
+            EncodingMode::Full => {
+                let byte = encoding.get(index >> 1)?;
+                Some(if index & 1 == 0 { byte >> 4 } else { byte & 0xf })
+            }
+            EncodingMode::Compact => {
+                let bit = 3 * index;
+                let window = (*encoding.get(bit / 8)? as u16) << 8 | *encoding.get(bit / 8 + 1).unwrap_or(&0) as u16;
+                let code = (window >> (13 - bit % 8)) as u8 & 0b111;
+                // The leading zero byte counts 0 to 3 mean the same in
+                // both modes.
+                Some((code & 0b100) << 1 | (code & 0b011))
+            }
+            EncodingMode::Constant => None,
+        }
+    }
+}
+
+/// Compresses `values` like [`compress`], then switches the block to
+/// [`EncodingMode::Compact`] if that makes it smaller. The residual bytes
+/// each mode needs follow from the codes alone, so deciding and switching
+/// take one pass over the block without compressing again.
+pub fn compress_compact(table_size: u64, values: &[f64]) -> Result<FPCCompressedBlock, CompressError> {
+    let blk = compress(table_size, values)?;
+    if blk.mode == EncodingMode::Constant {
+        return Ok(blk);
+    }
+    // Codes with a count field of 4 or more stand for 5 to 8 zero bytes,
+    // of which compact codes leave all but 3 in the residual.
+    let extra_residual: usize =
+        blk.codes().filter(|code| code & 0b111 >= 4).map(|code| 5 - residual_len_of_code(code)).sum();
+    let saved_encoding = blk.encoding.len() - EncodingMode::Compact.encoding_len(blk.num_bytes_encoded);
+    if extra_residual >= saved_encoding {
+        return Ok(blk);
+    }
+    let mut codes = Vec::with_capacity(blk.num_bytes_encoded);
+    let mut residual = Vec::with_capacity(blk.residual.len() + extra_residual);
+    let mut residual_index = 0;
+    for code in blk.codes() {
+        let len = residual_len_of_code(code);
+        let compact_code = if code & 0b111 >= 4 {
+            residual.resize(residual.len() + 5 - len, 0);
+            (code & 0b1000) >> 1 | 0b011
+        } else {
+            (code & 0b1000) >> 1 | (code & 0b011)
+        };
+        residual.extend_from_slice(&blk.residual[residual_index..residual_index + len]);
+        residual_index += len;
+        codes.push(compact_code);
+    }
+    Ok(FPCCompressedBlock {
+        table_size: blk.table_size,
+        num_bytes_encoded: blk.num_bytes_encoded,
+        mode: EncodingMode::Compact,
+        hash_params: blk.hash_params,
+        dropped_mantissa_bits: blk.dropped_mantissa_bits,
+        delta_of_delta: blk.delta_of_delta,
+        seed: blk.seed,
+        canonicalize_nan: blk.canonicalize_nan,
+        encoding: pack_compact_codes(&codes),
+        residual,
+    })
+}
+
+/// Packs 3-bit codes most significant bit first.
+pub(crate) fn pack_compact_codes(codes: &[u8]) -> Vec<u8> {
+    let mut encoding = Vec::with_capacity(EncodingMode::Compact.encoding_len(codes.len()));
+    let mut pending = 0_u16;
+    let mut pending_bits = 0;
+    for &code in codes {
+        pending = pending << 3 | code as u16;
+        pending_bits += 3;
+        if pending_bits >= 8 {
+            pending_bits -= 8;
+            encoding.push((pending >> pending_bits) as u8);
+        }
+    }
+    if pending_bits > 0 {
+        encoding.push((pending << (8 - pending_bits)) as u8);
+    }
+    encoding
+}
+
+impl FPCCompressedBlock {
+    pub fn mode(&self) -> EncodingMode {
+        self.mode
+    }
+
+    /// Rewrites a compact or constant block with full codes. The residual
+    /// of a compact block stays as it is, since compact codes only use
+    /// counts that full codes share; a constant block is expanded like
+    /// [`with_full_codes`](Self::with_full_codes).
+    pub(crate) fn switch_to_full_mode(&mut self) {
+        match self.mode {
+            EncodingMode::Full => return,
+            EncodingMode::Compact => {}
+            EncodingMode::Constant => {
+                *self = self.expand_constant();
+                return;
+            }
+        }
+        let codes: Vec<u8> = self.codes().collect();
+        self.encoding = codes.chunks(2).map(|pair| pair[0] << 4 | pair.get(1).copied().unwrap_or(0)).collect();
+        self.mode = EncodingMode::Full;
+    }
+
+    /// A block of `num_values` copies of the value with bits `bits`, with
+    /// the default settings like [`from_parts`](Self::from_parts).
+    pub(crate) fn constant(table_size: u64, num_values: usize, bits: u64) -> Self {
+        FPCCompressedBlock {
+            table_size,
+            num_bytes_encoded: num_values,
+            mode: EncodingMode::Constant,
+            hash_params: HashParams::DEFAULT,
+            dropped_mantissa_bits: 0,
+            delta_of_delta: false,
+            seed: 0,
+            canonicalize_nan: false,
+            encoding: Vec::new(),
+            residual: bits.to_be_bytes().to_vec(),
+        }
+    }
+
+    /// The block itself, or for a constant block the same values with full
+    /// codes, for reading the code of every value.
+    #[cfg(test)]
+    pub(crate) fn with_full_codes(&self) -> Cow<'_, Self> {
+        match self.mode {
+            EncodingMode::Constant => Cow::Owned(self.expand_constant()),
+            _ => Cow::Borrowed(self),
+        }
+    }
+
+    /// The bits of the value of a constant block.
+    pub(crate) fn constant_bits(&self) -> Option<u64> {
+        match self.mode {
+            EncodingMode::Constant => self.residual.as_slice().try_into().ok().map(u64::from_be_bytes),
+            _ => None,
+        }
+    }
+
+    /// The code of every value like [`codes`](Self::codes), but also for
+    /// a constant block, which yields the codes its values would have had
+    /// with full codes. Only the warm-up of a constant block is compressed
+    /// to read them.
+    pub(crate) fn full_codes(&self) -> impl Iterator<Item = u8> + '_ {
+        let (warm_up, settled) = match self.mode {
+            EncodingMode::Constant => {
+                let warm_up = self.constant_warm_up_block();
+                let settled = self.num_bytes_encoded - warm_up.num_bytes_encoded;
+                (warm_up.codes().collect(), settled)
+            }
+            _ => (Vec::new(), 0),
+        };
+        self.codes().chain(warm_up).chain(core::iter::repeat_n(SETTLED_CODE, settled))
+    }
+
+    /// Compresses the values of a constant block again with full codes
+    /// and the block's settings. Only the values before both predictors
+    /// settle on the repeated value need compressing, see
+    /// [`constant_warm_up`].
+    fn expand_constant(&self) -> Self {
+        let mut blk = FPCCompressedBlock { num_bytes_encoded: self.num_bytes_encoded, ..self.constant_warm_up_block() };
+        let settled = self.num_bytes_encoded - constant_warm_up(self.table_size).min(self.num_bytes_encoded);
+        blk.encoding.resize(blk.encoding.len() + settled / 2, SETTLED_CODE << 4 | SETTLED_CODE);
+        if settled % 2 == 1 {
+            blk.encoding.push(SETTLED_CODE << 4);
+        }
+        blk
+    }
+
+    /// The first values of a constant block, up to its warm-up, compressed
+    /// with full codes and the block's settings.
+    fn constant_warm_up_block(&self) -> Self {
+        let warm_up = self.num_bytes_encoded.min(constant_warm_up(self.table_size));
+        let values = match self.constant_bits() {
+            Some(bits) => vec![f64::from_bits(bits); warm_up],
+            None => panic!("constant block without an 8-byte value"),
+        };
+        let options = FpcOptions {
+            hash_params: self.hash_params,
+            delta_of_delta: self.delta_of_delta,
+            canonicalize_nan: self.canonicalize_nan,
+            ..FpcOptions::default()
+        };
+        FPCCompressedBlock {
+            dropped_mantissa_bits: self.dropped_mantissa_bits,
+            ..encode_block(self.table_size, &values, &options, self.seed)
+        }
+    }
+}
+
+/// The code of a value both predictors predict exactly: DFCM, which wins
+/// ties, with 8 leading zero bytes and so no residual.
+const SETTLED_CODE: u8 = 0b0111;
+
+/// Number of values of a constant block after which every value takes
+/// [`SETTLED_CODE`], whatever the hash parameters and the seed. The
+/// values fed to the predictors repeat from the third on, once the
+/// delta-of-delta transform has seen two, and the hashes of a table of
+/// `2^m` entries forget a value after `m` shifts, or alternate between
+/// two entries with a shift of 0. Both of the entries a hash then visits
+/// were last written with the repeated value or its zero delta. Kept even
+/// so the settled codes start on an encoding byte.
+fn constant_warm_up(table_size: u64) -> usize {
+    2 * (table_size.trailing_zeros() as usize + 4)
+}
+
+/// The bits shared by all of `bits`, if there are at least two and they
+/// are all the same, i.e. if they make a constant block.
+pub(crate) fn uniform_bits(bits: impl IntoIterator<Item = u64>) -> Option<u64> {
+    let mut scan = UniformScan::default();
+    bits.into_iter().try_for_each(|bits| scan.push(bits).then_some(()))?;
+    scan.bits()
+}
+
+/// Tracks whether the values seen so far make a constant block, for
+/// compressing values that arrive one at a time.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct UniformScan {
+    first: Option<u64>,
+    num_values: usize,
+    mixed: bool,
+}
+
+impl UniformScan {
+    /// Records one more value, returning whether all values so far are
+    /// still the same.
+    pub(crate) fn push(&mut self, bits: u64) -> bool {
+        match self.first {
+            None => self.first = Some(bits),
+            Some(first) => self.mixed |= first != bits,
+        }
+        self.num_values += 1;
+        !self.mixed
+    }
+
+    pub(crate) fn bits(&self) -> Option<u64> {
+        self.first.filter(|_| !self.mixed && (2..=MAX_CONSTANT_VALUES).contains(&self.num_values))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod encoding_mode_test {
+    use quickcheck_macros::quickcheck;
+
+    use crate::{bitwise_eq, decompress, try_decompress, DEFAULT_TABLE_SIZE};
+
+    use super::*;
+
+    /// Values in [2, 4) with well mixed mantissas, which neither predictor
+    /// gets close to.
+    pub(crate) fn noisy(n: u64) -> Vec<f64> {
+        (0..n)
+            .map(|x| {
+                let mixed = x.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+                let mixed = (mixed ^ mixed >> 31).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+                f64::from_bits(0x4000_0000_0000_0000 | mixed >> 12)
+            })
+            .collect()
+    }
+
+    #[quickcheck]
+    fn compact_blocks_must_round_trip(to_compress: Vec<f64>) -> bool {
+        let blk = compress_compact(DEFAULT_TABLE_SIZE, &to_compress).unwrap();
+        let full = compress(DEFAULT_TABLE_SIZE, &to_compress).unwrap();
+        blk.compressed_len() <= full.compressed_len()
+            && bitwise_eq(&decompress(DEFAULT_TABLE_SIZE, &blk), &to_compress)
+            && try_decompress(DEFAULT_TABLE_SIZE, &blk).is_ok_and(|values| bitwise_eq(&values, &to_compress))
+    }
+
+    #[quickcheck]
+    fn expanding_a_constant_must_match_compressing_every_value(
+        bits: u64,
+        len: u8,
+        log_table_size: u8,
+        shifts: (u8, u8, u8, u8),
+        delta_of_delta: bool,
+        seed: u64,
+    ) -> bool {
+        let table_size = 1 << (log_table_size % 12);
+        let (fcm_shift, fcm_fold, dfcm_shift, dfcm_fold) = shifts;
+        let options = FpcOptions {
+            hash_params: HashParams {
+                fcm_shift: fcm_shift % 64,
+                fcm_fold: fcm_fold % 64,
+                dfcm_shift: dfcm_shift % 64,
+                dfcm_fold: dfcm_fold % 64,
+            },
+            delta_of_delta,
+            ..FpcOptions::default()
+        };
+        let values = vec![f64::from_bits(bits); 2 + len as usize];
+        let expected = encode_block(table_size, &values, &options, seed);
+        let constant = FPCCompressedBlock {
+            mode: EncodingMode::Constant,
+            encoding: Vec::new(),
+            residual: bits.to_be_bytes().to_vec(),
+            ..expected.clone()
+        };
+        constant.with_full_codes().into_owned() == expected && constant.full_codes().eq(expected.codes())
+    }
+
+    #[test]
+    fn test_constant_blocks_hold_a_bounded_number_of_values() {
+        let at_most = core::iter::repeat_n(7, MAX_CONSTANT_VALUES);
+        assert_eq!(uniform_bits(at_most), Some(7));
+        assert_eq!(uniform_bits(core::iter::repeat_n(7, MAX_CONSTANT_VALUES + 1)), None);
+    }
+
+    #[test]
+    fn test_compact_mode_is_chosen_only_when_smaller() {
+        let noisy = noisy(1000);
+        let blk = compress_compact(DEFAULT_TABLE_SIZE, &noisy).unwrap();
+        assert_eq!(blk.mode(), EncodingMode::Compact);
+        assert_eq!(blk.encoding.len(), 375);
+        assert!(blk.compressed_len() < compress(DEFAULT_TABLE_SIZE, &noisy).unwrap().compressed_len());
+
+        let constant = vec![1.0; 1000];
+        assert_eq!(compress_compact(DEFAULT_TABLE_SIZE, &constant).unwrap().mode(), EncodingMode::Constant);
+        let ramp: Vec<f64> = (0..1000).map(|x| x as f64).collect();
+        assert_eq!(compress_compact(DEFAULT_TABLE_SIZE, &ramp).unwrap().mode(), EncodingMode::Full);
+    }
+
+    #[test]
+    fn test_compact_codes_pack_three_bits_each() {
+        let codes = [0b101, 0b011, 0b000, 0b111];
+        let encoding = pack_compact_codes(&codes);
+        assert_eq!(encoding, [0b1010_1100, 0b0111_0000]);
+        let unpacked: Vec<u8> = (0..4).map(|i| EncodingMode::Compact.code_at(&encoding, i).unwrap()).collect();
+        assert_eq!(unpacked, [0b1001, 0b0011, 0b0000, 0b1011]);
+        assert_eq!(EncodingMode::Compact.code_at(&encoding, 6), None);
+    }
+
+    #[test]
+    fn test_appending_to_a_compact_block_switches_to_full_codes() {
+        let values = noisy(101);
+        let mut blk = compress_compact(DEFAULT_TABLE_SIZE, &values[..60]).unwrap();
+        assert_eq!(blk.mode(), EncodingMode::Compact);
+        blk.append(DEFAULT_TABLE_SIZE, &values[60..]);
+        assert_eq!(blk.mode(), EncodingMode::Full);
+        assert!(bitwise_eq(&decompress(DEFAULT_TABLE_SIZE, &blk), &values));
+    }
+
+    #[test]
+    fn test_parts_of_a_compact_block_hold_full_codes() {
+        let values = noisy(101);
+        let blk = compress_compact(DEFAULT_TABLE_SIZE, &values).unwrap();
+        assert_eq!(blk.mode(), EncodingMode::Compact);
+        let (num_values, encoding, residual) = blk.into_parts();
+        assert_eq!(encoding.len(), 51);
+        let blk = FPCCompressedBlock::from_parts(DEFAULT_TABLE_SIZE, num_values, encoding, residual).unwrap();
+        assert!(bitwise_eq(&decompress(DEFAULT_TABLE_SIZE, &blk), &values));
+    }
+}
@@ -0,0 +1,224 @@
+//! Huffman coding of the encoding stream. A few codes tend to make up
+//! most of a block, e.g. the one for an exact FCM prediction on a
+//! repetitive series, while each still takes 4 bits, or 3 in
+//! [`EncodingMode::Compact`]. This stage replaces them with a canonical
+//! Huffman code built for the block. The residual is left untouched.
+//!
+//! The coded block is in-memory only and has no serialized form.
+
+use alloc::vec::Vec;
+
+use crate::encoding_mode::pack_compact_codes;
+use crate::format::{BitReader, BitWriter};
+use crate::{compress, try_decompress, CompressError, DecompressError, EncodingMode, FPCCompressedBlock, FpcError};
+
+/// Number of distinct codes, the 4-bit codes of [`EncodingMode::Full`].
+const NUM_CODES: usize = 16;
+
+/// A block whose encoding stream is Huffman coded, as returned by
+/// [`entropy_code`]. [`entropy_decode`] turns it back into the block it
+/// was made from.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EntropyCodedBlock {
+    // The block with its encoding moved out into `coded_encoding`.
+    block: FPCCompressedBlock,
+    code_lengths: [u8; NUM_CODES],
+    coded_encoding: Vec<u8>,
+}
+
+impl EntropyCodedBlock {
+    pub fn num_values(&self) -> usize {
+        self.block.num_bytes_encoded
+    }
+
+    /// Length in bits of the Huffman code of every 4-bit code, 0 for the
+    /// codes the block does not use. No length exceeds 15.
+    pub fn code_lengths(&self) -> &[u8; NUM_CODES] {
+        &self.code_lengths
+    }
+
+    /// The Huffman codes of the values, most significant bit first and
+    /// padded with zeros to whole bytes.
+    pub fn coded_encoding(&self) -> &[u8] {
+        &self.coded_encoding
+    }
+
+    /// Number of bytes of the coded encoding and the residual, plus the 8
+    /// bytes the code lengths take packed as nibbles.
+    pub fn compressed_len(&self) -> usize {
+        NUM_CODES / 2 + self.coded_encoding.len() + self.block.residual.len()
+    }
+}
+
+/// Huffman code lengths for codes occurring `counts` times. A code that
+/// occurs alone gets length 1. Ties are broken by position, so the lengths
+/// only depend on the counts.
+fn code_lengths(counts: &[usize; NUM_CODES]) -> [u8; NUM_CODES] {
+    let mut lengths = [0_u8; NUM_CODES];
+    // Every subtree as its weight and the set of codes below it.
+    let mut subtrees: Vec<(usize, u16)> =
+        (0..NUM_CODES).filter(|&code| counts[code] != 0).map(|code| (counts[code], 1 << code)).collect();
+    if let [(_, codes)] = subtrees[..] {
+        lengths[codes.trailing_zeros() as usize] = 1;
+    }
+    while subtrees.len() > 1 {
+        let mut lightest = || {
+            let index = (0..subtrees.len()).min_by_key(|&index| subtrees[index].0).unwrap();
+            subtrees.remove(index)
+        };
+        let ((a_weight, a_codes), (b_weight, b_codes)) = (lightest(), lightest());
+        for (code, length) in lengths.iter_mut().enumerate() {
+            if (a_codes | b_codes) & (1 << code) != 0 {
+                *length += 1;
+            }
+        }
+        subtrees.push((a_weight + b_weight, a_codes | b_codes));
+    }
+    lengths
+}
+
+/// Canonical Huffman codes of the given lengths: shorter codes first, and
+/// codes of equal length in order of the 4-bit code they stand for.
+fn canonical_codes(lengths: &[u8; NUM_CODES]) -> [u64; NUM_CODES] {
+    let mut codes = [0; NUM_CODES];
+    let mut next = 0_u64;
+    for length in 1..=15 {
+        for code in (0..NUM_CODES).filter(|&code| lengths[code] == length) {
+            codes[code] = next;
+            next += 1;
+        }
+        next <<= 1;
+    }
+    codes
+}
+
+/// Huffman codes the encoding stream of `blk` with a code built for its
+/// own codes. A constant block has no codes and stays as it is.
+pub fn entropy_code(blk: &FPCCompressedBlock) -> EntropyCodedBlock {
+    let mut counts = [0; NUM_CODES];
+    for code in blk.codes() {
+        counts[code as usize] += 1;
+    }
+    let code_lengths = code_lengths(&counts);
+    let huffman_codes = canonical_codes(&code_lengths);
+    let mut writer = BitWriter::with_capacity(blk.encoding.len());
+    for code in blk.codes() {
+        writer.write(huffman_codes[code as usize], code_lengths[code as usize] as u32);
+    }
+    let block = FPCCompressedBlock { encoding: Vec::new(), ..blk.clone() };
+    EntropyCodedBlock { block, code_lengths, coded_encoding: writer.finish() }
+}
+
+/// Reverses [`entropy_code`]. Fails with [`FpcError::InvalidEntropyCode`]
+/// if the code lengths do not make a prefix code or the coded encoding
+/// ends before every value has a code.
+pub fn entropy_decode(coded: &EntropyCodedBlock) -> Result<FPCCompressedBlock, FpcError> {
+    let lengths = &coded.code_lengths;
+    if lengths.iter().any(|&length| length > 15) {
+        return Err(FpcError::InvalidEntropyCode { at_value: 0 });
+    }
+    // The Kraft sum of a prefix code is at most 1, here 2^15.
+    if lengths.iter().filter(|&&length| length != 0).map(|&length| 1_u32 << (15 - length)).sum::<u32>() > 1 << 15 {
+        return Err(FpcError::InvalidEntropyCode { at_value: 0 });
+    }
+    let huffman_codes = canonical_codes(lengths);
+    let mut reader = BitReader::new(&coded.coded_encoding);
+    let num_values = coded.num_values();
+    let num_codes = if coded.block.mode == EncodingMode::Constant { 0 } else { num_values };
+    let mut codes = Vec::with_capacity(num_codes);
+    for at_value in 0..num_codes {
+        let invalid = || FpcError::InvalidEntropyCode { at_value };
+        let (mut value, mut length) = (0, 0);
+        let code = loop {
+            value = (value << 1) | reader.read(1).ok_or_else(invalid)?;
+            length += 1;
+            if let Some(code) = (0..NUM_CODES).find(|&code| lengths[code] == length && huffman_codes[code] == value) {
+                break code as u8;
+            }
+            if length == 15 {
+                return Err(invalid());
+            }
+        };
+        codes.push(code);
+    }
+    let encoding = match coded.block.mode {
+        EncodingMode::Full => codes.chunks(2).map(|pair| pair[0] << 4 | pair.get(1).copied().unwrap_or(0)).collect(),
+        EncodingMode::Compact => {
+            let compact: Vec<u8> = codes.iter().map(|code| (code & 0b1000) >> 1 | (code & 0b011)).collect();
+            pack_compact_codes(&compact)
+        }
+        EncodingMode::Constant => Vec::new(),
+    };
+    Ok(FPCCompressedBlock { encoding, ..coded.block.clone() })
+}
+
+/// Compresses `values` like [`compress`] and Huffman codes the encoding
+/// stream of the block with [`entropy_code`].
+pub fn compress_entropy_coded(table_size: u64, values: &[f64]) -> Result<EntropyCodedBlock, CompressError> {
+    compress(table_size, values).map(|blk| entropy_code(&blk))
+}
+
+/// Decompresses a block written by [`compress_entropy_coded`]. Fails like
+/// [`entropy_decode`] and [`try_decompress`].
+pub fn decompress_entropy_coded(table_size: u64, coded: &EntropyCodedBlock) -> Result<Vec<f64>, DecompressError> {
+    try_decompress(table_size, &entropy_decode(coded)?)
+}
+
+#[cfg(test)]
+mod entropy_test {
+    use quickcheck_macros::quickcheck;
+
+    use crate::{bitwise_eq, compress_compact, DEFAULT_TABLE_SIZE};
+
+    use super::*;
+
+    #[quickcheck]
+    fn entropy_coding_must_round_trip(to_compress: Vec<f64>) -> bool {
+        let coded = compress_entropy_coded(DEFAULT_TABLE_SIZE, &to_compress).unwrap();
+        let compact = compress_compact(DEFAULT_TABLE_SIZE, &to_compress).unwrap();
+        coded.num_values() == to_compress.len()
+            && entropy_decode(&coded) == compress(DEFAULT_TABLE_SIZE, &to_compress)
+            && entropy_decode(&entropy_code(&compact)) == Ok(compact)
+            && decompress_entropy_coded(DEFAULT_TABLE_SIZE, &coded).is_ok_and(|v| bitwise_eq(&v, &to_compress))
+    }
+
+    #[test]
+    fn test_code_lengths_follow_the_counts() {
+        let mut counts = [0; NUM_CODES];
+        counts[..4].copy_from_slice(&[8, 4, 2, 2]);
+        assert_eq!(code_lengths(&counts)[..5], [1, 2, 3, 3, 0]);
+        assert_eq!(canonical_codes(&code_lengths(&counts))[..4], [0b0, 0b10, 0b110, 0b111]);
+        let mut single = [0; NUM_CODES];
+        single[9] = 100;
+        assert_eq!(code_lengths(&single)[9], 1);
+        assert_eq!(code_lengths(&[0; NUM_CODES]), [0; NUM_CODES]);
+        // Counts growing like Fibonacci numbers make the deepest tree.
+        let mut fibonacci = [1; NUM_CODES];
+        for code in 2..NUM_CODES {
+            fibonacci[code] = fibonacci[code - 1] + fibonacci[code - 2];
+        }
+        assert_eq!(code_lengths(&fibonacci).iter().max(), Some(&15));
+    }
+
+    #[test]
+    fn test_entropy_coding_shrinks_skewed_encodings() {
+        let values: Vec<f64> = (0..4000).map(|x| ((x % 10) as f64).sqrt()).collect();
+        let blk = compress(DEFAULT_TABLE_SIZE, &values).unwrap();
+        let coded = entropy_code(&blk);
+        assert!(coded.coded_encoding().len() < blk.encoding().len() / 2);
+        assert!(coded.compressed_len() < blk.compressed_len());
+        let constant = compress(DEFAULT_TABLE_SIZE, &[2.5; 10]).unwrap();
+        assert_eq!(entropy_decode(&entropy_code(&constant)), Ok(constant));
+    }
+
+    #[test]
+    fn test_entropy_decode_rejects_bad_codes() {
+        let values: Vec<f64> = (0..100).map(|x| (x as f64 * 0.1).sin()).collect();
+        let mut coded = compress_entropy_coded(DEFAULT_TABLE_SIZE, &values).unwrap();
+        let mut truncated = coded.clone();
+        truncated.coded_encoding.truncate(truncated.coded_encoding.len() / 2);
+        assert!(matches!(entropy_decode(&truncated), Err(FpcError::InvalidEntropyCode { at_value }) if at_value > 0));
+        coded.code_lengths = [1; NUM_CODES];
+        assert_eq!(entropy_decode(&coded), Err(FpcError::InvalidEntropyCode { at_value: 0 }));
+    }
+}
@@ -0,0 +1,210 @@
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io;
+
+use crate::HashParams;
+
+/// Error returned by the compression entry points. Compression and
+/// decoding share [`FpcError`], so this is only a more descriptive name.
+pub type CompressError = FpcError;
+
+/// Error returned by the fallible decompression entry points, another
+/// name for [`FpcError`].
+pub type DecompressError = FpcError;
+
+/// Error returned when reading a serialized block, another name for
+/// [`FpcError`].
+pub type ParseError = FpcError;
+
+/// Error returned by [`FPCCompressedBlock::validate`](crate::FPCCompressedBlock::validate),
+/// another name for [`FpcError`].
+pub type ValidationError = FpcError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FpcError {
+    /// The built-in conformance check produced the wrong output. `stage`
+    /// names the step that failed, e.g. `"encoding"` or `"decompress"`.
+    SelfTestFailed { table_size: u64, stage: &'static str },
+    /// Fewer bytes than a full block header were supplied.
+    TruncatedHeader { len: usize },
+    /// The bytes do not start with the block magic.
+    BadMagic,
+    UnsupportedVersion { version: u8 },
+    /// The header has flag bits set that this version does not understand.
+    UnsupportedFlags { flags: u8 },
+    /// The table size is not a power of two of at most
+    /// [`MAX_TABLE_SIZE`](crate::MAX_TABLE_SIZE).
+    InvalidTableSize { given: u64 },
+    /// A file of the reference FPC tool declares a table of `2^given`
+    /// entries, more than [`MAX_TABLE_SIZE`](crate::MAX_TABLE_SIZE).
+    InvalidTableSizeLog { given: u8 },
+    /// A block was decoded with a different table size than it was
+    /// compressed with.
+    TableSizeMismatch { block: u64, given: u64 },
+    /// The header declares more encoding and residual bytes than follow it.
+    TruncatedPayload { expected: usize, available: usize },
+    /// A raw block was found where only a compressed one is accepted.
+    UnexpectedRawBlock,
+    /// Extra bytes follow the declared end of the block.
+    TrailingBytes { count: usize },
+    /// The encoding length does not match the number of values, which must
+    /// be what [`EncodingMode::encoding_len`](crate::EncodingMode::encoding_len)
+    /// gives for the block's mode.
+    InconsistentBlock { num_values: usize, encoding_len: usize },
+    /// The residual ran out while decoding the value at index `at_value`.
+    TruncatedResidual { at_value: usize, residual_len: usize },
+    /// The residual length does not match what the encoding implies.
+    ResidualLengthMismatch { expected: usize, actual: usize },
+    /// The CRC32 stored after a block does not match its payload.
+    ChecksumMismatch { expected: u32, actual: u32 },
+    /// A deduplicated stream refers to block `index` before that many
+    /// blocks have been read.
+    DanglingReference { index: u64, blocks_read: usize },
+    /// The output buffer cannot hold all values of the block.
+    OutputTooSmall { needed: usize, available: usize },
+    /// A caller-provided byte buffer is too small for the output.
+    BufferTooSmall { needed: usize, available: usize },
+    /// A requested range of values does not lie within the block.
+    RangeOutOfBounds { start: usize, end: usize, num_values: usize },
+    /// Blocks or chunks were asked to hold no values.
+    InvalidBlockLen { given: usize },
+    /// Column `column` holds a different number of values than the first
+    /// one.
+    ColumnLengthMismatch { column: usize, expected: usize, actual: usize },
+    /// A matrix of `rows` by `cols` values does not hold `len` values.
+    ShapeMismatch { rows: usize, cols: usize, len: usize },
+    /// A hash shift of the predictors is 64 or more.
+    InvalidHashParams { hash_params: HashParams },
+    /// A lossy block claims to have dropped more mantissa bits than an
+    /// `f64` has.
+    InvalidDroppedBits { given: u8 },
+    /// An [`FpcOptions`](crate::FpcOptions) setting is invalid on its own
+    /// or contradicts another one.
+    ConflictingOptions { detail: &'static str },
+    /// A serialized block of `len` bytes does not fit in a frame, whose
+    /// length prefix is 32 bits wide.
+    FrameTooLarge { len: usize },
+    /// A field of `value` in a resync segment header, such as a residual
+    /// length or the segment count, does not fit in its 32 bits.
+    SegmentFieldTooLarge { value: usize },
+    /// Text given as hex or base64 has an invalid character, or ends in
+    /// the middle of a byte or group, at character `position`.
+    InvalidText { position: usize },
+    /// A block compressed with the dictionary of id `expected` was given
+    /// the dictionary of id `given`.
+    DictionaryMismatch { expected: u32, given: u32 },
+    /// A block of `num_values` values cannot be split into two non-empty
+    /// blocks at value `at`.
+    InvalidSplit { at: usize, num_values: usize },
+    /// A Huffman coded encoding has no valid code for value `at_value`,
+    /// or its code lengths do not make a prefix code.
+    InvalidEntropyCode { at_value: usize },
+    /// Reading or writing the underlying stream failed.
+    #[cfg(feature = "std")]
+    Io { kind: io::ErrorKind, message: String },
+}
+
+impl fmt::Display for FpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FpcError::SelfTestFailed { table_size, stage } => {
+                write!(f, "self-test failed at {stage} with table size {table_size}")
+            }
+            FpcError::TruncatedHeader { len } => {
+                write!(f, "block header is truncated, only {len} bytes available")
+            }
+            FpcError::BadMagic => write!(f, "bytes do not start with an FPC block magic"),
+            FpcError::UnsupportedVersion { version } => {
+                write!(f, "unsupported block format version {version}")
+            }
+            FpcError::UnsupportedFlags { flags } => {
+                write!(f, "unsupported block flags {flags:#04x}")
+            }
+            FpcError::InvalidTableSize { given } => {
+                write!(f, "table size {given} is not a power of two of at most 2^24")
+            }
+            FpcError::InvalidTableSizeLog { given } => {
+                write!(f, "a table of 2^{given} entries is too large")
+            }
+            FpcError::TableSizeMismatch { block, given } => {
+                write!(f, "block was compressed with table size {block}, not {given}")
+            }
+            FpcError::TruncatedPayload { expected, available } => {
+                write!(f, "block payload is truncated, expected {expected} bytes but only {available} available")
+            }
+            FpcError::UnexpectedRawBlock => write!(f, "expected a compressed block but found a raw one"),
+            FpcError::TrailingBytes { count } => {
+                write!(f, "{count} unexpected bytes after the end of the block")
+            }
+            FpcError::InconsistentBlock { num_values, encoding_len } => {
+                write!(f, "block of {num_values} values cannot have {encoding_len} encoding bytes")
+            }
+            FpcError::TruncatedResidual { at_value, residual_len } => {
+                write!(f, "residual of {residual_len} bytes ran out while decoding value {at_value}")
+            }
+            FpcError::ResidualLengthMismatch { expected, actual } => {
+                write!(f, "encoding implies {expected} residual bytes but the block has {actual}")
+            }
+            FpcError::ChecksumMismatch { expected, actual } => {
+                write!(f, "block checksum is {actual:#010x} but {expected:#010x} was stored")
+            }
+            FpcError::DanglingReference { index, blocks_read } => {
+                write!(f, "reference to block {index} but only {blocks_read} blocks were read")
+            }
+            FpcError::OutputTooSmall { needed, available } => {
+                write!(f, "output holds {available} values but the block has {needed}")
+            }
+            FpcError::BufferTooSmall { needed, available } => {
+                write!(f, "buffer holds {available} bytes but {needed} are needed")
+            }
+            FpcError::RangeOutOfBounds { start, end, num_values } => {
+                write!(f, "range {start}..{end} is out of bounds for a block of {num_values} values")
+            }
+            FpcError::InvalidBlockLen { given } => {
+                write!(f, "blocks must hold at least one value, not {given}")
+            }
+            FpcError::ColumnLengthMismatch { column, expected, actual } => {
+                write!(f, "column {column} holds {actual} values, but the first one holds {expected}")
+            }
+            FpcError::ShapeMismatch { rows, cols, len } => {
+                write!(f, "a {rows} by {cols} matrix cannot hold {len} values")
+            }
+            FpcError::InvalidHashParams { hash_params } => {
+                write!(f, "hash shifts must be below 64, not {hash_params:?}")
+            }
+            FpcError::InvalidDroppedBits { given } => {
+                write!(f, "an f64 has 52 mantissa bits, so {given} cannot have been dropped")
+            }
+            FpcError::ConflictingOptions { detail } => write!(f, "invalid options: {detail}"),
+            FpcError::FrameTooLarge { len } => {
+                write!(f, "a block of {len} bytes does not fit in a frame of at most 4 GiB")
+            }
+            FpcError::SegmentFieldTooLarge { value } => {
+                write!(f, "{value} does not fit in a 32-bit resync segment header field")
+            }
+            FpcError::InvalidText { position } => {
+                write!(f, "invalid hex or base64 text at character {position}")
+            }
+            FpcError::DictionaryMismatch { expected, given } => {
+                write!(f, "block needs dictionary {expected}, but was given dictionary {given}")
+            }
+            FpcError::InvalidSplit { at, num_values } => {
+                write!(f, "cannot split a block of {num_values} values at value {at}")
+            }
+            FpcError::InvalidEntropyCode { at_value } => {
+                write!(f, "entropy coded encoding holds no valid code for value {at_value}")
+            }
+            #[cfg(feature = "std")]
+            FpcError::Io { message, .. } => write!(f, "I/O error: {message}"),
+        }
+    }
+}
+
+impl core::error::Error for FpcError {}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for FpcError {
+    fn from(e: io::Error) -> Self {
+        FpcError::Io { kind: e.kind(), message: e.to_string() }
+    }
+}
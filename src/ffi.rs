@@ -0,0 +1,236 @@
+//! C ABI, built with the `ffi` feature and declared in `include/fpc.h`.
+//! Every function returns [`FPC_OK`] or one of the negative `FPC_ERR_*`
+//! codes, and never unwinds into the caller.
+//!
+//! Output buffers are passed with their capacity in the length argument,
+//! which is overwritten with the number of bytes needed. Passing null
+//! buffers only queries those lengths.
+
+use core::ffi::c_int;
+use core::slice;
+
+use crate::{compress, CompressedBlockRef, FpcError};
+
+pub const FPC_OK: c_int = 0;
+/// A pointer that must not be null was null.
+pub const FPC_ERR_NULL_POINTER: c_int = -1;
+/// The table size is not a power of two of at most `2^24`.
+pub const FPC_ERR_INVALID_TABLE_SIZE: c_int = -2;
+/// An output buffer is too small; the lengths hold the sizes needed.
+pub const FPC_ERR_BUFFER_TOO_SMALL: c_int = -3;
+/// The encoding and residual do not describe the given number of values.
+pub const FPC_ERR_MALFORMED_BLOCK: c_int = -4;
+
+fn error_code(e: FpcError) -> c_int {
+    match e {
+        FpcError::InvalidTableSize { .. } => FPC_ERR_INVALID_TABLE_SIZE,
+        _ => FPC_ERR_MALFORMED_BLOCK,
+    }
+}
+
+/// Borrows `len` elements at `ptr`, which may be null when `len` is 0.
+///
+/// # Safety
+///
+/// Unless null, `ptr` must point to `len` initialized elements.
+unsafe fn borrow<'a, T>(ptr: *const T, len: usize) -> Option<&'a [T]> {
+    match (ptr.is_null(), len) {
+        (true, 0) => Some(&[]),
+        (true, _) => None,
+        (false, _) => Some(slice::from_raw_parts(ptr, len)),
+    }
+}
+
+/// Compresses the `n` values at `values` into an encoding and a residual,
+/// as [`compress`] does.
+///
+/// `*enc_len` and `*res_len` give the capacities of `out_encoding` and
+/// `out_residual` and are set to the lengths of the two streams. With
+/// both buffers null, only the lengths are set. If either buffer is too
+/// small nothing is written to them and [`FPC_ERR_BUFFER_TOO_SMALL`] is
+/// returned.
+///
+/// # Safety
+///
+/// `values` must point to `n` doubles, or be null if `n` is 0. `enc_len`
+/// and `res_len` must be valid for reads and writes. Non-null output
+/// buffers must be valid for writes of `*enc_len` and `*res_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn fpc_compress(
+    table_size: u64,
+    values: *const f64,
+    n: usize,
+    out_encoding: *mut u8,
+    enc_len: *mut usize,
+    out_residual: *mut u8,
+    res_len: *mut usize,
+) -> c_int {
+    if enc_len.is_null() || res_len.is_null() {
+        return FPC_ERR_NULL_POINTER;
+    }
+    let Some(values) = borrow(values, n) else {
+        return FPC_ERR_NULL_POINTER;
+    };
+    let blk = match compress(table_size, values) {
+        Ok(blk) => blk,
+        Err(e) => return error_code(e),
+    };
+    // The C interface has no room for the mode, so the streams always hold
+    // full codes.
+    let (_, encoding, residual) = blk.into_parts();
+    let (encoding_capacity, residual_capacity) = (*enc_len, *res_len);
+    *enc_len = encoding.len();
+    *res_len = residual.len();
+    match (out_encoding.is_null(), out_residual.is_null()) {
+        (true, true) => FPC_OK,
+        (false, false) => {
+            if encoding_capacity < encoding.len() || residual_capacity < residual.len() {
+                return FPC_ERR_BUFFER_TOO_SMALL;
+            }
+            out_encoding.copy_from_nonoverlapping(encoding.as_ptr(), encoding.len());
+            out_residual.copy_from_nonoverlapping(residual.as_ptr(), residual.len());
+            FPC_OK
+        }
+        _ => FPC_ERR_NULL_POINTER,
+    }
+}
+
+/// Decompresses `n` values from an encoding and residual written by
+/// [`fpc_compress`] with the same `table_size` into `out_values`.
+///
+/// # Safety
+///
+/// `encoding` and `residual` must point to `enc_len` and `res_len` bytes,
+/// or be null if their length is 0. `out_values` must be valid for writes
+/// of `n` doubles, or be null if `n` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn fpc_decompress(
+    table_size: u64,
+    encoding: *const u8,
+    enc_len: usize,
+    residual: *const u8,
+    res_len: usize,
+    out_values: *mut f64,
+    n: usize,
+) -> c_int {
+    let (Some(encoding), Some(residual)) = (borrow(encoding, enc_len), borrow(residual, res_len)) else {
+        return FPC_ERR_NULL_POINTER;
+    };
+    if out_values.is_null() && n != 0 {
+        return FPC_ERR_NULL_POINTER;
+    }
+    let blk_ref = CompressedBlockRef { num_bytes_encoded: n, encoding, residual };
+    let values = match blk_ref.iter(table_size) {
+        Ok(values) => values,
+        Err(e) => return error_code(e),
+    };
+    for (index, value) in values.enumerate() {
+        match value {
+            Ok(value) => out_values.add(index).write(value),
+            Err(e) => return error_code(e),
+        }
+    }
+    FPC_OK
+}
+
+#[cfg(test)]
+mod ffi_test {
+    use core::ptr;
+
+    use quickcheck_macros::quickcheck;
+
+    use crate::{bitwise_eq, DEFAULT_TABLE_SIZE};
+
+    use super::*;
+
+    fn compress_via_ffi(table_size: u64, values: &[f64]) -> Result<(Vec<u8>, Vec<u8>), c_int> {
+        let (mut enc_len, mut res_len) = (0, 0);
+        let code = unsafe {
+            fpc_compress(
+                table_size,
+                values.as_ptr(),
+                values.len(),
+                ptr::null_mut(),
+                &mut enc_len,
+                ptr::null_mut(),
+                &mut res_len,
+            )
+        };
+        if code != FPC_OK {
+            return Err(code);
+        }
+        let (mut encoding, mut residual) = (vec![0; enc_len], vec![0; res_len]);
+        let code = unsafe {
+            fpc_compress(
+                table_size,
+                values.as_ptr(),
+                values.len(),
+                encoding.as_mut_ptr(),
+                &mut enc_len,
+                residual.as_mut_ptr(),
+                &mut res_len,
+            )
+        };
+        if code != FPC_OK {
+            return Err(code);
+        }
+        Ok((encoding, residual))
+    }
+
+    #[quickcheck]
+    fn ffi_must_round_trip(to_compress: Vec<f64>) -> bool {
+        let Ok((encoding, residual)) = compress_via_ffi(DEFAULT_TABLE_SIZE, &to_compress) else {
+            return false;
+        };
+        let blk = compress(DEFAULT_TABLE_SIZE, &to_compress).unwrap();
+        let mut out = vec![0.0; to_compress.len()];
+        let code = unsafe {
+            fpc_decompress(
+                DEFAULT_TABLE_SIZE,
+                encoding.as_ptr(),
+                encoding.len(),
+                residual.as_ptr(),
+                residual.len(),
+                out.as_mut_ptr(),
+                out.len(),
+            )
+        };
+        let (_, expected_encoding, expected_residual) = blk.into_parts();
+        encoding == expected_encoding && residual == expected_residual && code == FPC_OK && bitwise_eq(&out, &to_compress)
+    }
+
+    #[test]
+    fn test_ffi_reports_errors() {
+        assert_eq!(compress_via_ffi(3, &[1.0]), Err(FPC_ERR_INVALID_TABLE_SIZE));
+        let values = [1.0, 2.5, 3.0];
+        let (mut enc_len, mut res_len) = (0, 0);
+        let mut small = [0_u8; 1];
+        let code = unsafe {
+            fpc_compress(
+                DEFAULT_TABLE_SIZE,
+                values.as_ptr(),
+                values.len(),
+                small.as_mut_ptr(),
+                &mut enc_len,
+                small.as_mut_ptr(),
+                &mut res_len,
+            )
+        };
+        assert_eq!(code, FPC_ERR_BUFFER_TOO_SMALL);
+        assert_eq!(enc_len, 2);
+        let code = unsafe {
+            fpc_compress(DEFAULT_TABLE_SIZE, ptr::null(), 3, ptr::null_mut(), &mut enc_len, ptr::null_mut(), &mut res_len)
+        };
+        assert_eq!(code, FPC_ERR_NULL_POINTER);
+
+        let (encoding, residual) = compress_via_ffi(DEFAULT_TABLE_SIZE, &values).unwrap();
+        let mut out = [0.0; 3];
+        let mut decompress = |n, res_len| unsafe {
+            fpc_decompress(DEFAULT_TABLE_SIZE, encoding.as_ptr(), 2, residual.as_ptr(), res_len, out.as_mut_ptr(), n)
+        };
+        assert_eq!(decompress(1, residual.len()), FPC_ERR_MALFORMED_BLOCK);
+        assert_eq!(decompress(3, 10), FPC_ERR_MALFORMED_BLOCK);
+        assert_eq!(decompress(3, residual.len()), FPC_OK);
+        assert_eq!(out, values);
+    }
+}
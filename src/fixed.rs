@@ -0,0 +1,147 @@
+//! Compression without a heap: the predictor tables of `N` entries each
+//! live in arrays on the stack, and the encoding and residual are written
+//! into caller-provided buffers.
+//!
+//! Output is byte-for-byte the encoding and residual that
+//! [`into_parts`](crate::FPCCompressedBlock::into_parts) returns for the
+//! block [`compress`](crate::compress) produces with a table size of `N`.
+//! Both spell out full codes even where `compress` picks a constant block.
+
+use crate::{
+    decode_value, encode_bits, CompressError, DecompressError, EncodingSink, FpcError, HashParams, Predictors,
+    TieBreak,
+};
+
+impl<const N: usize> Predictors<[u64; N]> {
+    fn on_stack() -> Self {
+        const { assert!(N.is_power_of_two(), "table size must be a power of two") };
+        Predictors {
+            table_mask: N as u64 - 1,
+            hash_params: HashParams::DEFAULT,
+            tie_break: TieBreak::PreferDfcm,
+            last_value: 0,
+            fcm_hash: 0,
+            fcm: [0; N],
+            dfcm_hash: 0,
+            dfcm: [0; N],
+        }
+    }
+}
+
+/// Writes into a pair of fixed buffers, counting how much would have been
+/// written once they are full.
+struct SliceSink<'a> {
+    encoding: &'a mut [u8],
+    encoding_len: usize,
+    residual: &'a mut [u8],
+    residual_len: usize,
+}
+
+impl EncodingSink for SliceSink<'_> {
+    fn push_encoding(&mut self, byte: u8) {
+        if let Some(slot) = self.encoding.get_mut(self.encoding_len) {
+            *slot = byte;
+        }
+        self.encoding_len += 1;
+    }
+
+    fn push_residual(&mut self, bytes: &[u8]) {
+        let end = self.residual_len + bytes.len();
+        if let Some(slots) = self.residual.get_mut(self.residual_len..end) {
+            slots.copy_from_slice(bytes);
+        }
+        self.residual_len = end;
+    }
+}
+
+/// Compresses `values` with tables of `N` entries, writing the encoding
+/// and residual to the start of the two buffers. Returns how many bytes of
+/// each were written.
+///
+/// `N` must be a power of two, which is checked at compile time. The
+/// encoding takes `(values.len() + 1) / 2` bytes and the residual at most
+/// `8 * values.len()`; if either buffer is too small this fails with
+/// [`FpcError::BufferTooSmall`] reporting the size needed, and the buffer
+/// contents are unspecified.
+pub fn compress_fixed<const N: usize>(
+    values: &[f64],
+    encoding: &mut [u8],
+    residual: &mut [u8],
+) -> Result<(usize, usize), CompressError> {
+    let mut predictors = Predictors::<[u64; N]>::on_stack();
+    let mut sink = SliceSink { encoding, encoding_len: 0, residual, residual_len: 0 };
+    encode_bits(&mut predictors, values.iter().map(|v| v.to_bits()), &mut sink, None);
+    for (needed, available) in [(sink.encoding_len, sink.encoding.len()), (sink.residual_len, sink.residual.len())] {
+        if needed > available {
+            return Err(FpcError::BufferTooSmall { needed, available });
+        }
+    }
+    Ok((sink.encoding_len, sink.residual_len))
+}
+
+/// Decodes `num_values` values written by [`compress_fixed`] with the same
+/// `N` into `out[..num_values]`, returning the number of values written.
+pub fn decompress_fixed<const N: usize>(
+    num_values: usize,
+    encoding: &[u8],
+    residual: &[u8],
+    out: &mut [f64],
+) -> Result<usize, DecompressError> {
+    if encoding.len() < num_values.div_ceil(2) {
+        return Err(FpcError::InconsistentBlock { num_values, encoding_len: encoding.len() });
+    }
+    if out.len() < num_values {
+        return Err(FpcError::OutputTooSmall { needed: num_values, available: out.len() });
+    }
+    let mut predictors = Predictors::<[u64; N]>::on_stack();
+    let mut residual_index = 0;
+    let codes = encoding.iter().flat_map(|&byte| [byte >> 4, byte & 0xf]).take(num_values);
+    for (at_value, (code, slot)) in codes.zip(out.iter_mut()).enumerate() {
+        let decoded = decode_value(&mut predictors, code, residual, &mut residual_index)
+            .ok_or(FpcError::TruncatedResidual { at_value, residual_len: residual.len() })?;
+        *slot = f64::from_bits(decoded);
+    }
+    Ok(num_values)
+}
+
+#[cfg(test)]
+mod fixed_test {
+    use quickcheck_macros::quickcheck;
+
+    use crate::compress;
+
+    use super::*;
+
+    #[quickcheck]
+    fn fixed_compression_must_match_compress(to_compress: Vec<f64>) -> bool {
+        let mut encoding = vec![0; to_compress.len().div_ceil(2)];
+        let mut residual = vec![0; to_compress.len() * 8];
+        let (encoding_len, residual_len) = compress_fixed::<64>(&to_compress, &mut encoding, &mut residual).unwrap();
+        let (_, expected_encoding, expected_residual) = compress(64, &to_compress).unwrap().into_parts();
+        let mut out = vec![0.0; to_compress.len()];
+        decompress_fixed::<64>(to_compress.len(), &encoding, &residual[..residual_len], &mut out).unwrap();
+        encoding[..encoding_len] == expected_encoding
+            && residual[..residual_len] == expected_residual
+            && out.iter().zip(&to_compress).all(|(a, b)| a.to_bits() == b.to_bits())
+    }
+
+    #[test]
+    fn test_fixed_reports_short_buffers() {
+        let values = [1.0, 2.5, -3.75];
+        let mut encoding = [0; 2];
+        let mut residual = [0; 4];
+        let needed = compress(16, &values).unwrap().residual().len();
+        assert_eq!(
+            compress_fixed::<16>(&values, &mut encoding, &mut residual),
+            Err(FpcError::BufferTooSmall { needed, available: 4 }),
+        );
+        assert_eq!(
+            compress_fixed::<16>(&values, &mut encoding[..1], &mut [0; 64]),
+            Err(FpcError::BufferTooSmall { needed: 2, available: 1 }),
+        );
+        assert_eq!(
+            decompress_fixed::<16>(3, &encoding, &residual, &mut [0.0; 2]),
+            Err(FpcError::OutputTooSmall { needed: 3, available: 2 }),
+        );
+    }
+}
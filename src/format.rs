@@ -0,0 +1,1105 @@
+//! Self-describing byte layout of a single block. All integers are
+//! little-endian.
+//!
+//! | offset | size | field                           |
+//! |--------|------|---------------------------------|
+//! | 0      | 4    | magic, `b"FPC1"`                |
+//! | 4      | 1    | format version, currently 2     |
+//! | 5      | 1    | flags                           |
+//! | 6      | 8    | table size                      |
+//! | 14     | 8    | number of values                |
+//! | 22     | 8    | encoding length `E`             |
+//! | 30     | 8    | residual length `R`             |
+//! | 38     | `P`  | hash parameters, 4 bytes if     |
+//! |        |      | flag bit 5 is set, then dropped |
+//! |        |      | mantissa bits, 1 byte if flag   |
+//! |        |      | bit 6 is set, then the          |
+//! |        |      | extension, 9 bytes if flag bit  |
+//! |        |      | 7 is set                        |
+//! | 38 + P | `E`  | encoding                        |
+//! | 38+P+E | `R`  | residual                        |
+//! | ...    | 4    | CRC32 of everything since the   |
+//! |        |      | header, only if flag bit 2 is   |
+//! |        |      | set                             |
+//!
+//! Flag bit 0 marks a residual stored in [`ResidualLayout::Transposed`]
+//! order and flag bit 3 one stored [`ResidualLayout::BitPacked`]; at most
+//! one of the two is set. Flag bit 1 marks a raw block (see
+//! [`StoredBlock::Raw`]), whose encoding is empty and whose residual
+//! region holds every value as a little-endian `f64` instead. Flag bit 2
+//! marks a block followed by a CRC32 of its encoding and residual bytes
+//! as serialized; it is only written and understood with the `checksum`
+//! feature. Flag bit 4 marks an encoding of 3-bit codes in
+//! [`EncodingMode::Compact`] rather than 4-bit ones, which changes the
+//! encoding length `E` a block of `n` values has from `(n + 1) / 2` to
+//! `(3n + 7) / 8`. Flag bit 5 marks a block compressed with
+//! [`HashParams`] other than [`HashParams::DEFAULT`], stored as the
+//! bytes `fcm_shift`, `fcm_fold`, `dfcm_shift` and `dfcm_fold`; blocks with
+//! the default parameters leave it clear and are laid out as before. Flag
+//! bit 6 marks a block of values whose low mantissa bits were dropped by
+//! [`compress_lossy`](crate::compress_lossy), stored as the number of bits
+//! dropped. Flag bit 7 marks a block with an extension, which holds a byte
+//! of further flags followed by the initial last value of the predictors
+//! as a `u64`, set by [`compress_with_seed`](crate::compress_with_seed).
+//! Extension flag bit 0 marks a block compressed after the delta-of-delta
+//! stage of [`FpcOptions::delta_of_delta`](crate::FpcOptions::delta_of_delta),
+//! and bit 1 one whose NaNs were replaced by `f64::NAN` as set by
+//! [`FpcOptions::canonicalize_nan`](crate::FpcOptions::canonicalize_nan).
+//! Blocks without a seed or extension flags leave flag bit 7 clear.
+//! Readers reject blocks with any flag they do not know.
+//!
+//! A block of [`EncodingMode::Constant`] has no flag of its own. It is the
+//! block whose encoding is empty even though it holds values, and its
+//! residual is the 8 bytes of the value, always interleaved.
+//!
+//! Version 2 added the flags byte; version 1 blocks are no longer read.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::{
+    is_valid_table_size, lossy, residual_len_of_code, EncodingMode, FPCCompressedBlock, FpcError, HashParams, ParseError,
+    StoredBlock, MAX_CONSTANT_VALUES,
+};
+
+const MAGIC: [u8; 4] = *b"FPC1";
+const FORMAT_VERSION: u8 = 2;
+pub(crate) const HEADER_LEN: usize = 38;
+
+const MAGIC_RANGE: Range<usize> = 0..4;
+const VERSION_RANGE: Range<usize> = 4..5;
+const FLAGS_RANGE: Range<usize> = 5..6;
+const TABLE_SIZE_RANGE: Range<usize> = 6..14;
+const COUNT_RANGE: Range<usize> = 14..22;
+const ENCODING_LEN_RANGE: Range<usize> = 22..30;
+const RESIDUAL_LEN_RANGE: Range<usize> = 30..38;
+
+const FLAG_TRANSPOSED_RESIDUAL: u8 = 1 << 0;
+pub(crate) const FLAG_RAW: u8 = 1 << 1;
+const FLAG_CHECKSUM: u8 = 1 << 2;
+const FLAG_BIT_PACKED_RESIDUAL: u8 = 1 << 3;
+const FLAG_COMPACT_ENCODING: u8 = 1 << 4;
+const FLAG_HASH_PARAMS: u8 = 1 << 5;
+const FLAG_LOSSY: u8 = 1 << 6;
+const FLAG_EXTENSION: u8 = 1 << 7;
+const BLOCK_PARAM_FLAGS: u8 = FLAG_HASH_PARAMS | FLAG_LOSSY | FLAG_EXTENSION;
+
+const EXTENSION_FLAG_DELTA_OF_DELTA: u8 = 1 << 0;
+const EXTENSION_FLAG_CANONICALIZE_NAN: u8 = 1 << 1;
+const KNOWN_EXTENSION_FLAGS: u8 = EXTENSION_FLAG_DELTA_OF_DELTA | EXTENSION_FLAG_CANONICALIZE_NAN;
+const LAYOUT_FLAGS: u8 = FLAG_TRANSPOSED_RESIDUAL | FLAG_BIT_PACKED_RESIDUAL;
+#[cfg(feature = "checksum")]
+const KNOWN_FLAGS: u8 = LAYOUT_FLAGS | FLAG_RAW | FLAG_CHECKSUM | FLAG_COMPACT_ENCODING | BLOCK_PARAM_FLAGS;
+#[cfg(not(feature = "checksum"))]
+const KNOWN_FLAGS: u8 = LAYOUT_FLAGS | FLAG_RAW | FLAG_COMPACT_ENCODING | BLOCK_PARAM_FLAGS;
+
+const CHECKSUM_LEN: usize = 4;
+const HASH_PARAMS_LEN: usize = 4;
+const DROPPED_BITS_LEN: usize = 1;
+const EXTENSION_LEN: usize = 9;
+
+/// Form in which [`FPCCompressedBlock::to_bytes_with_layout`] writes the
+/// residual. Decoding always works on the interleaved bytes, so this only
+/// affects the serialized bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResidualLayout {
+    /// The residual bytes of each value are stored together, in value order.
+    #[default]
+    Interleaved,
+    /// The residual bytes are grouped by their position within the 64-bit
+    /// difference, most significant position first. Bytes of similar
+    /// significance end up next to each other, which tends to help a
+    /// general-purpose compressor such as zlib or zstd run on the output.
+    Transposed,
+    /// The residual of each value is stored as a bit string instead of
+    /// whole bytes: 3 bits holding how many of its top bits are zero (7
+    /// meaning 7 or more), then the bits below those zeros, minus the
+    /// leading 1 bit when it is known to be there. This saves about a
+    /// bit and a half per value when the differences from the predictions
+    /// are spread evenly over bit positions, and costs up to 2 bits per
+    /// value when their top residual bytes tend to be full.
+    BitPacked,
+}
+
+/// Byte ranges of every field of a serialized block, as returned by
+/// [`layout`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockLayout {
+    pub magic: Range<usize>,
+    pub version: Range<usize>,
+    pub flags: Range<usize>,
+    pub table_size: Range<usize>,
+    pub count: Range<usize>,
+    pub encoding_len: Range<usize>,
+    pub residual_len: Range<usize>,
+    pub encoding: Range<usize>,
+    pub residual: Range<usize>,
+    /// The hash parameters, if the block has them.
+    pub hash_params: Option<Range<usize>>,
+    /// The number of dropped mantissa bits, if the block is lossy.
+    pub dropped_mantissa_bits: Option<Range<usize>>,
+    /// The extension flags and seed, if the block has them.
+    pub extension: Option<Range<usize>>,
+    /// The trailing CRC32, if the block has one.
+    pub checksum: Option<Range<usize>>,
+}
+
+/// Fields of a validated block header.
+pub(crate) struct Header {
+    pub(crate) raw: bool,
+    pub(crate) checksum: bool,
+    pub(crate) has_hash_params: bool,
+    pub(crate) lossy: bool,
+    pub(crate) extended: bool,
+    pub(crate) residual_layout: ResidualLayout,
+    pub(crate) mode: EncodingMode,
+    pub(crate) table_size: u64,
+    pub(crate) num_values: usize,
+    pub(crate) encoding_len: usize,
+    pub(crate) residual_len: usize,
+}
+
+impl Header {
+    fn hash_params_len(&self) -> usize {
+        if self.has_hash_params { HASH_PARAMS_LEN } else { 0 }
+    }
+
+    /// Length of the dropped mantissa bit count, present only in lossy
+    /// blocks.
+    fn dropped_bits_len(&self) -> usize {
+        if self.lossy { DROPPED_BITS_LEN } else { 0 }
+    }
+
+    /// Length of the block parameters between the header and the encoding.
+    pub(crate) fn params_len(&self) -> usize {
+        self.hash_params_len() + self.dropped_bits_len() + if self.extended { EXTENSION_LEN } else { 0 }
+    }
+
+    /// Length of everything after the header, including the block
+    /// parameters and the checksum.
+    pub(crate) fn payload_len(&self) -> usize {
+        self.params_len() + self.encoding_len + self.residual_len + if self.checksum { CHECKSUM_LEN } else { 0 }
+    }
+
+    pub(crate) fn block_len(&self) -> usize {
+        HEADER_LEN + self.payload_len()
+    }
+}
+
+fn read_u64(header: &[u8; HEADER_LEN], range: Range<usize>) -> u64 {
+    u64::from_le_bytes(header[range].try_into().unwrap())
+}
+
+fn read_len(header: &[u8; HEADER_LEN], range: Range<usize>, available: usize) -> Result<usize, FpcError> {
+    let len = read_u64(header, range);
+    usize::try_from(len).map_err(|_| FpcError::TruncatedPayload { expected: usize::MAX, available })
+}
+
+/// Parses and validates the header at the start of `bytes`. Only the
+/// header itself has to be present.
+///
+/// Every entry point that reads a serialized block goes through here, so
+/// short input is always reported as [`FpcError::TruncatedHeader`]. All
+/// fields are read from a fixed-size copy of the header, which keeps the
+/// reads in bounds by construction.
+// With the `checksum` feature every flag bit is known, which makes the
+// unknown flag check a no-op there.
+#[cfg_attr(feature = "checksum", allow(clippy::bad_bit_mask))]
+pub(crate) fn parse_header(bytes: &[u8]) -> Result<Header, FpcError> {
+    let available = bytes.len();
+    let header: &[u8; HEADER_LEN] = bytes
+        .get(..HEADER_LEN)
+        .and_then(|header| header.try_into().ok())
+        .ok_or(FpcError::TruncatedHeader { len: available })?;
+    if header[MAGIC_RANGE] != MAGIC {
+        return Err(FpcError::BadMagic);
+    }
+    let version = header[VERSION_RANGE.start];
+    if version != FORMAT_VERSION {
+        return Err(FpcError::UnsupportedVersion { version });
+    }
+    let flags = header[FLAGS_RANGE.start];
+    let raw = flags & FLAG_RAW != 0;
+    // A raw block has no codes to pack, no residual to reorder and holds
+    // its values exactly as given.
+    if flags & !KNOWN_FLAGS != 0
+        || (raw && flags & (LAYOUT_FLAGS | FLAG_COMPACT_ENCODING | BLOCK_PARAM_FLAGS) != 0)
+        || flags & LAYOUT_FLAGS == LAYOUT_FLAGS
+    {
+        return Err(FpcError::UnsupportedFlags { flags });
+    }
+    let residual_layout = if flags & FLAG_TRANSPOSED_RESIDUAL != 0 {
+        ResidualLayout::Transposed
+    } else if flags & FLAG_BIT_PACKED_RESIDUAL != 0 {
+        ResidualLayout::BitPacked
+    } else {
+        ResidualLayout::Interleaved
+    };
+    let mode = if flags & FLAG_COMPACT_ENCODING != 0 { EncodingMode::Compact } else { EncodingMode::Full };
+    let table_size = read_u64(header, TABLE_SIZE_RANGE);
+    if !is_valid_table_size(table_size) {
+        return Err(FpcError::InvalidTableSize { given: table_size });
+    }
+    let num_values = read_len(header, COUNT_RANGE, available)?;
+    let encoding_len = read_len(header, ENCODING_LEN_RANGE, available)?;
+    let residual_len = read_len(header, RESIDUAL_LEN_RANGE, available)?;
+    // Only a constant block holds values without any codes, and only up to
+    // a bound, since its values take no room in the block.
+    let constant = (1..=MAX_CONSTANT_VALUES).contains(&num_values) && encoding_len == 0;
+    let mode = if !raw && mode == EncodingMode::Full && constant {
+        EncodingMode::Constant
+    } else {
+        mode
+    };
+    if mode == EncodingMode::Constant {
+        if flags & LAYOUT_FLAGS != 0 {
+            return Err(FpcError::UnsupportedFlags { flags });
+        }
+        if residual_len != size_of::<u64>() {
+            return Err(FpcError::ResidualLengthMismatch { expected: size_of::<u64>(), actual: residual_len });
+        }
+    }
+    if raw {
+        if encoding_len != 0 {
+            return Err(FpcError::InconsistentBlock { num_values, encoding_len });
+        }
+        if num_values.checked_mul(size_of::<f64>()) != Some(residual_len) {
+            return Err(FpcError::ResidualLengthMismatch {
+                expected: num_values.saturating_mul(size_of::<f64>()),
+                actual: residual_len,
+            });
+        }
+    } else if encoding_len != mode.encoding_len(num_values) {
+        return Err(FpcError::InconsistentBlock { num_values, encoding_len });
+    }
+    if encoding_len
+        .checked_add(residual_len)
+        .and_then(|len| len.checked_add(HEADER_LEN + HASH_PARAMS_LEN + DROPPED_BITS_LEN + EXTENSION_LEN + CHECKSUM_LEN))
+        .is_none()
+    {
+        return Err(FpcError::TruncatedPayload { expected: usize::MAX, available });
+    }
+    let checksum = flags & FLAG_CHECKSUM != 0;
+    let has_hash_params = flags & FLAG_HASH_PARAMS != 0;
+    let lossy = flags & FLAG_LOSSY != 0;
+    let extended = flags & FLAG_EXTENSION != 0;
+    Ok(Header {
+        raw,
+        checksum,
+        has_hash_params,
+        lossy,
+        extended,
+        residual_layout,
+        mode,
+        table_size,
+        num_values,
+        encoding_len,
+        residual_len,
+    })
+}
+
+/// Settings of a compressed block stored between the header and the
+/// encoding, each only when it differs from its default.
+#[derive(Clone, Copy)]
+pub(crate) struct BlockParams {
+    pub(crate) hash_params: HashParams,
+    pub(crate) dropped_mantissa_bits: u8,
+    pub(crate) delta_of_delta: bool,
+    pub(crate) seed: u64,
+    pub(crate) canonicalize_nan: bool,
+}
+
+/// The regions of a block's payload, as split by [`split_payload`].
+pub(crate) struct Payload<'a> {
+    pub(crate) params: BlockParams,
+    pub(crate) encoding: &'a [u8],
+    pub(crate) residual: &'a [u8],
+}
+
+/// Splits the payload following a header into its block parameters,
+/// encoding and residual, after checking the trailing CRC32 if the block
+/// has one. `payload` must be exactly [`Header::payload_len`] bytes.
+pub(crate) fn split_payload<'a>(header: &Header, payload: &'a [u8]) -> Result<Payload<'a>, FpcError> {
+    let (data, stored) = payload.split_at(header.params_len() + header.encoding_len + header.residual_len);
+    #[cfg(feature = "checksum")]
+    if header.checksum {
+        let expected = u32::from_le_bytes(stored.try_into().unwrap());
+        let actual = crc32fast::hash(data);
+        if actual != expected {
+            return Err(FpcError::ChecksumMismatch { expected, actual });
+        }
+    }
+    // Without the feature the flag is rejected, so there is nothing left.
+    debug_assert!(header.checksum || stored.is_empty());
+    let (hash_params, data) = data.split_at(header.hash_params_len());
+    let hash_params = match *hash_params {
+        [fcm_shift, fcm_fold, dfcm_shift, dfcm_fold] => HashParams { fcm_shift, fcm_fold, dfcm_shift, dfcm_fold },
+        _ => HashParams::DEFAULT,
+    };
+    hash_params.validate()?;
+    let (dropped_mantissa_bits, data) = data.split_at(header.dropped_bits_len());
+    let dropped_mantissa_bits = dropped_mantissa_bits.first().copied().unwrap_or(0);
+    if dropped_mantissa_bits > lossy::MANTISSA_BITS {
+        return Err(FpcError::InvalidDroppedBits { given: dropped_mantissa_bits });
+    }
+    let (extension, data) = data.split_at(header.params_len() - header.hash_params_len() - header.dropped_bits_len());
+    let (extension_flags, seed) = match extension.split_first() {
+        Some((&flags, seed)) => (flags, u64::from_le_bytes(seed.try_into().unwrap())),
+        None => (0, 0),
+    };
+    if extension_flags & !KNOWN_EXTENSION_FLAGS != 0 {
+        return Err(FpcError::UnsupportedFlags { flags: extension_flags });
+    }
+    let delta_of_delta = extension_flags & EXTENSION_FLAG_DELTA_OF_DELTA != 0;
+    let canonicalize_nan = extension_flags & EXTENSION_FLAG_CANONICALIZE_NAN != 0;
+    let (encoding, residual) = data.split_at(header.encoding_len);
+    let params = BlockParams { hash_params, dropped_mantissa_bits, delta_of_delta, seed, canonicalize_nan };
+    Ok(Payload { params, encoding, residual })
+}
+
+/// Sets the checksum flag on the single serialized block in `bytes` and
+/// appends the CRC32 of its payload.
+#[cfg(feature = "checksum")]
+fn append_checksum(bytes: &mut Vec<u8>) {
+    bytes[FLAGS_RANGE.start] |= FLAG_CHECKSUM;
+    let crc = crc32fast::hash(&bytes[HEADER_LEN..]);
+    bytes.extend_from_slice(&crc.to_le_bytes());
+}
+
+fn check_payload(header: &Header, bytes: &[u8]) -> Result<(), FpcError> {
+    if bytes.len() < header.block_len() {
+        return Err(FpcError::TruncatedPayload { expected: header.block_len(), available: bytes.len() });
+    }
+    Ok(())
+}
+
+/// Reports where each field of the serialized block at the start of
+/// `bytes` lives, without decoding any values. Bytes after the end of
+/// the block are ignored.
+pub fn layout(bytes: &[u8]) -> Result<BlockLayout, ParseError> {
+    let header = parse_header(bytes)?;
+    check_payload(&header, bytes)?;
+    let hash_params = header.has_hash_params.then_some(HEADER_LEN..HEADER_LEN + HASH_PARAMS_LEN);
+    let dropped_bits_start = HEADER_LEN + header.hash_params_len();
+    let dropped_mantissa_bits = header.lossy.then_some(dropped_bits_start..dropped_bits_start + DROPPED_BITS_LEN);
+    let extension_start = dropped_bits_start + header.dropped_bits_len();
+    let extension = header.extended.then_some(extension_start..extension_start + EXTENSION_LEN);
+    let encoding_start = HEADER_LEN + header.params_len();
+    let encoding = encoding_start..encoding_start + header.encoding_len;
+    let residual = encoding.end..encoding.end + header.residual_len;
+    let checksum = header.checksum.then(|| residual.end..residual.end + CHECKSUM_LEN);
+    Ok(BlockLayout {
+        magic: MAGIC_RANGE,
+        version: VERSION_RANGE,
+        flags: FLAGS_RANGE,
+        table_size: TABLE_SIZE_RANGE,
+        count: COUNT_RANGE,
+        encoding_len: ENCODING_LEN_RANGE,
+        residual_len: RESIDUAL_LEN_RANGE,
+        encoding,
+        residual,
+        hash_params,
+        dropped_mantissa_bits,
+        extension,
+        checksum,
+    })
+}
+
+/// Reads the number of values in the serialized block at the start of
+/// `bytes` from its header alone. The payload does not have to be present.
+pub fn peek_num_values(bytes: &[u8]) -> Result<usize, ParseError> {
+    parse_header(bytes).map(|header| header.num_values)
+}
+
+/// Returns block `index` of a concatenation of serialized blocks, or
+/// `None` if there are not that many. Earlier blocks are skipped by their
+/// header lengths without being decoded.
+pub fn get_block(bytes: &[u8], index: usize) -> Result<Option<StoredBlock>, ParseError> {
+    let mut rest = bytes;
+    for current in 0.. {
+        if rest.is_empty() {
+            break;
+        }
+        let header = parse_header(rest)?;
+        check_payload(&header, rest)?;
+        let (block, tail) = rest.split_at(header.block_len());
+        if current == index {
+            let Payload { params, encoding, residual } = split_payload(&header, &block[HEADER_LEN..])?;
+            return StoredBlock::from_header_and_payload(&header, params, encoding.to_vec(), residual.to_vec()).map(Some);
+        }
+        rest = tail;
+    }
+    Ok(None)
+}
+
+fn residual_offsets(lengths: &[usize]) -> Vec<usize> {
+    lengths
+        .iter()
+        .scan(0, |offset, &len| {
+            let start = *offset;
+            *offset += len;
+            Some(start)
+        })
+        .collect()
+}
+
+/// Writes a block header with the given fields into `bytes`.
+pub(crate) fn write_header(bytes: &mut Vec<u8>, flags: u8, table_size: u64, num_values: usize, encoding_len: usize, residual_len: usize) {
+    bytes.extend_from_slice(&MAGIC);
+    bytes.push(FORMAT_VERSION);
+    bytes.push(flags);
+    bytes.extend_from_slice(&table_size.to_le_bytes());
+    for len in [num_values, encoding_len, residual_len] {
+        bytes.extend_from_slice(&(len as u64).to_le_bytes());
+    }
+}
+
+/// Reorders an interleaved residual into [`ResidualLayout::Transposed`]
+/// order. `lengths` holds the residual length of every value.
+fn transpose_residual(lengths: &[usize], residual: &[u8]) -> Vec<u8> {
+    let offsets = residual_offsets(lengths);
+    let mut transposed = Vec::with_capacity(residual.len());
+    for position in 0..8 {
+        for (&len, &start) in lengths.iter().zip(&offsets) {
+            if position >= 8 - len {
+                transposed.push(residual[start + position - (8 - len)]);
+            }
+        }
+    }
+    transposed
+}
+
+/// Inverse of [`transpose_residual`].
+fn untranspose_residual(lengths: &[usize], transposed: &[u8]) -> Vec<u8> {
+    let offsets = residual_offsets(lengths);
+    let mut residual = vec![0_u8; transposed.len()];
+    let mut next = transposed.iter();
+    for position in 0..8 {
+        for (&len, &start) in lengths.iter().zip(&offsets) {
+            if position >= 8 - len {
+                residual[start + position - (8 - len)] = *next.next().unwrap();
+            }
+        }
+    }
+    residual
+}
+
+/// Appends bit strings most significant bit first, padding the last byte
+/// with zeros.
+pub(crate) struct BitWriter {
+    bytes: Vec<u8>,
+    pending: u128,
+    pending_bits: u32,
+}
+
+impl BitWriter {
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        BitWriter { bytes: Vec::with_capacity(capacity), pending: 0, pending_bits: 0 }
+    }
+
+    /// Appends the low `bits` bits of `value`, at most 64.
+    pub(crate) fn write(&mut self, value: u64, bits: u32) {
+        self.pending = (self.pending << bits) | (value as u128 & ((1 << bits) - 1));
+        self.pending_bits += bits;
+        while self.pending_bits >= 8 {
+            self.pending_bits -= 8;
+            self.bytes.push((self.pending >> self.pending_bits) as u8);
+        }
+    }
+
+    pub(crate) fn finish(mut self) -> Vec<u8> {
+        if self.pending_bits > 0 {
+            let padding = 8 - self.pending_bits;
+            self.write(0, padding);
+        }
+        self.bytes
+    }
+}
+
+/// Reads the bit strings written by [`BitWriter`].
+pub(crate) struct BitReader<'a> {
+    bytes: &'a [u8],
+    bit: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        BitReader { bytes, bit: 0 }
+    }
+
+    pub(crate) fn read(&mut self, bits: u32) -> Option<u64> {
+        let mut value = 0;
+        for _ in 0..bits {
+            let byte = self.bytes.get(self.bit / 8)?;
+            value = (value << 1) | ((byte >> (7 - self.bit % 8)) & 1) as u64;
+            self.bit += 1;
+        }
+        Some(value)
+    }
+}
+
+/// Packs an interleaved residual into [`ResidualLayout::BitPacked`] form.
+/// `lengths` holds the residual length of every value.
+fn bit_pack_residual(lengths: &[usize], residual: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter::with_capacity(residual.len());
+    for (&len, start) in lengths.iter().zip(residual_offsets(lengths)) {
+        if len == 0 {
+            continue;
+        }
+        let bits = 8 * len as u32;
+        let value = residual[start..start + len].iter().fold(0_u64, |acc, &b| (acc << 8) | b as u64);
+        let zeros = (value.leading_zeros() - (64 - bits)).min(7);
+        writer.write(zeros as u64, 3);
+        if zeros < 7 {
+            // The bit below the zeros is 1 and need not be stored.
+            writer.write(value, bits - zeros - 1);
+        } else {
+            writer.write(value, bits - 7);
+        }
+    }
+    writer.finish()
+}
+
+/// Inverse of [`bit_pack_residual`].
+fn bit_unpack_residual(lengths: &[usize], packed: &[u8]) -> Result<Vec<u8>, FpcError> {
+    let mut reader = BitReader::new(packed);
+    let mut residual = Vec::with_capacity(lengths.iter().sum());
+    for (at_value, &len) in lengths.iter().enumerate() {
+        if len == 0 {
+            continue;
+        }
+        let bits = 8 * len as u32;
+        let truncated = FpcError::TruncatedResidual { at_value, residual_len: packed.len() };
+        let zeros = reader.read(3).ok_or(truncated.clone())? as u32;
+        let value = if zeros < 7 {
+            let below = bits - zeros - 1;
+            (1 << below) | reader.read(below).ok_or(truncated)?
+        } else {
+            reader.read(bits - 7).ok_or(truncated)?
+        };
+        residual.extend_from_slice(&value.to_be_bytes()[8 - len..]);
+    }
+    let expected = reader.bit.div_ceil(8);
+    if expected != packed.len() {
+        return Err(FpcError::ResidualLengthMismatch { expected, actual: packed.len() });
+    }
+    Ok(residual)
+}
+
+impl FPCCompressedBlock {
+    fn residual_lengths(&self) -> Vec<usize> {
+        self.codes().map(residual_len_of_code).collect()
+    }
+
+    /// Builds a block from a parsed header and its payload, undoing the
+    /// residual layout the block was written with, and validates it.
+    pub(crate) fn from_header_and_payload(
+        header: &Header,
+        params: BlockParams,
+        encoding: Vec<u8>,
+        residual: Vec<u8>,
+    ) -> Result<FPCCompressedBlock, FpcError> {
+        if header.raw {
+            return Err(FpcError::UnexpectedRawBlock);
+        }
+        let mut blk = FPCCompressedBlock {
+            table_size: header.table_size,
+            num_bytes_encoded: header.num_values,
+            mode: header.mode,
+            hash_params: params.hash_params,
+            dropped_mantissa_bits: params.dropped_mantissa_bits,
+            delta_of_delta: params.delta_of_delta,
+            seed: params.seed,
+            canonicalize_nan: params.canonicalize_nan,
+            encoding,
+            residual,
+        };
+        match header.residual_layout {
+            ResidualLayout::Interleaved => {}
+            ResidualLayout::Transposed => {
+                let lengths = blk.residual_lengths();
+                let expected: usize = lengths.iter().sum();
+                if expected != blk.residual.len() {
+                    return Err(FpcError::ResidualLengthMismatch { expected, actual: blk.residual.len() });
+                }
+                blk.residual = untranspose_residual(&lengths, &blk.residual);
+            }
+            ResidualLayout::BitPacked => {
+                blk.residual = bit_unpack_residual(&blk.residual_lengths(), &blk.residual)?;
+            }
+        }
+        blk.validate()?;
+        Ok(blk)
+    }
+
+    /// Serializes the block in the self-describing layout documented in
+    /// this module, with an interleaved residual.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_bytes_with_layout(ResidualLayout::Interleaved)
+    }
+
+    /// Serializes the block like [`FPCCompressedBlock::to_bytes`], writing
+    /// the residual in the given order. [`FPCCompressedBlock::from_bytes`]
+    /// reads either layout back. A constant block is always written
+    /// interleaved.
+    pub fn to_bytes_with_layout(&self, residual_layout: ResidualLayout) -> Vec<u8> {
+        // The value of a constant block is left as it is.
+        let residual_layout = if self.mode == EncodingMode::Constant { ResidualLayout::Interleaved } else { residual_layout };
+        let (flags, residual) = match residual_layout {
+            ResidualLayout::Interleaved => (0, None),
+            ResidualLayout::Transposed => {
+                (FLAG_TRANSPOSED_RESIDUAL, Some(transpose_residual(&self.residual_lengths(), &self.residual)))
+            }
+            ResidualLayout::BitPacked => {
+                (FLAG_BIT_PACKED_RESIDUAL, Some(bit_pack_residual(&self.residual_lengths(), &self.residual)))
+            }
+        };
+        let flags = match self.mode {
+            EncodingMode::Full | EncodingMode::Constant => flags,
+            EncodingMode::Compact => flags | FLAG_COMPACT_ENCODING,
+        };
+        let custom_hash_params = self.hash_params != HashParams::DEFAULT;
+        let flags = if custom_hash_params { flags | FLAG_HASH_PARAMS } else { flags };
+        let lossy = self.dropped_mantissa_bits != 0;
+        let flags = if lossy { flags | FLAG_LOSSY } else { flags };
+        let extension_flags = if self.delta_of_delta { EXTENSION_FLAG_DELTA_OF_DELTA } else { 0 }
+            | if self.canonicalize_nan { EXTENSION_FLAG_CANONICALIZE_NAN } else { 0 };
+        let extended = extension_flags != 0 || self.seed != 0;
+        let flags = if extended { flags | FLAG_EXTENSION } else { flags };
+        let residual = residual.as_deref().unwrap_or(&self.residual);
+        let params_len = HASH_PARAMS_LEN + DROPPED_BITS_LEN + EXTENSION_LEN;
+        let mut bytes = Vec::with_capacity(HEADER_LEN + params_len + self.encoding.len() + residual.len());
+        write_header(&mut bytes, flags, self.table_size, self.num_bytes_encoded, self.encoding.len(), residual.len());
+        if custom_hash_params {
+            let HashParams { fcm_shift, fcm_fold, dfcm_shift, dfcm_fold } = self.hash_params;
+            bytes.extend_from_slice(&[fcm_shift, fcm_fold, dfcm_shift, dfcm_fold]);
+        }
+        if lossy {
+            bytes.push(self.dropped_mantissa_bits);
+        }
+        if extended {
+            bytes.push(extension_flags);
+            bytes.extend_from_slice(&self.seed.to_le_bytes());
+        }
+        bytes.extend_from_slice(&self.encoding);
+        bytes.extend_from_slice(residual);
+        bytes
+    }
+
+    /// Serializes the block like [`FPCCompressedBlock::to_bytes`] followed
+    /// by a CRC32 of its encoding and residual, which every reader checks.
+    #[cfg(feature = "checksum")]
+    pub fn to_bytes_checked(&self) -> Vec<u8> {
+        let mut bytes = self.to_bytes();
+        append_checksum(&mut bytes);
+        bytes
+    }
+
+    /// Parses a block written by [`FPCCompressedBlock::to_bytes`],
+    /// [`FPCCompressedBlock::to_bytes_with_layout`] or, with the `checksum`
+    /// feature, `to_bytes_checked`. `bytes` must hold exactly one block.
+    /// Raw blocks are rejected; read those with [`StoredBlock::from_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<FPCCompressedBlock, ParseError> {
+        let (header, Payload { params, encoding, residual }) = split_block(bytes)?;
+        FPCCompressedBlock::from_header_and_payload(&header, params, encoding.to_vec(), residual.to_vec())
+    }
+}
+
+/// Parses the header of the single block in `bytes` and splits off its
+/// hash parameters, encoding and residual regions.
+pub(crate) fn split_block(bytes: &[u8]) -> Result<(Header, Payload<'_>), FpcError> {
+    let header = parse_header(bytes)?;
+    check_payload(&header, bytes)?;
+    if bytes.len() > header.block_len() {
+        return Err(FpcError::TrailingBytes { count: bytes.len() - header.block_len() });
+    }
+    let payload = split_payload(&header, &bytes[HEADER_LEN..])?;
+    Ok((header, payload))
+}
+
+impl StoredBlock {
+    /// Serializes the block in the same layout as
+    /// [`FPCCompressedBlock::to_bytes`], setting the raw flag for raw blocks.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            StoredBlock::Compressed(blk) => blk.to_bytes(),
+            StoredBlock::Raw { table_size, values } => {
+                let mut bytes = Vec::with_capacity(HEADER_LEN + size_of_val(values.as_slice()));
+                write_header(&mut bytes, FLAG_RAW, *table_size, values.len(), 0, size_of_val(values.as_slice()));
+                for value in values {
+                    bytes.extend_from_slice(&value.to_le_bytes());
+                }
+                bytes
+            }
+        }
+    }
+
+    /// Serializes the block like [`StoredBlock::to_bytes`] followed by a
+    /// CRC32 of its payload.
+    #[cfg(feature = "checksum")]
+    pub fn to_bytes_checked(&self) -> Vec<u8> {
+        let mut bytes = self.to_bytes();
+        append_checksum(&mut bytes);
+        bytes
+    }
+
+    /// Parses a raw or compressed block. `bytes` must hold exactly one block.
+    pub fn from_bytes(bytes: &[u8]) -> Result<StoredBlock, ParseError> {
+        let (header, Payload { params, encoding, residual }) = split_block(bytes)?;
+        StoredBlock::from_header_and_payload(&header, params, encoding.to_vec(), residual.to_vec())
+    }
+
+    pub(crate) fn from_header_and_payload(
+        header: &Header,
+        params: BlockParams,
+        encoding: Vec<u8>,
+        residual: Vec<u8>,
+    ) -> Result<StoredBlock, FpcError> {
+        if !header.raw {
+            return FPCCompressedBlock::from_header_and_payload(header, params, encoding, residual)
+                .map(StoredBlock::Compressed);
+        }
+        let values = residual
+            .chunks_exact(size_of::<f64>())
+            .map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        Ok(StoredBlock::Raw { table_size: header.table_size, values })
+    }
+}
+
+#[cfg(test)]
+mod format_test {
+    use quickcheck_macros::quickcheck;
+
+    use crate::encoding_mode::encoding_mode_test::noisy;
+    use crate::{compress, compress_compact, compress_lossy, compress_with, decompress, FpcOptions, DEFAULT_TABLE_SIZE};
+
+    use super::*;
+
+    #[quickcheck]
+    fn serialization_must_be_reversible(to_compress: Vec<f64>) -> bool {
+        let blk = compress(DEFAULT_TABLE_SIZE, &to_compress).unwrap();
+        FPCCompressedBlock::from_bytes(&blk.to_bytes()) == Ok(blk)
+    }
+
+    #[quickcheck]
+    fn transposed_serialization_must_be_reversible(to_compress: Vec<f64>) -> bool {
+        let blk = compress(DEFAULT_TABLE_SIZE, &to_compress).unwrap();
+        let bytes = blk.to_bytes_with_layout(ResidualLayout::Transposed);
+        bytes.len() == blk.to_bytes().len() && FPCCompressedBlock::from_bytes(&bytes) == Ok(blk)
+    }
+
+    #[quickcheck]
+    fn bit_packed_serialization_must_be_reversible(to_compress: Vec<f64>) -> bool {
+        let blk = compress(DEFAULT_TABLE_SIZE, &to_compress).unwrap();
+        FPCCompressedBlock::from_bytes(&blk.to_bytes_with_layout(ResidualLayout::BitPacked)) == Ok(blk)
+    }
+
+    #[quickcheck]
+    fn compact_serialization_must_be_reversible(to_compress: Vec<f64>) -> bool {
+        let blk = compress_compact(DEFAULT_TABLE_SIZE, &to_compress).unwrap();
+        [ResidualLayout::Interleaved, ResidualLayout::Transposed, ResidualLayout::BitPacked]
+            .into_iter()
+            .all(|layout| FPCCompressedBlock::from_bytes(&blk.to_bytes_with_layout(layout)).as_ref() == Ok(&blk))
+    }
+
+    #[test]
+    fn test_compact_flag_sets_the_encoding_length() {
+        let values = noisy(9);
+        let blk = compress_compact(DEFAULT_TABLE_SIZE, &values).unwrap();
+        assert_eq!(blk.mode(), EncodingMode::Compact);
+        let bytes = blk.to_bytes();
+        assert_eq!(bytes[FLAGS_RANGE.start], FLAG_COMPACT_ENCODING);
+        assert_eq!(bytes[ENCODING_LEN_RANGE], 4_u64.to_le_bytes());
+
+        let mut as_full = bytes.clone();
+        as_full[FLAGS_RANGE.start] = 0;
+        assert_eq!(layout(&as_full), Err(FpcError::InconsistentBlock { num_values: 9, encoding_len: 4 }));
+
+        let mut raw = StoredBlock::Raw { table_size: DEFAULT_TABLE_SIZE, values }.to_bytes();
+        raw[FLAGS_RANGE.start] |= FLAG_COMPACT_ENCODING;
+        assert_eq!(layout(&raw), Err(FpcError::UnsupportedFlags { flags: FLAG_RAW | FLAG_COMPACT_ENCODING }));
+    }
+
+    #[test]
+    fn test_hash_params_are_stored_only_when_custom() {
+        let vals: Vec<f64> = (0..50).map(|x| (x as f64).sqrt()).collect();
+        let default = compress(DEFAULT_TABLE_SIZE, &vals).unwrap().to_bytes();
+        assert_eq!(default[FLAGS_RANGE.start], 0);
+        assert_eq!(layout(&default).unwrap().hash_params, None);
+
+        let hash_params = HashParams { fcm_shift: 5, fcm_fold: 44, dfcm_shift: 3, dfcm_fold: 36 };
+        let options = FpcOptions { hash_params, ..FpcOptions::default() };
+        let blk = compress_with(DEFAULT_TABLE_SIZE, &vals, &options).unwrap();
+        let bytes = blk.to_bytes_with_layout(ResidualLayout::Transposed);
+        assert_eq!(bytes[FLAGS_RANGE.start], FLAG_TRANSPOSED_RESIDUAL | FLAG_HASH_PARAMS);
+        let block_layout = layout(&bytes).unwrap();
+        assert_eq!(block_layout.hash_params, Some(HEADER_LEN..HEADER_LEN + HASH_PARAMS_LEN));
+        assert_eq!(bytes[HEADER_LEN..HEADER_LEN + HASH_PARAMS_LEN], [5, 44, 3, 36]);
+        assert_eq!(block_layout.encoding.start, HEADER_LEN + HASH_PARAMS_LEN);
+        assert_eq!(FPCCompressedBlock::from_bytes(&bytes).as_ref(), Ok(&blk));
+        assert_eq!(get_block(&[default.clone(), bytes.clone()].concat(), 1), Ok(Some(StoredBlock::Compressed(blk))));
+
+        let mut bad_shift = bytes.clone();
+        bad_shift[HEADER_LEN + 1] = 64;
+        assert_eq!(
+            FPCCompressedBlock::from_bytes(&bad_shift),
+            Err(FpcError::InvalidHashParams { hash_params: HashParams { fcm_fold: 64, ..hash_params } }),
+        );
+    }
+
+    #[test]
+    fn test_lossy_blocks_record_the_dropped_bits() {
+        let vals: Vec<f64> = (0..50).map(|x| (x as f64).sqrt()).collect();
+        let bytes = compress_lossy(DEFAULT_TABLE_SIZE, &vals, 30).unwrap().to_bytes();
+        assert_eq!(bytes[FLAGS_RANGE.start], FLAG_LOSSY);
+        assert_eq!(layout(&bytes).unwrap().dropped_mantissa_bits, Some(HEADER_LEN..HEADER_LEN + DROPPED_BITS_LEN));
+        assert_eq!(bytes[HEADER_LEN], 22);
+
+        let mut bad_count = bytes.clone();
+        bad_count[HEADER_LEN] = 53;
+        assert_eq!(FPCCompressedBlock::from_bytes(&bad_count), Err(FpcError::InvalidDroppedBits { given: 53 }));
+    }
+
+    #[test]
+    fn test_bit_packing_drops_leading_zero_bits() {
+        // 0x01 and 0x0123 start with 7 or more zero bits, so the bit after
+        // them is stored. 0x40 and 0xff have fewer, and the 1 after their
+        // zeros is left out.
+        let lengths = [1, 2, 0, 1, 1];
+        let residual = [0x01, 0x01, 0x23, 0x40, 0xff];
+        let packed = bit_pack_residual(&lengths, &residual);
+        // 111 1 | 111 100100011 | 001 000000 | 000 1111111 | padding
+        assert_eq!(packed, [0b1111_1111, 0b0010_0011, 0b0010_0000, 0b0000_1111, 0b1110_0000]);
+        assert_eq!(bit_unpack_residual(&lengths, &packed), Ok(residual.to_vec()));
+        assert_eq!(
+            bit_unpack_residual(&lengths, &packed[..4]),
+            Err(FpcError::TruncatedResidual { at_value: 4, residual_len: 4 })
+        );
+        assert_eq!(
+            bit_unpack_residual(&lengths, &[packed.as_slice(), &[0]].concat()),
+            Err(FpcError::ResidualLengthMismatch { expected: 5, actual: 6 })
+        );
+    }
+
+    #[test]
+    fn test_bit_packing_shrinks_sub_byte_differences() {
+        // Differences from the predictions land at every bit position of
+        // their top byte alike.
+        let vals: Vec<f64> = (0..1000).map(|x| f64::from_bits(0x4000_0000_0000_0000 | (x * 0x1357_9bdf) >> (x % 40))).collect();
+        let blk = compress(DEFAULT_TABLE_SIZE, &vals).unwrap();
+        let packed = blk.to_bytes_with_layout(ResidualLayout::BitPacked);
+        assert!(packed.len() < blk.to_bytes().len(), "{} >= {}", packed.len(), blk.to_bytes().len());
+        assert_eq!(decompress(DEFAULT_TABLE_SIZE, &FPCCompressedBlock::from_bytes(&packed).unwrap()), vals);
+    }
+
+    #[test]
+    fn test_transposed_residual_groups_bytes_by_significance() {
+        // Every value stores 2 residual bytes, so all the high bytes come
+        // first, followed by all the low bytes.
+        let lengths = [2, 2, 2];
+        let residual = [0xa1, 0xb1, 0xa2, 0xb2, 0xa3, 0xb3];
+        let transposed = transpose_residual(&lengths, &residual);
+        assert_eq!(transposed, [0xa1, 0xa2, 0xa3, 0xb1, 0xb2, 0xb3]);
+        assert_eq!(untranspose_residual(&lengths, &transposed), residual);
+
+        // Shorter residuals only hold the less significant positions.
+        let lengths = [3, 1, 2];
+        let residual = [0xc1, 0xc2, 0xc3, 0xd3, 0xe2, 0xe3];
+        let transposed = transpose_residual(&lengths, &residual);
+        assert_eq!(transposed, [0xc1, 0xc2, 0xe2, 0xc3, 0xd3, 0xe3]);
+        assert_eq!(untranspose_residual(&lengths, &transposed), residual);
+    }
+
+    #[test]
+    fn test_transposed_residual_deflates_better_for_smooth_data() {
+        use std::io::Write;
+
+        use flate2::write::DeflateEncoder;
+        use flate2::Compression;
+
+        fn deflated_len(bytes: &[u8]) -> usize {
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(bytes).unwrap();
+            encoder.finish().unwrap().len()
+        }
+
+        let vals: Vec<f64> = (0..10_000).map(|x| 20.0 + (x as f64 * 0.001).sin()).collect();
+        let blk = compress(DEFAULT_TABLE_SIZE, &vals).unwrap();
+        let interleaved = deflated_len(&blk.to_bytes());
+        let transposed = deflated_len(&blk.to_bytes_with_layout(ResidualLayout::Transposed));
+        assert!(transposed < interleaved, "transposed {transposed} >= interleaved {interleaved}");
+    }
+
+    #[test]
+    fn test_layout_bounds_every_region_of_a_serialized_block() {
+        let vals: Vec<f64> = vec![1.0, 2.0, 2.0, -7.25, 3.0];
+        let blk = compress(256, &vals).unwrap();
+        let bytes = blk.to_bytes();
+        let layout = layout(&bytes).unwrap();
+
+        assert_eq!(&bytes[layout.magic.clone()], b"FPC1");
+        assert_eq!(bytes[layout.version.clone()], [2]);
+        assert_eq!(bytes[layout.flags.clone()], [0]);
+        assert_eq!(bytes[layout.table_size.clone()], 256_u64.to_le_bytes());
+        assert_eq!(bytes[layout.count.clone()], 5_u64.to_le_bytes());
+        assert_eq!(bytes[layout.encoding_len.clone()], (blk.encoding.len() as u64).to_le_bytes());
+        assert_eq!(bytes[layout.residual_len.clone()], (blk.residual.len() as u64).to_le_bytes());
+        assert_eq!(bytes[layout.encoding.clone()], blk.encoding[..]);
+        assert_eq!(bytes[layout.residual.clone()], blk.residual[..]);
+        assert_eq!(layout.residual.end, bytes.len());
+        assert_eq!(layout.checksum, None);
+        let parsed = FPCCompressedBlock::from_bytes(&bytes).unwrap();
+        assert_eq!(decompress(256, &parsed), vals);
+    }
+
+    #[test]
+    fn test_layout_rejects_malformed_headers() {
+        let bytes = compress(DEFAULT_TABLE_SIZE, &[1.0, 2.0, 3.0]).unwrap().to_bytes();
+        assert_eq!(layout(&bytes[..10]), Err(FpcError::TruncatedHeader { len: 10 }));
+        assert_eq!(
+            layout(&bytes[..bytes.len() - 1]),
+            Err(FpcError::TruncatedPayload { expected: bytes.len(), available: bytes.len() - 1 }),
+        );
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert_eq!(layout(&bad_magic), Err(FpcError::BadMagic));
+
+        let mut bad_version = bytes.clone();
+        bad_version[VERSION_RANGE.start] = 9;
+        assert_eq!(layout(&bad_version), Err(FpcError::UnsupportedVersion { version: 9 }));
+
+        let mut bad_table_size = bytes.clone();
+        bad_table_size[TABLE_SIZE_RANGE].copy_from_slice(&3_u64.to_le_bytes());
+        assert_eq!(layout(&bad_table_size), Err(FpcError::InvalidTableSize { given: 3 }));
+        bad_table_size[TABLE_SIZE_RANGE].copy_from_slice(&(1_u64 << 40).to_le_bytes());
+        assert_eq!(FPCCompressedBlock::from_bytes(&bad_table_size), Err(FpcError::InvalidTableSize { given: 1 << 40 }));
+
+        let mut bad_flags = bytes.clone();
+        bad_flags[FLAGS_RANGE.start] = FLAG_RAW | FLAG_EXTENSION;
+        assert_eq!(layout(&bad_flags), Err(FpcError::UnsupportedFlags { flags: FLAG_RAW | FLAG_EXTENSION }));
+        bad_flags[FLAGS_RANGE.start] = LAYOUT_FLAGS;
+        assert_eq!(layout(&bad_flags), Err(FpcError::UnsupportedFlags { flags: LAYOUT_FLAGS }));
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(layout(&trailing).is_ok());
+        assert_eq!(FPCCompressedBlock::from_bytes(&trailing), Err(FpcError::TrailingBytes { count: 1 }));
+    }
+
+    #[test]
+    fn test_seeded_blocks_store_an_extension() {
+        let plain = compress(DEFAULT_TABLE_SIZE, &[1.0, 2.0]).unwrap().to_bytes();
+        assert_eq!(layout(&plain).unwrap().extension, None);
+        let seeded = crate::compress_with_seed(DEFAULT_TABLE_SIZE, &[1.0, 2.0], 0.5).unwrap();
+        let mut bytes = seeded.to_bytes();
+        let extension = layout(&bytes).unwrap().extension.unwrap();
+        assert_eq!(bytes[extension.start], 0);
+        assert_eq!(bytes[extension.start + 1..extension.end], 0.5_f64.to_bits().to_le_bytes());
+        assert_eq!(FPCCompressedBlock::from_bytes(&bytes), Ok(seeded));
+        bytes[extension.start] = 0x82;
+        assert_eq!(FPCCompressedBlock::from_bytes(&bytes), Err(FpcError::UnsupportedFlags { flags: 0x82 }));
+    }
+
+    #[test]
+    fn test_constant_blocks_are_stored_as_their_value() {
+        let blk = compress(DEFAULT_TABLE_SIZE, &[-2.5; 40]).unwrap();
+        let bytes = blk.to_bytes_with_layout(ResidualLayout::BitPacked);
+        assert_eq!(bytes, blk.to_bytes());
+        let layout = layout(&bytes).unwrap();
+        assert!(layout.encoding.is_empty());
+        assert_eq!(bytes[layout.residual], (-2.5_f64).to_bits().to_be_bytes());
+        assert_eq!(FPCCompressedBlock::from_bytes(&bytes), Ok(blk));
+        let mut transposed = bytes.clone();
+        transposed[FLAGS_RANGE.start] |= FLAG_TRANSPOSED_RESIDUAL;
+        assert_eq!(FPCCompressedBlock::from_bytes(&transposed), Err(FpcError::UnsupportedFlags { flags: 1 }));
+        let mut forged_count = bytes.clone();
+        forged_count[COUNT_RANGE].copy_from_slice(&(1_u64 << 40).to_le_bytes());
+        assert_eq!(
+            FPCCompressedBlock::from_bytes(&forged_count),
+            Err(FpcError::InconsistentBlock { num_values: 1 << 40, encoding_len: 0 })
+        );
+        let mut long = bytes;
+        long[RESIDUAL_LEN_RANGE.start] = 9;
+        long.push(0);
+        assert_eq!(
+            FPCCompressedBlock::from_bytes(&long),
+            Err(FpcError::ResidualLengthMismatch { expected: 8, actual: 9 })
+        );
+    }
+
+    #[test]
+    fn test_from_bytes_rejects_lengths_past_the_slice() {
+        let bytes = compress(DEFAULT_TABLE_SIZE, &[1.0, 2.0, 3.0]).unwrap().to_bytes();
+        assert_eq!(bytes[MAGIC_RANGE], *b"FPC1");
+        for residual_len in [bytes.len() as u64, u64::MAX - HEADER_LEN as u64, u64::MAX] {
+            let mut oversized = bytes.clone();
+            oversized[RESIDUAL_LEN_RANGE].copy_from_slice(&residual_len.to_le_bytes());
+            assert!(matches!(FPCCompressedBlock::from_bytes(&oversized), Err(ParseError::TruncatedPayload { .. })));
+        }
+    }
+
+    #[test]
+    fn test_get_block_skips_to_the_requested_block() {
+        let first = compress(32, &[1.0, 2.0, 3.0]).unwrap();
+        let second = StoredBlock::Raw { table_size: 64, values: vec![4.0, 5.0] };
+        let bytes = [first.to_bytes(), second.to_bytes()].concat();
+        assert_eq!(get_block(&bytes, 0), Ok(Some(StoredBlock::Compressed(first))));
+        assert_eq!(get_block(&bytes, 1), Ok(Some(second)));
+        assert_eq!(get_block(&bytes, 2), Ok(None));
+        assert_eq!(peek_num_values(&bytes), Ok(3));
+    }
+
+    #[cfg(feature = "checksum")]
+    #[test]
+    fn test_checksum_detects_a_flipped_residual_byte() {
+        let blk = compress(DEFAULT_TABLE_SIZE, &[1.0, 2.5, -3.75, 1e300]).unwrap();
+        let bytes = blk.to_bytes_checked();
+        assert_eq!(bytes.len(), blk.to_bytes().len() + CHECKSUM_LEN);
+        assert_eq!(layout(&bytes).unwrap().checksum, Some(bytes.len() - CHECKSUM_LEN..bytes.len()));
+        assert_eq!(FPCCompressedBlock::from_bytes(&bytes).as_ref(), Ok(&blk));
+        let raw = StoredBlock::Raw { table_size: 32, values: vec![4.0, 5.0] };
+        assert_eq!(StoredBlock::from_bytes(&raw.to_bytes_checked()), Ok(raw));
+
+        let mut corrupted = bytes.clone();
+        corrupted[layout(&bytes).unwrap().residual.start] ^= 0x10;
+        let expected = crc32fast::hash(&bytes[HEADER_LEN..bytes.len() - CHECKSUM_LEN]);
+        let actual = crc32fast::hash(&corrupted[HEADER_LEN..bytes.len() - CHECKSUM_LEN]);
+        let err = FpcError::ChecksumMismatch { expected, actual };
+        assert_eq!(FPCCompressedBlock::from_bytes(&corrupted), Err(err.clone()));
+        assert_eq!(get_block(&corrupted, 0), Err(err.clone()));
+        #[cfg(feature = "std")]
+        assert_eq!(FPCCompressedBlock::from_reader(&mut corrupted.as_slice()), Err(err));
+    }
+
+    #[cfg(not(feature = "checksum"))]
+    #[test]
+    fn test_checksum_flag_needs_the_feature() {
+        let mut bytes = compress(DEFAULT_TABLE_SIZE, &[1.0, 2.0]).unwrap().to_bytes();
+        bytes[FLAGS_RANGE.start] |= FLAG_CHECKSUM;
+        bytes.extend_from_slice(&[0; CHECKSUM_LEN]);
+        assert_eq!(FPCCompressedBlock::from_bytes(&bytes), Err(FpcError::UnsupportedFlags { flags: FLAG_CHECKSUM }));
+    }
+
+    #[test]
+    fn test_every_entry_point_reports_truncated_headers() {
+        let bytes = compress(DEFAULT_TABLE_SIZE, &[1.0, 2.0, 3.0]).unwrap().to_bytes();
+        for len in [1, HEADER_LEN / 2] {
+            let truncated = &bytes[..len];
+            let expected = FpcError::TruncatedHeader { len };
+            assert_eq!(FPCCompressedBlock::from_bytes(truncated), Err(expected.clone()));
+            assert_eq!(StoredBlock::from_bytes(truncated), Err(expected.clone()));
+            #[cfg(feature = "std")]
+            assert_eq!(FPCCompressedBlock::from_reader(&mut &truncated[..]), Err(expected.clone()));
+            assert_eq!(peek_num_values(truncated), Err(expected.clone()));
+            assert_eq!(layout(truncated), Err(expected.clone()));
+            assert_eq!(get_block(truncated, 0), Err(expected.clone()));
+            // A truncated header after a complete block is reported too.
+            let mut second = bytes.clone();
+            second.extend_from_slice(truncated);
+            assert_eq!(get_block(&second, 1), Err(expected));
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use std::io::{self, ErrorKind, Read, Write};
+
+use crate::stream::read_up_to;
+use crate::{FPCCompressedBlock, FpcError};
+
+const LEN_PREFIX: usize = 4;
+
+/// Writes `blk` as a self-delimiting frame: its length in bytes as a
+/// little-endian `u32`, followed by [`FPCCompressedBlock::to_bytes`].
+/// Fails with [`FpcError::FrameTooLarge`] if the block does not fit in a
+/// frame.
+pub fn write_frame<W: Write>(w: &mut W, blk: &FPCCompressedBlock) -> Result<(), FpcError> {
+    let bytes = blk.to_bytes();
+    let len = u32::try_from(bytes.len()).map_err(|_| FpcError::FrameTooLarge { len: bytes.len() })?;
+    w.write_all(&len.to_le_bytes())?;
+    w.write_all(&bytes)?;
+    Ok(())
+}
+
+/// Reads the next frame written by [`write_frame`], waiting for partial
+/// reads to complete. Returns `Ok(None)` if the reader is exhausted
+/// between frames; running out of bytes within a frame fails with
+/// [`FpcError::Io`] of kind [`ErrorKind::UnexpectedEof`].
+pub fn read_frame<R: Read>(r: &mut R) -> Result<Option<FPCCompressedBlock>, FpcError> {
+    let mut len = [0_u8; LEN_PREFIX];
+    match read_up_to(r, &mut len)? {
+        0 => return Ok(None),
+        LEN_PREFIX => {}
+        _ => return Err(io::Error::from(ErrorKind::UnexpectedEof).into()),
+    }
+    let len = u32::from_le_bytes(len) as usize;
+    // As in from_reader, the buffer grows as bytes arrive instead of
+    // trusting the declared length.
+    let mut bytes = Vec::new();
+    r.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() < len {
+        return Err(io::Error::from(ErrorKind::UnexpectedEof).into());
+    }
+    FPCCompressedBlock::from_bytes(&bytes).map(Some)
+}
+
+#[cfg(test)]
+mod frame_test {
+    use std::thread;
+
+    use crate::{compress, DEFAULT_TABLE_SIZE};
+
+    use super::*;
+
+    /// Hands out at most one byte per read, like a slow socket.
+    struct Trickle<R>(R);
+
+    impl<R: Read> Read for Trickle<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(1);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    fn blocks() -> Vec<FPCCompressedBlock> {
+        (0..5_usize)
+            .map(|i| compress(DEFAULT_TABLE_SIZE, &(0..i * 100).map(|x| (x as f64).sqrt()).collect::<Vec<_>>()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_frames_round_trip_through_a_pipe() {
+        let (mut reader, mut writer) = io::pipe().unwrap();
+        let sender = thread::spawn(move || {
+            for blk in blocks() {
+                write_frame(&mut writer, &blk).unwrap();
+            }
+        });
+        let mut reader = Trickle(&mut reader);
+        let mut received = Vec::new();
+        while let Some(blk) = read_frame(&mut reader).unwrap() {
+            received.push(blk);
+        }
+        sender.join().unwrap();
+        assert_eq!(received, blocks());
+    }
+
+    #[test]
+    fn test_read_frame_rejects_truncated_frames() {
+        let mut bytes = Vec::new();
+        write_frame(&mut bytes, &blocks()[1]).unwrap();
+        assert_eq!(u32::from_le_bytes(bytes[..4].try_into().unwrap()) as usize, bytes.len() - LEN_PREFIX);
+        for len in [2, bytes.len() - 1] {
+            let result = read_frame(&mut &bytes[..len]);
+            assert!(matches!(result, Err(FpcError::Io { kind: ErrorKind::UnexpectedEof, .. })), "{len}: {result:?}");
+        }
+        bytes[0] += 1;
+        bytes.push(0);
+        assert_eq!(read_frame(&mut bytes.as_slice()), Err(FpcError::TrailingBytes { count: 1 }));
+    }
+}
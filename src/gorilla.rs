@@ -0,0 +1,207 @@
+use alloc::vec::Vec;
+
+use crate::format::{BitReader, BitWriter};
+use crate::{compress, try_decompress, validate_table_size, CompressError, DecompressError, FPCCompressedBlock, FpcError};
+
+/// Value compression scheme for [`compress_scheme`], to compare FPC with
+/// the XOR scheme of Facebook's Gorilla on the same data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Scheme {
+    /// The FPC predictors of the rest of this crate.
+    #[default]
+    Fpc,
+    /// XOR with the previous value, see [`GorillaBlock`].
+    Gorilla,
+}
+
+/// Values compressed with Gorilla's XOR scheme. The first value is stored
+/// in full. Every later value is XORed with the one before it, and the
+/// result written as a single 0 bit if it is zero. Otherwise a 1 bit is
+/// followed by either a 0 bit and the XOR's bits within the previous
+/// window of meaningful bits, if they fit, or by a 1 bit, 5 bits of
+/// leading zeros, 6 bits of the meaningful length minus one and the
+/// meaningful bits themselves, which become the new window.
+///
+/// The block is in-memory only and shares nothing with the FPC format.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GorillaBlock {
+    num_values: usize,
+    bits: Vec<u8>,
+}
+
+impl GorillaBlock {
+    /// Number of values in the block.
+    pub fn num_values(&self) -> usize {
+        self.num_values
+    }
+
+    /// The bit stream, padded with zeros to whole bytes.
+    pub fn bits(&self) -> &[u8] {
+        &self.bits
+    }
+}
+
+/// A block compressed with either [`Scheme`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SchemeBlock {
+    Fpc(FPCCompressedBlock),
+    Gorilla(GorillaBlock),
+}
+
+impl SchemeBlock {
+    /// Number of values in the block.
+    pub fn num_values(&self) -> usize {
+        match self {
+            SchemeBlock::Fpc(blk) => blk.num_values(),
+            SchemeBlock::Gorilla(blk) => blk.num_values,
+        }
+    }
+
+    /// Number of bytes the values were compressed into.
+    pub fn compressed_len(&self) -> usize {
+        match self {
+            SchemeBlock::Fpc(blk) => blk.compressed_len(),
+            SchemeBlock::Gorilla(blk) => blk.bits.len(),
+        }
+    }
+}
+
+/// Compresses `values` with `scheme`. Gorilla has no tables, but
+/// `table_size` is checked for it too so that switching schemes does not
+/// change which calls fail.
+pub fn compress_scheme(scheme: Scheme, table_size: u64, values: &[f64]) -> Result<SchemeBlock, CompressError> {
+    match scheme {
+        Scheme::Fpc => compress(table_size, values).map(SchemeBlock::Fpc),
+        Scheme::Gorilla => {
+            validate_table_size(table_size)?;
+            Ok(SchemeBlock::Gorilla(compress_gorilla(values)))
+        }
+    }
+}
+
+/// Decompresses a block written by [`compress_scheme`] with the same
+/// `table_size`. Fails like [`try_decompress`] on malformed blocks.
+pub fn decompress_scheme(table_size: u64, blk: &SchemeBlock) -> Result<Vec<f64>, DecompressError> {
+    match blk {
+        SchemeBlock::Fpc(blk) => try_decompress(table_size, blk),
+        SchemeBlock::Gorilla(blk) => decompress_gorilla(blk),
+    }
+}
+
+// The leading zero count is stored in 5 bits.
+const MAX_LEADING_ZEROS: u32 = 31;
+
+/// Compresses `values` with Gorilla's XOR scheme, described on
+/// [`GorillaBlock`].
+pub fn compress_gorilla(values: &[f64]) -> GorillaBlock {
+    let mut writer = BitWriter::with_capacity(size_of_val(values) / 4);
+    let mut previous = 0_u64;
+    // The window of meaningful bits as (leading zeros, length), None
+    // until the first non-zero XOR.
+    let mut window: Option<(u32, u32)> = None;
+    for (index, value) in values.iter().enumerate() {
+        let bits = value.to_bits();
+        if index == 0 {
+            writer.write(bits, 64);
+            previous = bits;
+            continue;
+        }
+        let xor = bits ^ previous;
+        previous = bits;
+        if xor == 0 {
+            writer.write(0, 1);
+            continue;
+        }
+        writer.write(1, 1);
+        let leading = xor.leading_zeros().min(MAX_LEADING_ZEROS);
+        let trailing = xor.trailing_zeros();
+        match window {
+            Some((window_leading, window_len))
+                if leading >= window_leading && trailing >= 64 - window_leading - window_len =>
+            {
+                writer.write(0, 1);
+                writer.write(xor >> (64 - window_leading - window_len), window_len);
+            }
+            _ => {
+                let len = 64 - leading - trailing;
+                writer.write(1, 1);
+                writer.write(leading as u64, 5);
+                writer.write(len as u64 - 1, 6);
+                writer.write(xor >> trailing, len);
+                window = Some((leading, len));
+            }
+        }
+    }
+    GorillaBlock { num_values: values.len(), bits: writer.finish() }
+}
+
+/// Decompresses a block written by [`compress_gorilla`]. Fails with
+/// [`FpcError::TruncatedResidual`] if the bit stream ends early.
+pub fn decompress_gorilla(blk: &GorillaBlock) -> Result<Vec<f64>, DecompressError> {
+    let mut reader = BitReader::new(&blk.bits);
+    let mut values = Vec::with_capacity(blk.num_values);
+    let mut previous = 0_u64;
+    let mut window = (0, 0);
+    for at_value in 0..blk.num_values {
+        let truncated = || FpcError::TruncatedResidual { at_value, residual_len: blk.bits.len() };
+        let mut read = |bits| reader.read(bits).ok_or_else(truncated);
+        let bits = if at_value == 0 {
+            read(64)?
+        } else if read(1)? == 0 {
+            previous
+        } else {
+            if read(1)? == 1 {
+                let leading = read(5)? as u32;
+                let len = read(6)? as u32 + 1;
+                if leading + len > 64 {
+                    return Err(truncated());
+                }
+                window = (leading, len);
+            }
+            let (leading, len) = window;
+            previous ^ (read(len)? << (64 - leading - len))
+        };
+        values.push(f64::from_bits(bits));
+        previous = bits;
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod gorilla_test {
+    use quickcheck_macros::quickcheck;
+
+    use crate::{bitwise_eq, DEFAULT_TABLE_SIZE};
+
+    use super::*;
+
+    #[quickcheck]
+    fn gorilla_must_round_trip(to_compress: Vec<f64>) -> bool {
+        let blk = compress_gorilla(&to_compress);
+        blk.num_values() == to_compress.len() && bitwise_eq(&decompress_gorilla(&blk).unwrap(), &to_compress)
+    }
+
+    #[quickcheck]
+    fn schemes_must_round_trip(to_compress: Vec<f64>) -> bool {
+        [Scheme::Fpc, Scheme::Gorilla].into_iter().all(|scheme| {
+            let blk = compress_scheme(scheme, DEFAULT_TABLE_SIZE, &to_compress).unwrap();
+            bitwise_eq(&decompress_scheme(DEFAULT_TABLE_SIZE, &blk).unwrap(), &to_compress)
+        })
+    }
+
+    #[test]
+    fn test_gorilla_encodes_repeats_in_one_bit() {
+        let blk = compress_gorilla(&[2.5; 17]);
+        assert_eq!(blk.bits(), [2.5_f64.to_bits().to_be_bytes().as_slice(), &[0, 0]].concat());
+        assert!(compress_gorilla(&[]).bits().is_empty());
+    }
+
+    #[test]
+    fn test_gorilla_reports_truncation_and_bad_table_sizes() {
+        let values: Vec<f64> = (0..100).map(|x| (x as f64 * 0.1).sin()).collect();
+        let mut blk = compress_gorilla(&values);
+        blk.bits.truncate(blk.bits.len() / 2);
+        assert!(matches!(decompress_gorilla(&blk), Err(FpcError::TruncatedResidual { .. })));
+        assert_eq!(compress_scheme(Scheme::Gorilla, 3, &values), Err(FpcError::InvalidTableSize { given: 3 }));
+    }
+}
@@ -0,0 +1,247 @@
+use alloc::vec::Vec;
+use core::iter::FusedIterator;
+
+use crate::delta::DeltaOfDelta;
+use crate::{
+    decode_value, validate_table_size, DecompressError, EncodingMode, FPCCompressedBlock, FpcError, Predictors,
+};
+
+/// Lazily decodes the values of a block, borrowing its encoding and
+/// residual instead of decompressing into a vector. Created by
+/// [`FPCCompressedBlock::iter`].
+///
+/// Yields an error instead of panicking if the block turns out to be
+/// malformed, and yields nothing further after that.
+pub struct FpcValueIter<'a> {
+    predictors: Predictors,
+    delta_of_delta: Option<DeltaOfDelta>,
+    num_values: usize,
+    mode: EncodingMode,
+    encoding: &'a [u8],
+    residual: &'a [u8],
+    index: usize,
+    residual_index: usize,
+    failed: bool,
+}
+
+/// The incremental counterpart of [`Compressor`](crate::Compressor),
+/// decoding one value per call to `next`.
+pub type Decompressor<'a> = FpcValueIter<'a>;
+
+impl FPCCompressedBlock {
+    /// Lazily decodes the values of the block. Fails with
+    /// [`FpcError::InvalidTableSize`] or [`FpcError::TableSizeMismatch`]
+    /// if `table_size` is not the one the block was compressed with;
+    /// a malformed block shows up as an error from the iterator.
+    pub fn iter(&self, table_size: u64) -> Result<FpcValueIter<'_>, DecompressError> {
+        validate_table_size(table_size)?;
+        self.check_table_size(table_size)?;
+        Ok(self.iter_from(self.predictors(table_size)))
+    }
+
+    /// Decodes starting from an already primed predictor state.
+    pub(crate) fn iter_from(&self, predictors: Predictors) -> FpcValueIter<'_> {
+        FpcValueIter {
+            predictors,
+            delta_of_delta: self.delta_of_delta.then(DeltaOfDelta::default),
+            num_values: self.num_bytes_encoded,
+            mode: self.mode,
+            encoding: &self.encoding,
+            residual: &self.residual,
+            index: 0,
+            residual_index: 0,
+            failed: false,
+        }
+    }
+}
+
+/// A compressed block borrowed from memory someone else owns, such as a
+/// memory-mapped file or a network buffer, made of the parts returned by
+/// [`FPCCompressedBlock::into_parts`]. It describes a block compressed
+/// with the default [`FpcOptions`](crate::FpcOptions), whose encoding holds
+/// one nibble per value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressedBlockRef<'a> {
+    pub num_bytes_encoded: usize,
+    pub encoding: &'a [u8],
+    pub residual: &'a [u8],
+}
+
+impl<'a> CompressedBlockRef<'a> {
+    /// Lazily decodes the values straight from the borrowed slices. As
+    /// the view does not record a table size, `table_size` must be the one
+    /// the block was compressed with.
+    pub fn iter(&self, table_size: u64) -> Result<FpcValueIter<'a>, DecompressError> {
+        validate_table_size(table_size)?;
+        if self.encoding.len() != EncodingMode::Full.encoding_len(self.num_bytes_encoded) {
+            return Err(FpcError::InconsistentBlock {
+                num_values: self.num_bytes_encoded,
+                encoding_len: self.encoding.len(),
+            });
+        }
+        Ok(FpcValueIter {
+            predictors: Predictors::new(table_size),
+            delta_of_delta: None,
+            num_values: self.num_bytes_encoded,
+            mode: EncodingMode::Full,
+            encoding: self.encoding,
+            residual: self.residual,
+            index: 0,
+            residual_index: 0,
+            failed: false,
+        })
+    }
+}
+
+/// Decompresses a borrowed block without copying its encoding or residual.
+/// Fails like [`try_decompress`](crate::try_decompress) on an invalid
+/// table size or a malformed block.
+pub fn decompress_ref(table_size: u64, blk_ref: &CompressedBlockRef<'_>) -> Result<Vec<f64>, DecompressError> {
+    blk_ref.iter(table_size)?.collect()
+}
+
+impl Iterator for FpcValueIter<'_> {
+    type Item = Result<f64, DecompressError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.index >= self.num_values {
+            return None;
+        }
+        if self.mode == EncodingMode::Constant {
+            let Ok(bytes) = self.residual.try_into() else {
+                self.failed = true;
+                return Some(Err(FpcError::ResidualLengthMismatch {
+                    expected: size_of::<u64>(),
+                    actual: self.residual.len(),
+                }));
+            };
+            self.index += 1;
+            return Some(Ok(f64::from_bits(u64::from_be_bytes(bytes))));
+        }
+        let Some(code) = self.mode.code_at(self.encoding, self.index) else {
+            self.failed = true;
+            return Some(Err(FpcError::InconsistentBlock {
+                num_values: self.num_values,
+                encoding_len: self.encoding.len(),
+            }));
+        };
+        match decode_value(&mut self.predictors, code, self.residual, &mut self.residual_index) {
+            Some(bits) => {
+                self.index += 1;
+                let bits = match &mut self.delta_of_delta {
+                    Some(stage) => stage.undo(bits),
+                    None => bits,
+                };
+                Some(Ok(f64::from_bits(bits)))
+            }
+            None => {
+                self.failed = true;
+                Some(Err(FpcError::TruncatedResidual { at_value: self.index, residual_len: self.residual.len() }))
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.failed {
+            (0, Some(0))
+        } else {
+            (0, Some(self.num_values - self.index))
+        }
+    }
+}
+
+impl FusedIterator for FpcValueIter<'_> {}
+
+#[cfg(test)]
+mod iter_test {
+    use quickcheck_macros::quickcheck;
+
+    use crate::{compress, decompress, DEFAULT_TABLE_SIZE};
+
+    use super::*;
+
+    #[quickcheck]
+    fn iterating_must_match_decompress(to_compress: Vec<f64>) -> bool {
+        let blk = compress(DEFAULT_TABLE_SIZE, &to_compress).unwrap();
+        let iterated: Vec<f64> = blk.iter(DEFAULT_TABLE_SIZE).unwrap().collect::<Result<_, _>>().unwrap();
+        let decompressed = decompress(DEFAULT_TABLE_SIZE, &blk);
+        iterated.len() == decompressed.len()
+            && iterated.iter().zip(&decompressed).all(|(x, y)| x.to_bits() == y.to_bits())
+    }
+
+    #[test]
+    fn test_iter_borrows_block_slices() {
+        let blk = compress(DEFAULT_TABLE_SIZE, &[1.0, 2.0, 3.0]).unwrap();
+        let iter = blk.iter(DEFAULT_TABLE_SIZE).unwrap();
+        assert!(std::ptr::eq(iter.encoding, blk.encoding.as_slice()));
+        assert!(std::ptr::eq(iter.residual, blk.residual.as_slice()));
+    }
+
+    #[test]
+    fn test_iter_rejects_other_table_sizes() {
+        let blk = compress(32, &[1.0, 2.5, 3.0]).unwrap();
+        assert_eq!(blk.iter(64).err(), Some(FpcError::TableSizeMismatch { block: 32, given: 64 }));
+        assert_eq!(blk.iter(0).err(), Some(FpcError::InvalidTableSize { given: 0 }));
+    }
+
+    #[test]
+    fn test_iter_reports_truncated_residual_and_fuses() {
+        let mut blk = compress(DEFAULT_TABLE_SIZE, &[1.0, 2.5, 3.0]).unwrap();
+        blk.residual.truncate(10);
+        let mut iter = blk.iter(DEFAULT_TABLE_SIZE).unwrap();
+        assert_eq!(iter.next(), Some(Ok(1.0)));
+        assert_eq!(iter.next(), Some(Err(FpcError::TruncatedResidual { at_value: 1, residual_len: 10 })));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn test_iter_stops_after_odd_count_tail() {
+        let vals = vec![0.5, -1.0, 7.0];
+        let blk = compress(DEFAULT_TABLE_SIZE, &vals).unwrap();
+        let mut iter: Decompressor = blk.iter(DEFAULT_TABLE_SIZE).unwrap();
+        assert_eq!(iter.by_ref().map(Result::unwrap).collect::<Vec<_>>(), vals);
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn test_decompressor_pairs_with_compressor() {
+        let mut compressor = crate::Compressor::new(DEFAULT_TABLE_SIZE).unwrap();
+        for x in 0..101 {
+            compressor.push(x as f64 * 0.1);
+        }
+        let blk = compressor.finish();
+        let mut decompressor = blk.iter(DEFAULT_TABLE_SIZE).unwrap();
+        for x in 0..101 {
+            assert_eq!(decompressor.next(), Some(Ok(x as f64 * 0.1)));
+            assert_eq!(decompressor.size_hint(), (0, Some(100 - x)));
+        }
+        assert_eq!(decompressor.next(), None);
+    }
+
+    #[quickcheck]
+    fn borrowed_blocks_must_decompress_like_owned_ones(to_compress: Vec<f64>) -> bool {
+        let (num_values, encoding, residual) = compress(DEFAULT_TABLE_SIZE, &to_compress).unwrap().into_parts();
+        let bytes = [encoding.as_slice(), &residual].concat();
+        let (encoding, residual) = bytes.split_at(encoding.len());
+        let blk_ref = CompressedBlockRef { num_bytes_encoded: num_values, encoding, residual };
+        decompress_ref(DEFAULT_TABLE_SIZE, &blk_ref).is_ok_and(|values| crate::bitwise_eq(&values, &to_compress))
+    }
+
+    #[test]
+    fn test_decompress_ref_rejects_malformed_views() {
+        let blk = compress(DEFAULT_TABLE_SIZE, &[1.0, 2.5, 3.0]).unwrap();
+        let blk_ref = CompressedBlockRef { num_bytes_encoded: 3, encoding: blk.encoding(), residual: blk.residual() };
+        assert_eq!(decompress_ref(3, &blk_ref), Err(FpcError::InvalidTableSize { given: 3 }));
+        let short = CompressedBlockRef { num_bytes_encoded: 5, ..blk_ref };
+        assert_eq!(
+            decompress_ref(DEFAULT_TABLE_SIZE, &short),
+            Err(FpcError::InconsistentBlock { num_values: 5, encoding_len: 2 })
+        );
+        let truncated = CompressedBlockRef { residual: &blk.residual()[..10], ..blk_ref };
+        assert_eq!(
+            decompress_ref(DEFAULT_TABLE_SIZE, &truncated),
+            Err(FpcError::TruncatedResidual { at_value: 1, residual_len: 10 })
+        );
+    }
+}
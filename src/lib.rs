@@ -1,92 +1,1045 @@
-const DEFAULT_TABLE_SIZE: u64 = 32;
-
-const BYTE_MASK: [u64; 8] = [
-    0xff_00_00_00_00_00_00_00,
-    0x00_ff_00_00_00_00_00_00,
-    0x00_00_ff_00_00_00_00_00,
-    0x00_00_00_ff_00_00_00_00,
-    0x00_00_00_00_ff_00_00_00,
-    0x00_00_00_00_00_ff_00_00,
-    0x00_00_00_00_00_00_ff_00,
-    0x00_00_00_00_00_00_00_ff,
-];
-
-#[derive(Debug, PartialEq)]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+mod analysis;
+#[cfg(feature = "ndarray")]
+mod array;
+#[cfg(feature = "tokio")]
+mod async_io;
+#[cfg(feature = "std")]
+mod burtscher;
+mod chunked;
+mod columns;
+mod combined;
+mod compressor;
+#[cfg(feature = "std")]
+mod dedup;
+mod delta;
+mod dictionary;
+mod encoding_mode;
+#[cfg(feature = "entropy")]
+mod entropy;
+mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod fixed;
+mod format;
+#[cfg(feature = "std")]
+mod frame;
+mod gorilla;
+mod iter;
+mod lossy;
+mod matrix;
+mod points;
+mod options;
+mod predictor;
+mod resync;
+mod self_test;
+mod series;
+mod single;
+mod split;
+mod stats;
+mod stored;
+#[cfg(feature = "std")]
+mod stream;
+mod text;
+mod trace;
+mod transcode;
+mod warmup;
+#[cfg(feature = "wasm")]
+mod wasm;
+#[cfg(feature = "std")]
+mod writer;
+
+pub use analysis::{
+    block_distance, compress_with_report, decode_encoding, estimate_compressed_size, estimate_mode_sizes,
+    partition_by_predictor, CompressedSize, CompressionReport, LzbEntropy, ModeSizes, ValueMeta,
+};
+#[cfg(feature = "std")]
+pub use analysis::lzb_entropy;
+#[cfg(feature = "ndarray")]
+pub use array::{compress_array1, compress_array2, decompress_array1, decompress_array2};
+#[cfg(feature = "tokio")]
+pub use async_io::{AsyncFpcReader, AsyncFpcWriter};
+#[cfg(feature = "std")]
+pub use burtscher::{read_fpc_file, write_fpc_file};
+pub use chunked::{compress_chunked, decompress_range, ChunkedBlock};
+#[cfg(feature = "rayon")]
+pub use chunked::{compress_parallel, decompress_parallel};
+pub use columns::{compress_columns, decompress_columns};
+pub use combined::{compress_combined, decompress_combined};
+pub use compressor::{
+    compress_continue, compress_with_context, decompress_continue, BlockBuilder, Compressor, CompressorProgress,
+    FpcContext, FpcState,
+};
+#[cfg(feature = "std")]
+pub use dedup::{content_hash, decompress_dedup, FpcDedupWriter};
+pub use dictionary::{compress_with_dictionary, decompress_with_dictionary, DictionaryBlock, FpcDictionary};
+pub use encoding_mode::{compress_compact, EncodingMode, MAX_CONSTANT_VALUES};
+#[cfg(feature = "entropy")]
+pub use entropy::{
+    compress_entropy_coded, decompress_entropy_coded, entropy_code, entropy_decode, EntropyCodedBlock,
+};
+use encoding_mode::{uniform_bits, UniformScan};
+pub use error::{CompressError, DecompressError, FpcError, ParseError, ValidationError};
+pub use fixed::{compress_fixed, decompress_fixed};
+pub use format::{get_block, layout, peek_num_values, BlockLayout, ResidualLayout};
+#[cfg(feature = "std")]
+pub use frame::{read_frame, write_frame};
+pub use gorilla::{
+    compress_gorilla, compress_scheme, decompress_gorilla, decompress_scheme, GorillaBlock, Scheme, SchemeBlock,
+};
+pub use iter::{decompress_ref, CompressedBlockRef, Decompressor, FpcValueIter};
+pub use lossy::{compress_lossy, quantization_savings};
+pub use matrix::{compress_2d, decompress_2d, MatrixBlock, Order};
+pub use points::{compress_points3, decompress_points3, Points3Block};
+pub use options::{FpcOptions, FpcOptionsBuilder, HashParams, ResidualSizing, TieBreak};
+pub use predictor::{
+    compress_order, compress_with_predictors, decompress_order, decompress_with_predictors, DfcmPredictor, FcmPredictor,
+    OrderFcmPredictor, Predictor, PredictorBlock, ValuePredictor,
+};
+pub use resync::{compress_resync, decompress_resync, ResyncRecovery};
+pub use self_test::self_test;
+pub use series::{compress_series, decompress_series, SeriesBlock};
+pub use single::{compress_f32, decompress_f32, FPCCompressedBlockF32};
+pub use split::{merge, split_at};
+pub use stats::{compress_with_stats, ValueStats};
+pub use stored::{compress_or_raw, decompress_stored, StoredBlock};
+#[cfg(feature = "std")]
+pub use stream::{decompress_stream, FpcReader};
+pub use transcode::transcode;
+pub use warmup::{compress_skip_warmup, decompress_after_warmup};
+#[cfg(feature = "wasm")]
+pub use wasm::{wasm_compress, wasm_decompress};
+#[cfg(feature = "std")]
+pub use writer::FpcWriter;
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use delta::DeltaOfDelta;
+use trace::decompress_span;
+
+pub const DEFAULT_TABLE_SIZE: u64 = 32;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FPCCompressedBlock {
+    table_size: u64,
     num_bytes_encoded: usize,
+    #[cfg_attr(feature = "serde", serde(default))]
+    mode: EncodingMode,
+    #[cfg_attr(feature = "serde", serde(default))]
+    hash_params: HashParams,
+    #[cfg_attr(feature = "serde", serde(default))]
+    dropped_mantissa_bits: u8,
+    #[cfg_attr(feature = "serde", serde(default))]
+    delta_of_delta: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    seed: u64,
+    #[cfg_attr(feature = "serde", serde(default))]
+    canonicalize_nan: bool,
     encoding: Vec<u8>,
     residual: Vec<u8>,
 }
 
-pub fn compress(table_size: u64, fp_values: &Vec<f64>) -> FPCCompressedBlock {
-    let mut encoding = vec![0_u8; (fp_values.len() + 1)/2];
-    let mut residual = Vec::with_capacity((size_of::<f64>() * fp_values.len())/4);
-    compress_into(table_size, fp_values, &mut encoding, &mut residual);
-    FPCCompressedBlock { num_bytes_encoded: fp_values.len(), encoding, residual }
+impl FPCCompressedBlock {
+    /// Number of values in the block.
+    pub fn num_values(&self) -> usize {
+        self.num_bytes_encoded
+    }
+
+    /// Table size the block was compressed with, and must be decompressed
+    /// with.
+    pub fn table_size(&self) -> u64 {
+        self.table_size
+    }
+
+    /// Hash parameters the block was compressed with.
+    pub fn hash_params(&self) -> HashParams {
+        self.hash_params
+    }
+
+    /// Number of low mantissa bits [`compress_lossy`] cleared before
+    /// compressing, 0 for a lossless block. Every decompressed normal
+    /// value is within a relative error of `2^(dropped - 52)` of the
+    /// original, and subnormals within `2^(dropped - 1074)` absolute.
+    pub fn dropped_mantissa_bits(&self) -> u8 {
+        self.dropped_mantissa_bits
+    }
+
+    /// Whether the values went through the delta-of-delta stage of
+    /// [`FpcOptions::delta_of_delta`] before compression. Decompression
+    /// undoes it either way.
+    pub fn delta_of_delta(&self) -> bool {
+        self.delta_of_delta
+    }
+
+    /// The value the DFCM predictor started from, as given to
+    /// [`compress_with_seed`]; 0.0 for every other block.
+    pub fn seed(&self) -> f64 {
+        f64::from_bits(self.seed)
+    }
+
+    /// Whether every NaN was replaced by `f64::NAN` before compression, as
+    /// set by [`FpcOptions::canonicalize_nan`]. Such a block decompresses
+    /// NaNs with the bits of `f64::NAN` rather than their own payloads.
+    pub fn canonicalize_nan(&self) -> bool {
+        self.canonicalize_nan
+    }
+
+    /// Predictors in the state the block's first value was compressed
+    /// from.
+    fn predictors(&self, table_size: u64) -> Predictors {
+        Predictors::with_hash_params(table_size, self.hash_params).seeded(self.seed)
+    }
+
+    /// Decompresses the block with the table size it was compressed with.
+    /// Panics if the block is malformed.
+    pub fn decompress(&self) -> Vec<f64> {
+        decompress(self.table_size, self)
+    }
+
+    /// The encoding stream, holding the code of every value in the block's
+    /// [`EncodingMode`].
+    pub fn encoding(&self) -> &[u8] {
+        &self.encoding
+    }
+
+    /// The residual stream, holding the bytes the predictions got wrong.
+    pub fn residual(&self) -> &[u8] {
+        &self.residual
+    }
+
+    /// Takes the block apart into its number of values, encoding and
+    /// residual, without copying either stream. A compact block is
+    /// rewritten with full codes first, so the encoding always holds one
+    /// nibble per value. The other settings of the block are not part of
+    /// the parts; see [`from_parts`](Self::from_parts).
+    pub fn into_parts(mut self) -> (usize, Vec<u8>, Vec<u8>) {
+        self.switch_to_full_mode();
+        (self.num_bytes_encoded, self.encoding, self.residual)
+    }
+
+    /// Reassembles a block from the parts returned by
+    /// [`into_parts`](Self::into_parts) and the table size it was
+    /// compressed with. The result has the default hash parameters and no
+    /// preprocessing, so only blocks compressed with the default
+    /// [`FpcOptions`] come back exactly as they were. Fails like
+    /// [`validate`](Self::validate) if the parts do not make a block.
+    pub fn from_parts(
+        table_size: u64,
+        num_bytes_encoded: usize,
+        encoding: Vec<u8>,
+        residual: Vec<u8>,
+    ) -> Result<Self, ValidationError> {
+        let blk = FPCCompressedBlock::from_parts_unchecked(table_size, num_bytes_encoded, encoding, residual);
+        blk.validate()?;
+        Ok(blk)
+    }
+
+    /// Like [`from_parts`](Self::from_parts), for parts known to be
+    /// consistent.
+    pub(crate) fn from_parts_unchecked(
+        table_size: u64,
+        num_bytes_encoded: usize,
+        encoding: Vec<u8>,
+        residual: Vec<u8>,
+    ) -> Self {
+        FPCCompressedBlock {
+            table_size,
+            num_bytes_encoded,
+            mode: EncodingMode::Full,
+            hash_params: HashParams::DEFAULT,
+            dropped_mantissa_bits: 0,
+            delta_of_delta: false,
+            seed: 0,
+            canonicalize_nan: false,
+            encoding,
+            residual,
+        }
+    }
+
+    /// Size of the compressed data in bytes, excluding any serialization
+    /// header.
+    pub fn compressed_len(&self) -> usize {
+        self.encoding.len() + self.residual.len()
+    }
+
+    /// Uncompressed size divided by [`compressed_len`](Self::compressed_len).
+    /// An empty block has a ratio of 0.
+    pub fn compression_ratio(&self) -> f64 {
+        if self.num_bytes_encoded == 0 {
+            return 0.0;
+        }
+        // Every non-empty block carries at least one encoding byte, so the
+        // division is well defined.
+        (self.num_bytes_encoded * size_of::<f64>()) as f64 / self.compressed_len() as f64
+    }
+
+    /// Compressed bytes per value, the inverse view of
+    /// [`compression_ratio`](Self::compression_ratio) in units of bytes.
+    /// An empty block takes 0 bytes per value.
+    pub fn bytes_per_value(&self) -> f64 {
+        if self.num_bytes_encoded == 0 {
+            return 0.0;
+        }
+        self.compressed_len() as f64 / self.num_bytes_encoded as f64
+    }
+
+    /// Yields the 4-bit code of every encoded value, in order, whatever
+    /// the block's mode.
+    fn codes(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..self.num_bytes_encoded).map_while(|index| self.mode.code_at(&self.encoding, index))
+    }
+
+    /// Checks that the block is consistent in itself: a valid table size,
+    /// hash parameters and number of dropped mantissa bits, an encoding of
+    /// the length its mode needs for the number of values, and a residual
+    /// of at least the bytes the codes call for. [`from_parts`](Self::from_parts)
+    /// and [`from_bytes`](Self::from_bytes) check this, but a block
+    /// deserialized with serde should be validated before use.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        validate_table_size(self.table_size)?;
+        self.hash_params.validate()?;
+        if self.dropped_mantissa_bits > lossy::MANTISSA_BITS {
+            return Err(FpcError::InvalidDroppedBits { given: self.dropped_mantissa_bits });
+        }
+        self.check_encoding_len()?;
+        let expected: usize = self.codes().map(residual_len_of_code).sum();
+        if self.residual.len() < expected {
+            return Err(FpcError::ResidualLengthMismatch { expected, actual: self.residual.len() });
+        }
+        Ok(())
+    }
+
+    fn check_table_size(&self, table_size: u64) -> Result<(), FpcError> {
+        if table_size != self.table_size {
+            return Err(FpcError::TableSizeMismatch { block: self.table_size, given: table_size });
+        }
+        Ok(())
+    }
+
+    /// Every value takes one code of the encoding, so a block of `n` values
+    /// must carry exactly the bytes its mode needs for `n` codes.
+    fn check_encoding_len(&self) -> Result<(), FpcError> {
+        let too_many_constants = self.mode == EncodingMode::Constant && self.num_bytes_encoded > MAX_CONSTANT_VALUES;
+        if self.encoding.len() != self.mode.encoding_len(self.num_bytes_encoded) || too_many_constants {
+            return Err(FpcError::InconsistentBlock {
+                num_values: self.num_bytes_encoded,
+                encoding_len: self.encoding.len(),
+            });
+        }
+        if self.mode == EncodingMode::Constant && self.residual.len() != size_of::<u64>() {
+            return Err(FpcError::ResidualLengthMismatch { expected: size_of::<u64>(), actual: self.residual.len() });
+        }
+        Ok(())
+    }
+}
+
+/// Compresses `fp_values` with the default [`FpcOptions`]. Fails with
+/// [`FpcError::InvalidTableSize`] unless `table_size` is a power of two
+/// of at most [`MAX_TABLE_SIZE`].
+///
+/// The residual starts out with room for 2 bytes per value
+/// ([`ResidualSizing::Conservative`]), which compressible data rarely
+/// outgrows. Incompressible data takes up to 8 bytes per value and
+/// reallocates along the way; pass [`ResidualSizing::Exact`] to
+/// [`compress_with`] to size the residual with a first pass over the
+/// values instead.
+pub fn compress(table_size: u64, fp_values: &[f64]) -> Result<FPCCompressedBlock, CompressError> {
+    compress_with(table_size, fp_values, &FpcOptions::default())
 }
 
+/// Like [`compress`], but panics on an invalid table size.
+pub fn compress_unchecked(table_size: u64, fp_values: &[f64]) -> FPCCompressedBlock {
+    compress_block(table_size, fp_values, &FpcOptions::default())
+}
+
+pub fn compress_with(table_size: u64, fp_values: &[f64], options: &FpcOptions) -> Result<FPCCompressedBlock, CompressError> {
+    validate_table_size(table_size)?;
+    options.hash_params.validate()?;
+    Ok(compress_block(table_size, fp_values, options))
+}
+
+/// Compresses `fp_values` with the DFCM predictor starting from `seed`
+/// as the previous value instead of 0.0. Seeding with a value close to
+/// the first one, such as the last value of a previous block, shrinks the
+/// residuals of the first few values. The seed is stored in the block, so
+/// decompressing needs nothing extra; a seed of 0.0 gives the same block
+/// as [`compress`].
+pub fn compress_with_seed(table_size: u64, fp_values: &[f64], seed: f64) -> Result<FPCCompressedBlock, CompressError> {
+    validate_table_size(table_size)?;
+    Ok(compress_block_seeded(table_size, fp_values, &FpcOptions::default(), seed.to_bits()))
+}
+
+fn compress_block(table_size: u64, fp_values: &[f64], options: &FpcOptions) -> FPCCompressedBlock {
+    compress_block_seeded(table_size, fp_values, options, 0)
+}
+
+fn compress_block_seeded(table_size: u64, fp_values: &[f64], options: &FpcOptions, seed: u64) -> FPCCompressedBlock {
+    #[cfg(feature = "tracing")]
+    let span = tracing::debug_span!(
+        "compress",
+        table_size,
+        num_values = fp_values.len(),
+        compressed_len = tracing::field::Empty,
+        mode = tracing::field::Empty,
+    )
+    .entered();
+    let prepared = |v: &f64| if options.canonicalize_nan { lossy::canonicalize_nan(v.to_bits()) } else { v.to_bits() };
+    let blk = match uniform_bits(fp_values.iter().map(prepared)) {
+        Some(bits) => {
+            check_table_size(table_size);
+            FPCCompressedBlock {
+                hash_params: options.hash_params,
+                delta_of_delta: options.delta_of_delta,
+                seed,
+                canonicalize_nan: options.canonicalize_nan,
+                ..FPCCompressedBlock::constant(table_size, fp_values.len(), bits)
+            }
+        }
+        None => encode_block(table_size, fp_values, options, seed),
+    };
+    #[cfg(feature = "tracing")]
+    span.record("compressed_len", blk.compressed_len()).record("mode", tracing::field::debug(blk.mode));
+    blk
+}
+
+/// Compresses `fp_values` with full codes, even if they are all the same.
+fn encode_block(table_size: u64, fp_values: &[f64], options: &FpcOptions, seed: u64) -> FPCCompressedBlock {
+    let bits = || {
+        let mut stage = options.delta_of_delta.then(DeltaOfDelta::default);
+        fp_values.iter().map(move |v| {
+            let bits = if options.canonicalize_nan { lossy::canonicalize_nan(v.to_bits()) } else { v.to_bits() };
+            match &mut stage {
+                Some(stage) => stage.apply(bits),
+                None => bits,
+            }
+        })
+    };
+    let residual_capacity = match options.residual_sizing {
+        ResidualSizing::Conservative => size_of_val(fp_values)/4,
+        ResidualSizing::Exact if fp_values.is_empty() => 0,
+        ResidualSizing::Exact => {
+            check_table_size(table_size);
+            residual_len_from(Predictors::with_hash_params(table_size, options.hash_params).seeded(seed), bits())
+        }
+        ResidualSizing::Pessimistic => size_of_val(fp_values),
+    };
+    let mut encoding = Vec::with_capacity(fp_values.len().div_ceil(2));
+    let mut residual = Vec::with_capacity(residual_capacity);
+    if !fp_values.is_empty() {
+        check_table_size(table_size);
+        let mut predictors = Predictors::with_hash_params(table_size, options.hash_params).seeded(seed);
+        predictors.tie_break = options.tie_break;
+        encode_bits(&mut predictors, bits(), (&mut encoding, &mut residual), None);
+    }
+    #[cfg(feature = "tracing")]
+    if residual.len() > residual_capacity {
+        tracing::debug!(residual_capacity, residual_len = residual.len(), "residual outgrew its conservative capacity");
+    }
+    FPCCompressedBlock {
+        table_size,
+        num_bytes_encoded: fp_values.len(),
+        mode: EncodingMode::Full,
+        hash_params: options.hash_params,
+        dropped_mantissa_bits: 0,
+        delta_of_delta: options.delta_of_delta,
+        seed,
+        canonicalize_nan: options.canonicalize_nan,
+        encoding,
+        residual,
+    }
+}
+
+/// Decompresses `blk` with an explicitly given table size. Panics if it
+/// differs from the size the block was compressed with; see
+/// [`FPCCompressedBlock::decompress`] to use the block's own.
 pub fn decompress(table_size: u64, blk: &FPCCompressedBlock) -> Vec<f64> {
     let mut res = Vec::with_capacity(blk.num_bytes_encoded);
     decompress_into(table_size, blk, &mut res);
     res
 }
 
-pub fn compress_into(
-    table_size: u64, fp_values: &Vec<f64>,
-    encoding: &mut Vec<u8>,
-    residual: &mut Vec<u8>,
-) {
+/// Like [`decompress`], but returns an error instead of panicking or
+/// producing the wrong number of values when the block is malformed or
+/// `table_size` is not the block's.
+pub fn try_decompress(table_size: u64, blk: &FPCCompressedBlock) -> Result<Vec<f64>, DecompressError> {
+    decompress_span!(table_size, blk);
+    let values = blk.iter(table_size)?;
+    blk.check_encoding_len()?;
+    values.collect()
+}
+
+/// Like [`decompress_into`], but returns an error instead of panicking.
+/// On error `res` is left as it was before the call.
+pub fn try_decompress_into(table_size: u64, blk: &FPCCompressedBlock, res: &mut Vec<f64>) -> Result<(), DecompressError> {
+    decompress_span!(table_size, blk);
+    let values = blk.iter(table_size)?;
+    blk.check_encoding_len()?;
+    let start = res.len();
+    for value in values {
+        match value {
+            Ok(value) => res.push(value),
+            Err(e) => {
+                res.truncate(start);
+                return Err(e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Decodes `blk` into `out[..blk.num_values()]` and returns the number of
+/// values written. Nothing is allocated apart from the predictor tables,
+/// so one buffer can be reused across many blocks. `out` must hold at
+/// least [`FPCCompressedBlock::num_values`] values; anything past them is
+/// left untouched. After a decoding error `out` starts with the values
+/// decoded before it.
+pub fn decompress_into_slice(table_size: u64, blk: &FPCCompressedBlock, out: &mut [f64]) -> Result<usize, DecompressError> {
+    decompress_span!(table_size, blk);
+    let values = blk.iter(table_size)?;
+    blk.check_encoding_len()?;
+    if out.len() < blk.num_bytes_encoded {
+        return Err(FpcError::OutputTooSmall { needed: blk.num_bytes_encoded, available: out.len() });
+    }
+    for (slot, value) in out.iter_mut().zip(values) {
+        *slot = value?;
+    }
+    Ok(blk.num_bytes_encoded)
+}
+
+/// Decodes `blk` and hands every value to `f` as soon as it is decoded,
+/// so aggregating or streaming the values out needs no memory beyond the
+/// predictor tables. After a decoding error `f` has seen the values
+/// decoded before it.
+pub fn decompress_for_each(
+    table_size: u64,
+    blk: &FPCCompressedBlock,
+    mut f: impl FnMut(f64),
+) -> Result<(), DecompressError> {
+    decompress_span!(table_size, blk);
+    let values = blk.iter(table_size)?;
+    blk.check_encoding_len()?;
+    for value in values {
+        f(value?);
+    }
+    Ok(())
+}
+
+/// Whether `a` and `b` hold the same values bit for bit. Unlike `==`,
+/// NaNs equal themselves down to their payload and `0.0` differs from
+/// `-0.0`, which is the equality compression preserves.
+pub fn bitwise_eq(a: &[f64], b: &[f64]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(x, y)| x.to_bits() == y.to_bits())
+}
+
+/// Checks that `blk` decompresses to exactly `original`, for use as a
+/// last check before storing a block. A malformed block or a `table_size`
+/// other than the block's fails the check instead of panicking.
+pub fn verify(table_size: u64, original: &[f64], blk: &FPCCompressedBlock) -> bool {
+    try_decompress(table_size, blk).is_ok_and(|decompressed| bitwise_eq(&decompressed, original))
+}
+
+/// Destination for the two streams produced by the compressor. Encoding
+/// bytes arrive one at a time, each packing the codes of two values (the
+/// first value in the high nibble), and residual bytes arrive per value.
+pub trait EncodingSink {
+    fn push_encoding(&mut self, byte: u8);
+    fn push_residual(&mut self, bytes: &[u8]);
+}
+
+impl EncodingSink for (&mut Vec<u8>, &mut Vec<u8>) {
+    fn push_encoding(&mut self, byte: u8) {
+        self.0.push(byte);
+    }
+
+    fn push_residual(&mut self, bytes: &[u8]) {
+        self.1.extend_from_slice(bytes);
+    }
+}
+
+impl<S: EncodingSink + ?Sized> EncodingSink for &mut S {
+    fn push_encoding(&mut self, byte: u8) {
+        (**self).push_encoding(byte);
+    }
+
+    fn push_residual(&mut self, bytes: &[u8]) {
+        (**self).push_residual(bytes);
+    }
+}
+
+/// Compresses `fp_values` into `sink`. Passing `(&mut encoding, &mut residual)`
+/// appends the two streams to a pair of vectors. The streams always hold
+/// full codes, even for values that [`compress`] stores as a constant
+/// block. Fails like [`compress`] on an invalid table size, without
+/// touching `sink`.
+pub fn compress_into<S: EncodingSink>(table_size: u64, fp_values: &[f64], sink: S) -> Result<(), CompressError> {
+    validate_table_size(table_size)?;
     if fp_values.is_empty() {
-        return;
+        return Ok(());
+    }
+    compress_bits_into(table_size, HashParams::DEFAULT, fp_values.iter().map(|v| v.to_bits()), sink);
+    Ok(())
+}
+
+/// Compresses one `f64` field of every item in `items`, as if the field
+/// had first been collected into a `Vec<f64>` and passed to [`compress`].
+/// Fails like [`compress`] on an invalid table size.
+pub fn compress_by<T>(
+    table_size: u64,
+    items: &[T],
+    extract: impl Fn(&T) -> f64,
+) -> Result<FPCCompressedBlock, CompressError> {
+    validate_table_size(table_size)?;
+    if let Some(bits) = uniform_bits(items.iter().map(|item| extract(item).to_bits())) {
+        return Ok(FPCCompressedBlock::constant(table_size, items.len(), bits));
+    }
+    // Items of a zero-sized type make any length possible.
+    let (mut encoding, mut residual) = (Vec::new(), Vec::new());
+    try_reserve(&mut encoding, items.len().div_ceil(2));
+    try_reserve(&mut residual, residual_capacity(items.len()));
+    if !items.is_empty() {
+        let bits = items.iter().map(|item| extract(item).to_bits());
+        compress_bits_into(table_size, HashParams::DEFAULT, bits, (&mut encoding, &mut residual));
     }
-    if table_size == 0 || (table_size & (table_size-1)) != 0 {
+    Ok(FPCCompressedBlock {
+        table_size,
+        num_bytes_encoded: items.len(),
+        mode: EncodingMode::Full,
+        hash_params: HashParams::DEFAULT,
+        dropped_mantissa_bits: 0,
+        delta_of_delta: false,
+        seed: 0,
+        canonicalize_nan: false,
+        encoding,
+        residual,
+    })
+}
+
+/// Compresses the values of `values` as they are produced, without
+/// collecting them first. The block is identical to the one [`compress`]
+/// returns for the collected values; the streams start out sized for the
+/// lower bound of the iterator's size hint and grow as needed.
+///
+/// The values can come from any iterator. For example,
+/// `compress_iter(table_size, view.iter().copied())` compresses an
+/// `ndarray` view whether or not it is contiguous, which is what
+/// `compress_array1` does with the `ndarray` feature.
+pub fn compress_iter<I: IntoIterator<Item = f64>>(table_size: u64, values: I) -> Result<FPCCompressedBlock, CompressError> {
+    validate_table_size(table_size)?;
+    let values = values.into_iter();
+    let expected_len = values.size_hint().0;
+    let (mut encoding, mut residual) = (Vec::new(), Vec::new());
+    try_reserve(&mut encoding, expected_len.div_ceil(2));
+    try_reserve(&mut residual, residual_capacity(expected_len));
+    let mut num_values = 0;
+    let mut scan = UniformScan::default();
+    let bits = values.map(f64::to_bits).inspect(|&bits| {
+        num_values += 1;
+        scan.push(bits);
+    });
+    compress_bits_into(table_size, HashParams::DEFAULT, bits, (&mut encoding, &mut residual));
+    if let Some(bits) = scan.bits() {
+        return Ok(FPCCompressedBlock::constant(table_size, num_values, bits));
+    }
+    Ok(FPCCompressedBlock {
+        table_size,
+        num_bytes_encoded: num_values,
+        mode: EncodingMode::Full,
+        hash_params: HashParams::DEFAULT,
+        dropped_mantissa_bits: 0,
+        delta_of_delta: false,
+        seed: 0,
+        canonicalize_nan: false,
+        encoding,
+        residual,
+    })
+}
+
+/// Like [`compress`], but also returns the contents of the FCM and DFCM
+/// tables after the last value, for diffing the predictor state against
+/// a reference implementation. Fails like [`compress`] on an invalid
+/// table size.
+pub fn compress_debug(
+    table_size: u64,
+    fp_values: &[f64],
+) -> Result<(FPCCompressedBlock, Vec<u64>, Vec<u64>), CompressError> {
+    validate_table_size(table_size)?;
+    if let Some(bits) = uniform_bits(fp_values.iter().map(|v| v.to_bits())) {
+        // The tables still see every value, but nothing is encoded.
+        let mut predictors = Predictors::new(table_size);
+        for _ in fp_values {
+            predictors.update(bits);
+        }
+        return Ok((FPCCompressedBlock::constant(table_size, fp_values.len(), bits), predictors.fcm, predictors.dfcm));
+    }
+    let mut encoding = Vec::with_capacity(fp_values.len().div_ceil(2));
+    let mut residual = Vec::new();
+    let bits = fp_values.iter().map(|v| v.to_bits());
+    let predictors = compress_bits_into(table_size, HashParams::DEFAULT, bits, (&mut encoding, &mut residual));
+    let blk = FPCCompressedBlock {
+        table_size,
+        num_bytes_encoded: fp_values.len(),
+        mode: EncodingMode::Full,
+        hash_params: HashParams::DEFAULT,
+        dropped_mantissa_bits: 0,
+        delta_of_delta: false,
+        seed: 0,
+        canonicalize_nan: false,
+        encoding,
+        residual,
+    };
+    Ok((blk, predictors.fcm, predictors.dfcm))
+}
+
+/// Largest table size blocks may be compressed with or declare. Both
+/// tables are allocated up front, so at this size they already take
+/// 256 MiB; a bound keeps a corrupt header from asking for terabytes.
+pub const MAX_TABLE_SIZE: u64 = 1 << 24;
+
+/// Whether `table_size` can be used to compress, i.e. is a power of two
+/// of at most [`MAX_TABLE_SIZE`].
+pub fn is_valid_table_size(table_size: u64) -> bool {
+    table_size != 0 && (table_size & (table_size-1)) == 0 && table_size <= MAX_TABLE_SIZE
+}
+
+/// Suggests a table size for compressing `num_values` values: one table
+/// entry per 16 values, rounded up to a power of two and kept between 32
+/// and 65536. Larger tables only pay off once there are enough values to
+/// fill them, and beyond 65536 entries the two tables (1 MiB) no longer
+/// fit in cache.
+pub fn recommended_table_size(num_values: usize) -> u64 {
+    (num_values as u64 / 16).next_power_of_two().clamp(32, 65536)
+}
+
+fn validate_table_size(table_size: u64) -> Result<(), FpcError> {
+    if !is_valid_table_size(table_size) {
+        return Err(FpcError::InvalidTableSize { given: table_size });
+    }
+    Ok(())
+}
+
+fn check_table_size(table_size: u64) {
+    if !is_valid_table_size(table_size) {
         panic!("table size must be a multiple of 2 and preferably fit in L1 cache");
     }
-    let mut true_value: u64;
-    let mut last_value: u64 = 0;
+}
+
+/// Residual bytes to reserve for `num_values` values: 2 per value, which
+/// compressible data rarely exceeds. Saturates rather than overflowing on
+/// counts no slice of `f64` can reach, such as a size hint.
+fn residual_capacity(num_values: usize) -> usize {
+    num_values.saturating_mul(size_of::<f64>()) / 4
+}
 
-    let mut fcm_hash: u64 = 0;
-    let mut fcm: Vec<u64> = vec![0_u64; table_size as usize];
+/// Reserves room for `additional` more bytes in `bytes` if the allocator
+/// grants it. A request too large to satisfy leaves the buffer to grow as
+/// bytes arrive, instead of aborting before any value is looked at.
+fn try_reserve(bytes: &mut Vec<u8>, additional: usize) {
+    let _ = bytes.try_reserve(additional);
+}
 
-    let mut dfcm_hash: u64 = 0;
-    let mut dfcm: Vec<u64> = vec![0_u64; table_size as usize];
+/// FCM and DFCM predictor state shared by the compressor and decompressor.
+/// The tables live on the heap by default, or in arrays for
+/// [`compress_fixed`].
+#[derive(Clone)]
+struct Predictors<T = Vec<u64>> {
+    table_mask: u64,
+    hash_params: HashParams,
+    tie_break: TieBreak,
+    last_value: u64,
+    fcm_hash: u64,
+    fcm: T,
+    dfcm_hash: u64,
+    dfcm: T,
+}
 
-    for i in 0..fp_values.len() {
-        true_value = fp_values[i].to_bits();
+impl Predictors {
+    fn new(table_size: u64) -> Self {
+        Predictors::with_hash_params(table_size, HashParams::DEFAULT)
+    }
 
-        let fcm_prediction = fcm[fcm_hash as usize];
-        fcm[fcm_hash as usize] = true_value;
-        fcm_hash = ((fcm_hash << 6) ^ (true_value >> 48)) & (table_size - 1);
+    fn with_hash_params(table_size: u64, hash_params: HashParams) -> Self {
+        Predictors {
+            table_mask: table_size - 1,
+            hash_params,
+            tie_break: TieBreak::default(),
+            last_value: 0,
+            fcm_hash: 0,
+            fcm: vec![0_u64; table_size as usize],
+            dfcm_hash: 0,
+            dfcm: vec![0_u64; table_size as usize],
+        }
+    }
 
-        let dfcm_prediction = dfcm[dfcm_hash as usize].wrapping_add(last_value);
-        dfcm[dfcm_hash as usize] = true_value.wrapping_sub(last_value);
-        dfcm_hash = ((dfcm_hash << 2) ^ (true_value.wrapping_sub(last_value) >> 40)) & (table_size - 1);
-        last_value = true_value;
+    /// Starts the DFCM predictor from `seed` as the previous value.
+    fn seeded(mut self, seed: u64) -> Self {
+        self.last_value = seed;
+        self
+    }
 
-        let fcm_diff = fcm_prediction ^ true_value;
-        let dfcm_diff = dfcm_prediction ^ true_value;
-        let to_encode = std::cmp::min(fcm_diff, dfcm_diff);
-        let mut lzb = 0;
-        for x in 0..BYTE_MASK.len() {
-            if (to_encode & BYTE_MASK[x]) != 0 {
-                break;
+    /// Returns to the state of `Predictors::new(table_size)`, reusing the
+    /// existing tables when they are large enough.
+    fn reset(&mut self, table_size: u64) {
+        self.table_mask = table_size - 1;
+        self.last_value = 0;
+        self.fcm_hash = 0;
+        self.dfcm_hash = 0;
+        for table in [&mut self.fcm, &mut self.dfcm] {
+            table.truncate(table_size as usize);
+            table.fill(0);
+            table.resize(table_size as usize, 0);
+        }
+    }
+}
+
+impl<T: AsRef<[u64]> + AsMut<[u64]>> Predictors<T> {
+    /// Returns the FCM and DFCM predictions for the next value.
+    fn predict(&self) -> (u64, u64) {
+        let fcm_prediction = self.fcm.as_ref()[self.fcm_hash as usize];
+        let dfcm_prediction = self.dfcm.as_ref()[self.dfcm_hash as usize].wrapping_add(self.last_value);
+        (fcm_prediction, dfcm_prediction)
+    }
+
+    fn update(&mut self, true_value: u64) {
+        self.fcm.as_mut()[self.fcm_hash as usize] = true_value;
+        let HashParams { fcm_shift, fcm_fold, dfcm_shift, dfcm_fold } = self.hash_params;
+        self.fcm_hash = ((self.fcm_hash << fcm_shift) ^ (true_value >> fcm_fold)) & self.table_mask;
+
+        let delta = true_value.wrapping_sub(self.last_value);
+        self.dfcm.as_mut()[self.dfcm_hash as usize] = delta;
+        self.dfcm_hash = ((self.dfcm_hash << dfcm_shift) ^ (delta >> dfcm_fold)) & self.table_mask;
+        self.last_value = true_value;
+    }
+}
+
+/// A pair of predictors the encoding loop can drive: the built-in
+/// [`Predictors`] or a pair of [`ValuePredictor`]s.
+trait PredictorState {
+    /// Returns the FCM and DFCM predictions for the next value.
+    fn predict(&self) -> (u64, u64);
+    fn update(&mut self, true_value: u64);
+
+    /// Which predictor a value is attributed to when both predict it
+    /// equally well.
+    fn tie_break(&self) -> TieBreak {
+        TieBreak::default()
+    }
+}
+
+impl<T: AsRef<[u64]> + AsMut<[u64]>> PredictorState for Predictors<T> {
+    fn predict(&self) -> (u64, u64) {
+        Predictors::predict(self)
+    }
+
+    fn update(&mut self, true_value: u64) {
+        Predictors::update(self, true_value);
+    }
+
+    fn tie_break(&self) -> TieBreak {
+        self.tie_break
+    }
+}
+
+/// Picks the closer of the two predictions for `true_value`, DFCM on a
+/// tie. Returns the 4-bit code stored in the encoding, the XOR difference
+/// against the chosen prediction and how many of its low bytes go into
+/// the residual.
+fn encode_value(fcm_prediction: u64, dfcm_prediction: u64, true_value: u64) -> (u8, u64, usize) {
+    let fcm_diff = fcm_prediction ^ true_value;
+    let dfcm_diff = dfcm_prediction ^ true_value;
+    let to_encode = core::cmp::min(fcm_diff, dfcm_diff);
+    // A zero difference has 64 leading zero bits, so 8 zero bytes.
+    let mut lzb = (to_encode.leading_zeros() / 8) as u8;
+    // If the number of leading bytes is 4, then treat it
+    // as 3 and encode an additional 0 to the residual.
+    let residual_len = if lzb == 4 { 5 } else { 8 - lzb as usize };
+    if lzb >= 4 {
+        lzb -= 1;
+    }
+    let code = lzb | (if fcm_diff < dfcm_diff { 1 << 3 } else { 0 });
+    (code, to_encode, residual_len)
+}
+
+/// Number of residual bytes stored for a value with the given 4-bit code.
+fn residual_len_of_code(code: u8) -> usize {
+    let mut num_leading_zero_bytes = (code & 0b0111) as usize;
+    if num_leading_zero_bytes >= 4 {
+        num_leading_zero_bytes += 1;
+    }
+    8 - num_leading_zero_bytes
+}
+
+/// Reverses [`encode_value`]: reads the residual bytes of one value with
+/// the given 4-bit code starting at `*residual_index`, and advances the
+/// predictors past it. Returns `None` if the residual runs out.
+fn decode_value(predictors: &mut impl PredictorState, code: u8, residual: &[u8], residual_index: &mut usize) -> Option<u64> {
+    let is_fcm_predicted = code & 0b1000 != 0;
+    let bytes = residual.get(*residual_index..*residual_index + residual_len_of_code(code))?;
+    *residual_index += bytes.len();
+    let diff = bytes.iter().fold(0_u64, |acc, &b| (acc << 8) | b as u64);
+    let (fcm_prediction, dfcm_prediction) = predictors.predict();
+    let decoded = diff ^ if is_fcm_predicted { fcm_prediction } else { dfcm_prediction };
+    predictors.update(decoded);
+    Some(decoded)
+}
+
+fn residual_len(table_size: u64, hash_params: HashParams, bits: impl Iterator<Item = u64>) -> usize {
+    check_table_size(table_size);
+    residual_len_from(Predictors::with_hash_params(table_size, hash_params), bits)
+}
+
+fn residual_len_from(mut predictors: Predictors, bits: impl Iterator<Item = u64>) -> usize {
+    let mut total = 0;
+    for true_value in bits {
+        let (fcm_prediction, dfcm_prediction) = predictors.predict();
+        predictors.update(true_value);
+        total += encode_value(fcm_prediction, dfcm_prediction, true_value).2;
+    }
+    total
+}
+
+fn compress_bits_into(
+    table_size: u64,
+    hash_params: HashParams,
+    bits: impl Iterator<Item = u64>,
+    sink: impl EncodingSink,
+) -> Predictors {
+    check_table_size(table_size);
+    let mut predictors = Predictors::with_hash_params(table_size, hash_params);
+    encode_bits(&mut predictors, bits, sink, None);
+    predictors
+}
+
+/// Encodes `bits` into `sink` starting from the given predictor state.
+/// `pending_code` is the code of a preceding value whose encoding byte
+/// has not been written yet because its low nibble was still free.
+fn encode_bits(
+    predictors: &mut impl PredictorState,
+    bits: impl Iterator<Item = u64>,
+    mut sink: impl EncodingSink,
+    pending_code: Option<u8>,
+) {
+    if let Some(last) = encode_bits_pending(predictors, bits, &mut sink, pending_code) {
+        sink.push_encoding(last << 4);
+    }
+}
+
+/// Like [`encode_bits`], but returns the code of a trailing odd value
+/// instead of writing it out, so that more values can follow.
+fn encode_bits_pending(
+    predictors: &mut impl PredictorState,
+    bits: impl Iterator<Item = u64>,
+    mut sink: impl EncodingSink,
+    mut pending_code: Option<u8>,
+) -> Option<u8> {
+    for true_value in bits {
+        let (fcm_prediction, dfcm_prediction) = predictors.predict();
+        predictors.update(true_value);
+
+        let (mut code, to_encode, residual_len) = encode_value(fcm_prediction, dfcm_prediction, true_value);
+        if fcm_prediction == dfcm_prediction && predictors.tie_break() == TieBreak::PreferFcm {
+            code |= 1 << 3;
+        }
+        sink.push_residual(&to_encode.to_be_bytes()[8 - residual_len..]);
+        match pending_code.take() {
+            Some(first) => sink.push_encoding((first << 4) | code),
+            None => pending_code = Some(code),
+        }
+    }
+    pending_code
+}
+
+impl FPCCompressedBlock {
+    /// Appends `new_values` to the block, producing the same block as
+    /// compressing the old and new values in one go.
+    ///
+    /// The block does not keep its final predictor state, so this first
+    /// decodes every value already in the block to rebuild it. Appending
+    /// is therefore as expensive as decompressing the existing block.
+    ///
+    /// Panics if `table_size` differs from the block's table size or the
+    /// block is malformed; [`try_append`](Self::try_append) reports these
+    /// as errors instead.
+    pub fn append(&mut self, table_size: u64, new_values: &[f64]) {
+        if let Err(e) = self.try_append(table_size, new_values) {
+            panic!("{e}");
+        }
+    }
+
+    /// Like [`append`](Self::append), but fails like
+    /// [`try_decompress`] if `table_size` is invalid or does not match the
+    /// block, or the block is malformed. The block is left as it was then.
+    pub fn try_append(&mut self, table_size: u64, new_values: &[f64]) -> Result<(), DecompressError> {
+        self.check_table_size(table_size)?;
+        validate_table_size(table_size)?;
+        self.check_encoding_len()?;
+        // A lossy block stays equally lossy, as if compressed in one go.
+        let keep_mantissa_bits = lossy::MANTISSA_BITS - self.dropped_mantissa_bits;
+        let canonicalize_nan = self.canonicalize_nan;
+        let prepared = |v: &f64| {
+            let bits = lossy::quantize_bits(v.to_bits(), keep_mantissa_bits);
+            if canonicalize_nan { lossy::canonicalize_nan(bits) } else { bits }
+        };
+        // Blocks of at most one value may turn into constant blocks, and
+        // constant blocks stay constant while the same value is appended.
+        if self.num_bytes_encoded < 2 || self.mode == EncodingMode::Constant {
+            let mut old_error = None;
+            let old_bits = self.iter(table_size)?.map_while(|value| value.map_err(|e| old_error = Some(e)).ok());
+            let bits = uniform_bits(old_bits.map(f64::to_bits).chain(new_values.iter().map(prepared)));
+            if let Some(e) = old_error {
+                return Err(e);
+            }
+            if let Some(bits) = bits {
+                let num_values = self.num_bytes_encoded + new_values.len();
+                *self = FPCCompressedBlock {
+                    num_bytes_encoded: num_values,
+                    mode: EncodingMode::Constant,
+                    encoding: Vec::new(),
+                    residual: bits.to_be_bytes().to_vec(),
+                    ..self.clone()
+                };
+                return Ok(());
             }
-            lzb += 1;
         }
-        let bytes: [u8; 8] = to_encode.to_be_bytes();
-        if lzb == 4 {
-            // If the number of leading bytes is 4, then treat it
-            // as 3 and encode an additional 0 to the residual.
-            residual.extend_from_slice(&bytes[3..]);
-        } else {
-            residual.extend_from_slice(&bytes[lzb as usize..]);
+        // New codes are packed as nibbles, so a compact or constant block
+        // switches to full codes. A compact block keeps its residual, so it
+        // switches only once decoding it succeeded.
+        if self.mode == EncodingMode::Constant {
+            self.switch_to_full_mode();
         }
-        if lzb >= 4 {
-            lzb -= 1;
+        let mut predictors = self.predictors(table_size);
+        let mut residual_index = 0;
+        let mut stage = self.delta_of_delta.then(DeltaOfDelta::default);
+        for (index, code) in self.codes().enumerate() {
+            match decode_value(&mut predictors, code, &self.residual, &mut residual_index) {
+                Some(decoded) => {
+                    if let Some(stage) = &mut stage {
+                        stage.undo(decoded);
+                    }
+                }
+                None => return Err(FpcError::TruncatedResidual { at_value: index, residual_len: self.residual.len() }),
+            }
         }
-        let mask = lzb | (if fcm_diff < dfcm_diff { 1 << 3 } else { 0 });
-        let shift = if i & 1 == 0 { 4 } else { 0 };
-        encoding[i>>1] = encoding[i>>1] | (mask << shift);
+        self.switch_to_full_mode();
+        let pending_code = if self.num_bytes_encoded & 1 != 0 {
+            self.encoding.pop().map(|byte| byte >> 4)
+        } else {
+            None
+        };
+        let bits = new_values.iter().map(|v| {
+            let bits = prepared(v);
+            match &mut stage {
+                Some(stage) => stage.apply(bits),
+                None => bits,
+            }
+        });
+        encode_bits(&mut predictors, bits, (&mut self.encoding, &mut self.residual), pending_code);
+        self.num_bytes_encoded += new_values.len();
+        Ok(())
     }
 }
 
@@ -95,83 +1048,31 @@ pub fn decompress_into(
     blk: &FPCCompressedBlock,
     res: &mut Vec<f64>,
 ) {
+    decompress_span!(table_size, blk);
+    if let Err(e) = blk.check_table_size(table_size).and_then(|()| blk.check_encoding_len()) {
+        panic!("{e}");
+    }
     if blk.num_bytes_encoded == 0 {
         return;
     }
+    check_table_size(table_size);
+    if let Some(bits) = blk.constant_bits() {
+        res.resize(res.len() + blk.num_bytes_encoded, f64::from_bits(bits));
+        return;
+    }
 
-    let mut last_value: u64 = 0;
-    let mut fcm_hash: u64 = 0;
-    let mut fcm: Vec<u64> = vec![0_u64; table_size as usize];
-    let mut dfcm_hash: u64 = 0;
-    let mut dfcm: Vec<u64> = vec![0_u64; table_size as usize];
-
+    let mut predictors = blk.predictors(table_size);
     let mut residual_index: usize = 0;
-    let mut encoded_index: usize = 0;
-    while encoded_index < blk.encoding.len() {
-        let cur_encoding = blk.encoding[encoded_index];
-        let (first_enc, second_enc) = (cur_encoding >> 4, cur_encoding & 0xf);
-
-        let mut is_fcm_predicted;
-        let mut fcm_prediction;
-        let mut dfcm_prediction;
-        let mut num_leading_zero_bytes;
-        let mut decoded;
-
-        fcm_prediction = fcm[fcm_hash as usize];
-        dfcm_prediction = dfcm[dfcm_hash as usize];
-        is_fcm_predicted = first_enc & 0b1000 != 0;
-        num_leading_zero_bytes = first_enc & 0b0111;
-        if num_leading_zero_bytes >= 4 {
-            num_leading_zero_bytes += 1;
-        }
-        decoded = 0;
-        for _ in 0..(8 - num_leading_zero_bytes) {
-            if residual_index >= blk.residual.len() {
-                panic!("not enough residual bytes in the encoding");
-            }
-            decoded = (decoded << 8) | (blk.residual[residual_index] as u64);
-            residual_index += 1;
-        }
-        decoded = decoded ^ (if is_fcm_predicted { fcm_prediction } else { dfcm_prediction.wrapping_add(last_value) });
-        res.push(f64::from_bits(decoded));
-        fcm[fcm_hash as usize] = decoded;
-        fcm_hash = ((fcm_hash << 6) ^ (decoded >> 48)) & (table_size - 1);
-
-        dfcm[dfcm_hash as usize] = decoded.wrapping_sub(last_value);
-        dfcm_hash = ((dfcm_hash << 2) ^ ((decoded.wrapping_sub(last_value)) >> 40)) & (table_size - 1);
-        last_value = decoded;
-
-        // Now decode the second byte
-        if encoded_index == blk.encoding.len()-1 && blk.num_bytes_encoded & 1 != 0 {
-            break;
-        }
-        // todo: remove code duplication
-        fcm_prediction = fcm[fcm_hash as usize];
-        dfcm_prediction = dfcm[dfcm_hash as usize];
-        is_fcm_predicted = second_enc & 0b1000 != 0;
-        num_leading_zero_bytes = second_enc & 0b0111;
-        if num_leading_zero_bytes >= 4 {
-            num_leading_zero_bytes += 1;
-        }
-        decoded = 0;
-        for _ in 0..(8 - num_leading_zero_bytes) {
-            if residual_index >= blk.residual.len() {
-                panic!("not enough residual bytes in the encoding");
-            }
-            decoded = (decoded << 8) | (blk.residual[residual_index] as u64);
-            residual_index += 1;
+    let mut stage = blk.delta_of_delta.then(DeltaOfDelta::default);
+    // codes() already stops before the unused low nibble of an odd count.
+    for code in blk.codes() {
+        match decode_value(&mut predictors, code, &blk.residual, &mut residual_index) {
+            Some(decoded) => res.push(f64::from_bits(match &mut stage {
+                Some(stage) => stage.undo(decoded),
+                None => decoded,
+            })),
+            None => panic!("not enough residual bytes in the encoding"),
         }
-        decoded = decoded ^ (if is_fcm_predicted { fcm_prediction } else { dfcm_prediction.wrapping_add(last_value) });
-        res.push(f64::from_bits(decoded));
-
-        fcm[fcm_hash as usize] = decoded;
-        fcm_hash = ((fcm_hash << 6) ^ (decoded >> 48)) & (table_size - 1);
-
-        dfcm[dfcm_hash as usize] = decoded.wrapping_sub(last_value);
-        dfcm_hash = ((dfcm_hash << 2) ^ ((decoded.wrapping_sub(last_value)) >> 40)) & (table_size - 1);
-        last_value = decoded;
-
-        encoded_index += 1;
     }
 }
 
@@ -181,30 +1082,41 @@ mod compress_decompress_test {
 
     use super::*;
 
-    fn bitwise_compare_vec_f64(a: &Vec<f64>, b: &Vec<f64>) -> bool {
-        a.len() == b.len() &&
-            a.iter().zip(b).all(|(x, y)| x.to_bits() == y.to_bits())
-    }
-
     #[test]
     fn test_compress_even_number_of_zeros() {
         let vals: Vec<f64> = vec![0.0; 16];
-        let compressed = compress(DEFAULT_TABLE_SIZE, &vals);
-        assert_eq!(compressed, FPCCompressedBlock{
+        let compressed = compress(DEFAULT_TABLE_SIZE, &vals).unwrap();
+        assert_eq!(compressed.mode(), EncodingMode::Constant);
+        assert_eq!(*compressed.with_full_codes(), FPCCompressedBlock{
+            table_size: DEFAULT_TABLE_SIZE,
             num_bytes_encoded: vals.len(),
+            mode: EncodingMode::Full,
+            hash_params: HashParams::DEFAULT,
+            dropped_mantissa_bits: 0,
+            delta_of_delta: false,
+            seed: 0,
+            canonicalize_nan: false,
             encoding: vec![0b01110111; 8],
             residual: vec![],
         });
         let decompressed = decompress(DEFAULT_TABLE_SIZE, &compressed);
-        assert!(bitwise_compare_vec_f64(&decompressed, &vals));
+        assert!(bitwise_eq(&decompressed, &vals));
     }
 
     #[test]
     fn test_compress_odd_number_of_zeros() {
         let vals: Vec<f64> = vec![0.0; 15];
-        let compressed = compress(DEFAULT_TABLE_SIZE, &vals);
-        assert_eq!(compressed, FPCCompressedBlock{
+        let compressed = compress(DEFAULT_TABLE_SIZE, &vals).unwrap();
+        assert_eq!(compressed.mode(), EncodingMode::Constant);
+        assert_eq!(*compressed.with_full_codes(), FPCCompressedBlock{
+            table_size: DEFAULT_TABLE_SIZE,
             num_bytes_encoded: vals.len(),
+            mode: EncodingMode::Full,
+            hash_params: HashParams::DEFAULT,
+            dropped_mantissa_bits: 0,
+            delta_of_delta: false,
+            seed: 0,
+            canonicalize_nan: false,
             encoding: vec![
                 0b01110111, 0b01110111, 0b01110111, 0b01110111,
                 0b01110111, 0b01110111, 0b01110111, 0b01110000,
@@ -212,15 +1124,23 @@ mod compress_decompress_test {
             residual: vec![],
         });
         let decompressed = decompress(DEFAULT_TABLE_SIZE, &compressed);
-        assert!(bitwise_compare_vec_f64(&decompressed, &vals));
+        assert!(bitwise_eq(&decompressed, &vals));
     }
 
     #[test]
     fn test_compress_same_positive_value() {
         let vals: Vec<f64> = vec![1.0; 16];
-        let compressed = compress(DEFAULT_TABLE_SIZE, &vals);
-        assert_eq!(compressed, FPCCompressedBlock{
+        let compressed = compress(DEFAULT_TABLE_SIZE, &vals).unwrap();
+        assert_eq!(compressed.mode(), EncodingMode::Constant);
+        assert_eq!(*compressed.with_full_codes(), FPCCompressedBlock{
+            table_size: DEFAULT_TABLE_SIZE,
             num_bytes_encoded: vals.len(),
+            mode: EncodingMode::Full,
+            hash_params: HashParams::DEFAULT,
+            dropped_mantissa_bits: 0,
+            delta_of_delta: false,
+            seed: 0,
+            canonicalize_nan: false,
             encoding: vec![
                 0b00001000, 0b01110111, 0b01110111, 0b01110111,
                 0b01110111, 0b01110111, 0b01110111, 0b01110111,
@@ -228,15 +1148,23 @@ mod compress_decompress_test {
             residual: vec![63, 240, 0, 0, 0, 0, 0, 0, 63, 240, 0, 0, 0, 0, 0, 0],
         });
         let decompressed = decompress(DEFAULT_TABLE_SIZE, &compressed);
-        assert!(bitwise_compare_vec_f64(&decompressed, &vals));
+        assert!(bitwise_eq(&decompressed, &vals));
     }
 
     #[test]
     fn test_compress_same_negative_value() {
         let vals: Vec<f64> = vec![-1.0; 16];
-        let compressed = compress(DEFAULT_TABLE_SIZE, &vals);
-        assert_eq!(compressed, FPCCompressedBlock{
+        let compressed = compress(DEFAULT_TABLE_SIZE, &vals).unwrap();
+        assert_eq!(compressed.mode(), EncodingMode::Constant);
+        assert_eq!(*compressed.with_full_codes(), FPCCompressedBlock{
+            table_size: DEFAULT_TABLE_SIZE,
             num_bytes_encoded: vals.len(),
+            mode: EncodingMode::Full,
+            hash_params: HashParams::DEFAULT,
+            dropped_mantissa_bits: 0,
+            delta_of_delta: false,
+            seed: 0,
+            canonicalize_nan: false,
             encoding: vec![
                 0b00001000, 0b01110111, 0b01110111, 0b01110111,
                 0b01110111, 0b01110111, 0b01110111, 0b01110111,
@@ -244,15 +1172,55 @@ mod compress_decompress_test {
             residual: vec![191, 240, 0, 0, 0, 0, 0, 0, 191, 240, 0, 0, 0, 0, 0, 0],
         });
         let decompressed = decompress(DEFAULT_TABLE_SIZE, &compressed);
-        assert!(bitwise_compare_vec_f64(&decompressed, &vals));
+        assert!(bitwise_eq(&decompressed, &vals));
+    }
+
+    #[test]
+    fn test_repeated_values_make_a_constant_block() {
+        for value in [0.0, -0.0, 1.0, -1.0, f64::NAN, f64::INFINITY] {
+            for len in [2, 15, 16, 1000] {
+                let vals = vec![value; len];
+                let blk = compress(DEFAULT_TABLE_SIZE, &vals).unwrap();
+                assert_eq!(blk, FPCCompressedBlock::constant(DEFAULT_TABLE_SIZE, len, value.to_bits()));
+                assert_eq!(blk.compressed_len(), 8);
+                assert!(bitwise_eq(&decompress(DEFAULT_TABLE_SIZE, &blk), &vals));
+                assert!(bitwise_eq(&try_decompress(DEFAULT_TABLE_SIZE, &blk).unwrap(), &vals));
+                assert_eq!(FPCCompressedBlock::from_bytes(&blk.to_bytes()).unwrap(), blk);
+            }
+        }
+        // A single value and values that only compare equal keep their codes.
+        assert_eq!(compress(DEFAULT_TABLE_SIZE, &[1.0]).unwrap().mode(), EncodingMode::Full);
+        assert_eq!(compress(DEFAULT_TABLE_SIZE, &[0.0, -0.0]).unwrap().mode(), EncodingMode::Full);
+    }
+
+    #[test]
+    fn test_constant_blocks_expand_when_appended_to() {
+        let mut blk = compress(DEFAULT_TABLE_SIZE, &[2.5]).unwrap();
+        blk.append(DEFAULT_TABLE_SIZE, &[2.5, 2.5]);
+        assert_eq!(blk.mode(), EncodingMode::Constant);
+        blk.append(DEFAULT_TABLE_SIZE, &[3.0]);
+        assert_eq!(blk, compress(DEFAULT_TABLE_SIZE, &[2.5, 2.5, 2.5, 3.0]).unwrap());
+        let mut truncated = compress(DEFAULT_TABLE_SIZE, &[2.5; 4]).unwrap();
+        truncated.residual.pop();
+        assert_eq!(
+            try_decompress(DEFAULT_TABLE_SIZE, &truncated),
+            Err(FpcError::ResidualLengthMismatch { expected: 8, actual: 7 })
+        );
     }
 
     #[test]
     fn test_compress_nan_and_infinities() {
         let vals: Vec<f64> = vec![f64::NAN, f64::INFINITY, f64::NEG_INFINITY];
-        let compressed = compress(DEFAULT_TABLE_SIZE, &vals);
+        let compressed = compress(DEFAULT_TABLE_SIZE, &vals).unwrap();
         assert_eq!(compressed, FPCCompressedBlock{
+            table_size: DEFAULT_TABLE_SIZE,
             num_bytes_encoded: 3,
+            mode: EncodingMode::Full,
+            hash_params: HashParams::DEFAULT,
+            dropped_mantissa_bits: 0,
+            delta_of_delta: false,
+            seed: 0,
+            canonicalize_nan: false,
             encoding: vec![8, 0],
             residual: vec![
                 127, 248, 0, 0, 0, 0, 0, 0,
@@ -261,20 +1229,639 @@ mod compress_decompress_test {
             ],
         });
         let decompressed = decompress(DEFAULT_TABLE_SIZE, &compressed);
-        assert!(bitwise_compare_vec_f64(&decompressed, &vals));
+        assert!(bitwise_eq(&decompressed, &vals));
+    }
+
+    #[test]
+    fn test_compress_mix_of_all_float_categories() {
+        let vals: Vec<f64> = vec![
+            1.5,
+            -1234.5678,
+            f64::MIN_POSITIVE / 2.0,               // subnormal
+            f64::from_bits(1),                     // smallest subnormal
+            0.0,
+            -0.0,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::NAN,
+            f64::from_bits(0x7ff0_0000_0000_0001), // signaling NaN
+            f64::from_bits(0xfff8_dead_beef_0001), // negative quiet NaN with payload
+            f64::MAX,
+            f64::MIN,
+            f64::MIN_POSITIVE,
+            -f64::MIN_POSITIVE,
+            f64::EPSILON,
+            1.5,
+        ];
+        for table_size in [1, DEFAULT_TABLE_SIZE, 1024] {
+            let compressed = compress(table_size, &vals).unwrap();
+            let decompressed = decompress(table_size, &compressed);
+            assert!(bitwise_eq(&decompressed, &vals));
+        }
+    }
+
+    #[test]
+    fn test_compress_with_smallest_table_sizes() {
+        // A table of size 1 has a hash mask of 0, so every value hits
+        // the same slot. Size 2 keeps only the lowest bit of the hash.
+        let vals: Vec<f64> = vec![1.0, 2.5, -3.75, 2.5, 0.0, f64::NAN, 1.0, 1e300, -0.0];
+        for table_size in [1, 2] {
+            let compressed = compress(table_size, &vals).unwrap();
+            let decompressed = decompress(table_size, &compressed);
+            assert!(bitwise_eq(&decompressed, &vals));
+        }
+    }
+
+    #[quickcheck]
+    fn compression_must_be_reversible_with_smallest_table_sizes(to_compress: Vec<f64>) -> bool {
+        [1, 2].iter().all(|&table_size| {
+            let compressed = compress(table_size, &to_compress).unwrap();
+            let decompressed = decompress(table_size, &compressed);
+            bitwise_eq(&to_compress, &decompressed)
+        })
+    }
+
+    #[quickcheck]
+    fn custom_hash_params_must_round_trip(to_compress: Vec<f64>, shifts: (u8, u8, u8, u8), split: usize) -> bool {
+        let hash_params = HashParams {
+            fcm_shift: shifts.0 % 64,
+            fcm_fold: shifts.1 % 64,
+            dfcm_shift: shifts.2 % 64,
+            dfcm_fold: shifts.3 % 64,
+        };
+        let options = FpcOptions { hash_params, ..FpcOptions::default() };
+        let blk = compress_with(DEFAULT_TABLE_SIZE, &to_compress, &options).unwrap();
+        let split = split % (to_compress.len() + 1);
+        let mut appended = compress_with(DEFAULT_TABLE_SIZE, &to_compress[..split], &options).unwrap();
+        appended.append(DEFAULT_TABLE_SIZE, &to_compress[split..]);
+        blk.hash_params() == hash_params
+            && bitwise_eq(&decompress(DEFAULT_TABLE_SIZE, &blk), &to_compress)
+            && verify(DEFAULT_TABLE_SIZE, &to_compress, &blk)
+            && appended == blk
+    }
+
+    #[quickcheck]
+    fn tie_break_must_only_flip_selection_bits(to_compress: Vec<f64>) -> bool {
+        let options = FpcOptions { tie_break: TieBreak::PreferFcm, ..FpcOptions::default() };
+        let fcm_ties = compress_with(DEFAULT_TABLE_SIZE, &to_compress, &options).unwrap();
+        let dfcm_ties = compress(DEFAULT_TABLE_SIZE, &to_compress).unwrap();
+        fcm_ties.residual == dfcm_ties.residual
+            && fcm_ties.codes().zip(dfcm_ties.codes()).all(|(a, b)| a & 0b0111 == b & 0b0111 && a >= b)
+            && bitwise_eq(&fcm_ties.decompress(), &to_compress)
+    }
+
+    #[test]
+    fn test_tie_break_decides_repeated_values() {
+        // Both predictors start out predicting 0, so the first value is a tie.
+        let vals = [0.0, 0.0, 1.0, 1.0];
+        let options = FpcOptions { tie_break: TieBreak::PreferFcm, ..FpcOptions::default() };
+        let fcm_ties = compress_with(DEFAULT_TABLE_SIZE, &vals, &options).unwrap();
+        assert_eq!(fcm_ties.codes().next(), Some(0b1111));
+        assert_eq!(compress(DEFAULT_TABLE_SIZE, &vals).unwrap().codes().next(), Some(0b0111));
+    }
+
+    #[test]
+    fn test_hash_params_change_the_predictions() {
+        // The default FCM hash only sees bits 48 and up, which these values
+        // all share, so it cannot tell the cycle apart.
+        let vals: Vec<f64> = (0..200).map(|x| f64::from_bits(0x4000_0000_0000_0000 | (x % 5) << 20)).collect();
+        let default = compress(DEFAULT_TABLE_SIZE, &vals).unwrap();
+        let hash_params = HashParams { fcm_fold: 20, ..HashParams::DEFAULT };
+        let options = FpcOptions { hash_params, ..FpcOptions::default() };
+        let tuned = compress_with(DEFAULT_TABLE_SIZE, &vals, &options).unwrap();
+        assert!(tuned.compressed_len() < default.compressed_len());
+        assert!(bitwise_eq(&tuned.decompress(), &vals));
+
+        let options = FpcOptions { hash_params: HashParams { fcm_shift: 64, ..HashParams::DEFAULT }, ..options };
+        assert_eq!(
+            compress_with(DEFAULT_TABLE_SIZE, &vals, &options),
+            Err(FpcError::InvalidHashParams { hash_params: options.hash_params }),
+        );
+    }
+
+    #[test]
+    fn test_compress_by_field_matches_compress_of_projected_field() {
+        struct SensorReading {
+            temperature: f64,
+            humidity: f64,
+        }
+        let readings: Vec<SensorReading> = (0..25)
+            .map(|i| SensorReading { temperature: 20.0 + (i as f64) * 0.25, humidity: 0.4 + (i % 3) as f64 })
+            .collect();
+        let temperatures: Vec<f64> = readings.iter().map(|r| r.temperature).collect();
+        let humidities: Vec<f64> = readings.iter().map(|r| r.humidity).collect();
+        assert_eq!(
+            compress_by(DEFAULT_TABLE_SIZE, &readings, |r| r.temperature),
+            compress(DEFAULT_TABLE_SIZE, &temperatures),
+        );
+        assert_eq!(
+            compress_by(DEFAULT_TABLE_SIZE, &readings, |r| r.humidity),
+            compress(DEFAULT_TABLE_SIZE, &humidities),
+        );
+        assert_eq!(compress_by(3, &readings, |r| r.humidity), Err(CompressError::InvalidTableSize { given: 3 }));
+    }
+
+    #[test]
+    fn test_residual_sizing_reserves_enough_for_incompressible_data() {
+        // Bit patterns from a xorshift generator look random, so neither
+        // predictor can do better than a few leading zero bytes.
+        let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+        let vals: Vec<f64> = (0..1000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                f64::from_bits(state)
+            })
+            .collect();
+        let exact = compress_with(DEFAULT_TABLE_SIZE, &vals, &FpcOptions { residual_sizing: ResidualSizing::Exact, ..FpcOptions::default() }).unwrap();
+        assert_eq!(exact.residual.capacity(), exact.residual.len());
+        let pessimistic = compress_with(DEFAULT_TABLE_SIZE, &vals, &FpcOptions { residual_sizing: ResidualSizing::Pessimistic, ..FpcOptions::default() }).unwrap();
+        assert_eq!(pessimistic.residual.capacity(), size_of::<f64>() * vals.len());
+        let conservative = compress_with(DEFAULT_TABLE_SIZE, &vals, &FpcOptions { residual_sizing: ResidualSizing::Conservative, ..FpcOptions::default() }).unwrap();
+        assert!(conservative.residual.len() > size_of::<f64>() * vals.len() / 4);
+    }
+
+    #[quickcheck]
+    fn residual_sizing_must_not_change_the_compressed_block(to_compress: Vec<f64>) -> bool {
+        let expected = compress(DEFAULT_TABLE_SIZE, &to_compress).unwrap();
+        [ResidualSizing::Conservative, ResidualSizing::Exact, ResidualSizing::Pessimistic]
+            .into_iter()
+            .all(|residual_sizing| compress_with(DEFAULT_TABLE_SIZE, &to_compress, &FpcOptions { residual_sizing, ..FpcOptions::default() }).unwrap() == expected)
+    }
+
+    #[derive(Default)]
+    struct CountingSink {
+        encoding_bytes: usize,
+        residual_bytes: usize,
+    }
+
+    impl EncodingSink for CountingSink {
+        fn push_encoding(&mut self, _byte: u8) {
+            self.encoding_bytes += 1;
+        }
+
+        fn push_residual(&mut self, bytes: &[u8]) {
+            self.residual_bytes += bytes.len();
+        }
+    }
+
+    #[quickcheck]
+    fn custom_sink_must_receive_as_many_bytes_as_the_vector_sink(to_compress: Vec<f64>) -> bool {
+        let mut counter = CountingSink::default();
+        compress_into(DEFAULT_TABLE_SIZE, &to_compress, &mut counter).unwrap();
+        let (mut encoding, mut residual) = (Vec::new(), Vec::new());
+        compress_into(DEFAULT_TABLE_SIZE, &to_compress, (&mut encoding, &mut residual)).unwrap();
+        counter.encoding_bytes == encoding.len() && counter.residual_bytes == residual.len()
+    }
+
+    #[test]
+    fn test_decompress_rejects_odd_count_with_empty_encoding() {
+        let blk = FPCCompressedBlock {
+            table_size: DEFAULT_TABLE_SIZE,
+            num_bytes_encoded: 3,
+            mode: EncodingMode::Full,
+            hash_params: HashParams::DEFAULT,
+            dropped_mantissa_bits: 0,
+            delta_of_delta: false,
+            seed: 0,
+            canonicalize_nan: false,
+            encoding: vec![],
+            residual: vec![],
+        };
+        assert_eq!(
+            try_decompress(DEFAULT_TABLE_SIZE, &blk),
+            Err(FpcError::InconsistentBlock { num_values: 3, encoding_len: 0 }),
+        );
+        let short_encoding = FPCCompressedBlock { encoding: vec![0b01110111], ..blk };
+        assert_eq!(
+            try_decompress(DEFAULT_TABLE_SIZE, &short_encoding),
+            Err(FpcError::InconsistentBlock { num_values: 3, encoding_len: 1 }),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "block of 1 values cannot have 0 encoding bytes")]
+    fn test_decompress_panics_on_inconsistent_block() {
+        let blk = FPCCompressedBlock {
+            table_size: DEFAULT_TABLE_SIZE,
+            num_bytes_encoded: 1,
+            mode: EncodingMode::Full,
+            hash_params: HashParams::DEFAULT,
+            dropped_mantissa_bits: 0,
+            delta_of_delta: false,
+            seed: 0,
+            canonicalize_nan: false,
+            encoding: vec![],
+            residual: vec![],
+        };
+        decompress(DEFAULT_TABLE_SIZE, &blk);
+    }
+
+    #[test]
+    fn test_try_decompress_into_reports_truncated_residual() {
+        let vals: Vec<f64> = (0..10).map(|x| (x as f64).sqrt()).collect();
+        let mut blk = compress(DEFAULT_TABLE_SIZE, &vals).unwrap();
+        let at_value = blk.codes().scan(0, |len, code| {
+            *len += residual_len_of_code(code);
+            Some(*len)
+        }).position(|len| len > blk.residual.len() - 3).unwrap();
+        blk.residual.truncate(blk.residual.len() - 3);
+        let residual_len = blk.residual.len();
+        let expected = Err(DecompressError::TruncatedResidual { at_value, residual_len });
+        assert_eq!(try_decompress(DEFAULT_TABLE_SIZE, &blk), expected);
+
+        let mut res = vec![-1.0];
+        assert_eq!(try_decompress_into(DEFAULT_TABLE_SIZE, &blk, &mut res), expected.map(|_| ()));
+        assert_eq!(res, vec![-1.0]);
+    }
+
+    #[quickcheck]
+    fn try_decompress_into_must_append_to_res(to_compress: Vec<f64>) -> bool {
+        let blk = compress(DEFAULT_TABLE_SIZE, &to_compress).unwrap();
+        let mut res = vec![1.0];
+        try_decompress_into(DEFAULT_TABLE_SIZE, &blk, &mut res).unwrap();
+        res.len() == to_compress.len() + 1
+            && res[1..].iter().zip(&to_compress).all(|(a, b)| a.to_bits() == b.to_bits())
+    }
+
+    #[quickcheck]
+    fn accessors_must_match_the_serialized_layout(to_compress: Vec<f64>) -> bool {
+        let blk = compress(DEFAULT_TABLE_SIZE, &to_compress).unwrap();
+        let bytes = blk.to_bytes();
+        let layout = layout(&bytes).unwrap();
+        blk.num_values() == to_compress.len()
+            && blk.encoding() == &bytes[layout.encoding]
+            && blk.residual() == &bytes[layout.residual]
+            && blk.compressed_len() == bytes.len() - format::HEADER_LEN
+    }
+
+    #[test]
+    fn test_compression_ratio_and_bytes_per_value() {
+        let blk = compress(DEFAULT_TABLE_SIZE, &vec![1.0; 100]).unwrap();
+        let len = blk.compressed_len() as f64;
+        assert!(len < 800.0);
+        assert_eq!(blk.compression_ratio(), 800.0 / len);
+        assert_eq!(blk.bytes_per_value(), len / 100.0);
+
+        let empty = compress(DEFAULT_TABLE_SIZE, &[]).unwrap();
+        assert_eq!(empty.compression_ratio(), 0.0);
+        assert_eq!(empty.bytes_per_value(), 0.0);
+    }
+
+    #[quickcheck]
+    fn compress_iter_must_match_compress(to_compress: Vec<f64>) -> bool {
+        // Filtering hides the length from the size hint.
+        let lazy = to_compress.iter().copied().filter(|_| true);
+        compress_iter(DEFAULT_TABLE_SIZE, lazy).as_ref() == Ok(&compress(DEFAULT_TABLE_SIZE, &to_compress).unwrap())
+    }
+
+    #[test]
+    fn test_compress_iter_of_generated_values() {
+        let blk = compress_iter(DEFAULT_TABLE_SIZE, (0..1001).map(|x| x as f64 * 0.5)).unwrap();
+        assert_eq!(blk.num_values(), 1001);
+        assert_eq!(blk.decompress(), (0..1001).map(|x| x as f64 * 0.5).collect::<Vec<_>>());
+        assert_eq!(compress_iter(3, [1.0]), Err(FpcError::InvalidTableSize { given: 3 }));
+    }
+
+    /// Claims far more values than it yields.
+    struct Boastful(core::ops::Range<u32>);
+
+    impl Iterator for Boastful {
+        type Item = f64;
+
+        fn next(&mut self) -> Option<f64> {
+            self.0.next().map(f64::from)
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (usize::MAX, None)
+        }
+    }
+
+    #[test]
+    fn test_huge_size_hints_do_not_overflow_the_capacity() {
+        let blk = compress_iter(DEFAULT_TABLE_SIZE, Boastful(0..100)).unwrap();
+        assert_eq!(blk, compress(DEFAULT_TABLE_SIZE, &(0..100).map(f64::from).collect::<Vec<_>>()).unwrap());
+        assert_eq!(residual_capacity(usize::MAX), usize::MAX / 4);
+    }
+
+    #[test]
+    fn test_compress_accepts_arrays_and_subslices() {
+        let array = [0.5, 1.5, 2.5, 3.5, 4.5, 5.5];
+        let blk = compress(DEFAULT_TABLE_SIZE, &array[1..4]).unwrap();
+        assert_eq!(blk, compress(DEFAULT_TABLE_SIZE, &[1.5, 2.5, 3.5]).unwrap());
+        let (mut encoding, mut residual) = (Vec::new(), Vec::new());
+        compress_into(DEFAULT_TABLE_SIZE, &array, (&mut encoding, &mut residual)).unwrap();
+        assert_eq!(decompress(DEFAULT_TABLE_SIZE, &compress(DEFAULT_TABLE_SIZE, &array).unwrap()), array);
+    }
+
+    #[quickcheck]
+    fn decompress_into_slice_must_fill_the_prefix(to_compress: Vec<f64>) -> bool {
+        let blk = compress(DEFAULT_TABLE_SIZE, &to_compress).unwrap();
+        let mut out = vec![7.0; to_compress.len() + 2];
+        decompress_into_slice(DEFAULT_TABLE_SIZE, &blk, &mut out) == Ok(to_compress.len())
+            && bitwise_eq(&out[..to_compress.len()], &to_compress)
+            && out[to_compress.len()..] == [7.0, 7.0]
+    }
+
+    #[test]
+    fn test_decompress_into_slice_rejects_short_output() {
+        let blk = compress(DEFAULT_TABLE_SIZE, &[1.0, 2.0, 3.0]).unwrap();
+        let mut out = [0.0; 2];
+        assert_eq!(
+            decompress_into_slice(DEFAULT_TABLE_SIZE, &blk, &mut out),
+            Err(DecompressError::OutputTooSmall { needed: 3, available: 2 }),
+        );
+        assert_eq!(out, [0.0; 2]);
+    }
+
+    #[test]
+    fn test_equal_blocks_hash_alike() {
+        let vals: Vec<f64> = (0..50).map(|x| x as f64 * 1.5).collect();
+        let blk = compress(DEFAULT_TABLE_SIZE, &vals).unwrap();
+        let mut blocks = std::collections::HashSet::new();
+        assert!(blocks.insert(blk.clone()));
+        assert!(!blocks.insert(compress(DEFAULT_TABLE_SIZE, &vals).unwrap()));
+        assert!(blocks.insert(compress(DEFAULT_TABLE_SIZE, &vals[1..]).unwrap()));
+        assert_eq!(blocks.len(), 2);
+        assert!(blocks.contains(&blk));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_json_round_trip() {
+        let vals = vec![1.0, 2.5, -3.75, 2.5, 0.0, f64::NAN, 1.0, 1e300, -0.0];
+        let blk = compress(DEFAULT_TABLE_SIZE, &vals).unwrap();
+        let json = serde_json::to_string(&blk).unwrap();
+        let parsed: FPCCompressedBlock = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, blk);
+        assert!(bitwise_eq(&decompress(DEFAULT_TABLE_SIZE, &parsed), &vals));
+    }
+
+    #[test]
+    fn test_decompress_odd_count_ending_in_dfcm_value() {
+        // A constant stride is predicted exactly by DFCM once it has seen
+        // the stride, so the final, unpaired value selects DFCM.
+        let vals: Vec<f64> = (0..7).map(|x| f64::from_bits(0x4000_0000_0000_0000 + x * 3)).collect();
+        let blk = compress(DEFAULT_TABLE_SIZE, &vals).unwrap();
+        assert_eq!(blk.codes().last().unwrap() & 0b1000, 0);
+        assert_eq!(blk.encoding.last().unwrap() & 0xf, 0);
+        assert!(bitwise_eq(&decompress(DEFAULT_TABLE_SIZE, &blk), &vals));
+    }
+
+    #[test]
+    #[should_panic(expected = "not enough residual bytes in the encoding")]
+    fn test_decompress_panics_on_truncated_residual() {
+        let mut blk = compress(DEFAULT_TABLE_SIZE, &[1.0, 2.0, 3.0]).unwrap();
+        blk.residual.pop();
+        decompress(DEFAULT_TABLE_SIZE, &blk);
+    }
+
+    #[test]
+    fn test_encode_value_counts_leading_zero_bytes() {
+        for bits in (0..64).map(|shift| 1_u64 << shift).chain([0, u64::MAX, 0x00ff_0000_0000_0000]) {
+            let zero_bytes = (bits.leading_zeros() / 8) as u8;
+            let (code, to_encode, residual_len) = encode_value(0, 0, bits);
+            assert_eq!(to_encode, bits);
+            assert_eq!(code & 0b0111, if zero_bytes >= 4 { zero_bytes - 1 } else { zero_bytes }, "{bits:#x}");
+            assert_eq!(residual_len, if zero_bytes == 4 { 5 } else { 8 - zero_bytes as usize }, "{bits:#x}");
+            assert_eq!(residual_len_of_code(code), residual_len);
+        }
+    }
+
+    #[test]
+    fn test_encoding_of_all_ones_and_nan_is_pinned() {
+        // All ones differs from the zeroed tables in every byte and goes to
+        // DFCM on the tie. After that DFCM predicts all ones again, while
+        // FCM predicts 0, which is closer since the NaN's sign bit is clear.
+        let blk = compress(DEFAULT_TABLE_SIZE, &[f64::from_bits(u64::MAX), f64::NAN]).unwrap();
+        assert_eq!(blk.encoding, [0x08]);
+        assert_eq!(blk.residual, [[0xff; 8], f64::NAN.to_bits().to_be_bytes()].concat());
+    }
+
+    #[test]
+    fn test_verify_compares_bitwise() {
+        let vals = [f64::from_bits(0x7ff8_0000_0000_0001), 0.0, 1.5];
+        let blk = compress(DEFAULT_TABLE_SIZE, &vals).unwrap();
+        assert!(verify(DEFAULT_TABLE_SIZE, &vals, &blk));
+        assert!(!verify(DEFAULT_TABLE_SIZE, &[f64::NAN, 0.0, 1.5], &blk));
+        assert!(!verify(DEFAULT_TABLE_SIZE, &[vals[0], -0.0, 1.5], &blk));
+        assert!(!verify(DEFAULT_TABLE_SIZE, &vals[..2], &blk));
+        assert!(!verify(64, &vals, &blk));
+    }
+
+    #[test]
+    fn test_decompress_checks_block_table_size() {
+        let vals = [1.0, 2.5, -3.75, 1e300];
+        let blk = compress(64, &vals).unwrap();
+        assert_eq!(blk.table_size(), 64);
+        assert_eq!(blk.decompress(), vals);
+        let expected = FpcError::TableSizeMismatch { block: 64, given: 32 };
+        assert_eq!(try_decompress(32, &blk), Err(expected.clone()));
+        assert_eq!(try_decompress_into(32, &blk, &mut vec![]), Err(expected.clone()));
+        assert_eq!(decompress_into_slice(32, &blk, &mut [0.0; 4]), Err(expected));
+    }
+
+    #[test]
+    #[should_panic(expected = "block was compressed with table size 64, not 32")]
+    fn test_decompress_panics_on_table_size_mismatch() {
+        decompress(32, &compress(64, &[1.0, 2.0]).unwrap());
+    }
+
+    #[test]
+    fn test_table_size_validation_and_recommendation() {
+        assert!([1, 2, 32, MAX_TABLE_SIZE].into_iter().all(is_valid_table_size));
+        assert!(![0, 3, 48, MAX_TABLE_SIZE << 1, 1 << 40, u64::MAX].into_iter().any(is_valid_table_size));
+        assert_eq!(recommended_table_size(0), 32);
+        assert_eq!(recommended_table_size(1000), 64);
+        assert_eq!(recommended_table_size(1 << 20), 65536);
+        assert_eq!(recommended_table_size(usize::MAX), 65536);
+        for num_values in [0, 1, 17, 513, 100_000, 1 << 30] {
+            assert!(is_valid_table_size(recommended_table_size(num_values)));
+        }
+    }
+
+    #[test]
+    fn test_compress_rejects_invalid_table_sizes() {
+        for table_size in [0, 3, 48] {
+            let expected = Err(CompressError::InvalidTableSize { given: table_size });
+            assert_eq!(compress(table_size, &[1.0, 2.0]), expected);
+            let (mut encoding, mut residual) = (Vec::new(), Vec::new());
+            assert_eq!(compress_into(table_size, &[1.0, 2.0], (&mut encoding, &mut residual)), expected.map(|_| ()));
+            assert!(encoding.is_empty() && residual.is_empty());
+        }
+        let (mut encoding, mut residual) = (Vec::new(), Vec::new());
+        assert_eq!(compress_into(DEFAULT_TABLE_SIZE, &[], (&mut encoding, &mut residual)), Ok(()));
+    }
+
+    #[test]
+    #[should_panic(expected = "table size must be a multiple of 2")]
+    fn test_compress_unchecked_panics_on_invalid_table_size() {
+        compress_unchecked(3, &[1.0]);
+    }
+
+    #[test]
+    fn test_compress_debug_dumps_final_predictor_tables() {
+        let (a, b) = (0x0001_0000_0000_0005_u64, 0x0002_0000_0000_0007_u64);
+        let vals: Vec<f64> = [a, b, a].iter().map(|&bits| f64::from_bits(bits)).collect();
+        let (blk, fcm, dfcm) = compress_debug(4, &vals).unwrap();
+        assert_eq!(blk, compress(4, &vals).unwrap());
+        // FCM hashes visit slots 0, 1 and 2, keyed by the top 16 bits.
+        assert_eq!(fcm, vec![a, b, a, 0]);
+        // Both deltas hash to slot 0, so only the last one (a - b) survives.
+        assert_eq!(dfcm, vec![a.wrapping_sub(b), 0, 0, 0]);
+        // A constant block still reports the tables of every value.
+        let (blk, fcm, _) = compress_debug(4, &[f64::from_bits(a); 3]).unwrap();
+        assert_eq!(blk.mode(), EncodingMode::Constant);
+        assert_eq!(fcm, vec![a, a, 0, 0]);
+        assert_eq!(compress_debug(3, &vals), Err(CompressError::InvalidTableSize { given: 3 }));
+    }
+
+    #[quickcheck]
+    fn compress_debug_must_produce_the_compressed_block(to_compress: Vec<f64>) -> bool {
+        let (blk, fcm, dfcm) = compress_debug(DEFAULT_TABLE_SIZE, &to_compress).unwrap();
+        let mut predictors = Predictors::new(DEFAULT_TABLE_SIZE);
+        to_compress.iter().for_each(|value| predictors.update(value.to_bits()));
+        blk == compress(DEFAULT_TABLE_SIZE, &to_compress).unwrap() && fcm == predictors.fcm && dfcm == predictors.dfcm
+    }
+
+    #[quickcheck]
+    fn appending_must_match_compressing_everything_at_once(a: Vec<f64>, b: Vec<f64>) -> bool {
+        let mut blk = compress(DEFAULT_TABLE_SIZE, &a).unwrap();
+        blk.append(DEFAULT_TABLE_SIZE, &b);
+        blk == compress(DEFAULT_TABLE_SIZE, &[a, b].concat()).unwrap()
+    }
+
+    #[test]
+    fn test_validate_reports_inconsistent_parts() {
+        let (num_values, encoding, residual) = compress(DEFAULT_TABLE_SIZE, &[1.0, 2.5, 3.0]).unwrap().into_parts();
+        let from_parts = |table_size, num_values, encoding: &[u8], residual: &[u8]| {
+            FPCCompressedBlock::from_parts(table_size, num_values, encoding.to_vec(), residual.to_vec())
+        };
+        assert!(from_parts(DEFAULT_TABLE_SIZE, num_values, &encoding, &residual).is_ok_and(|blk| blk.validate().is_ok()));
+        assert_eq!(
+            from_parts(DEFAULT_TABLE_SIZE, 5, &encoding, &residual),
+            Err(FpcError::InconsistentBlock { num_values: 5, encoding_len: 2 })
+        );
+        assert_eq!(
+            from_parts(DEFAULT_TABLE_SIZE, num_values, &encoding, &residual[1..]),
+            Err(FpcError::ResidualLengthMismatch { expected: residual.len(), actual: residual.len() - 1 })
+        );
+        assert_eq!(from_parts(3, num_values, &encoding, &residual), Err(FpcError::InvalidTableSize { given: 3 }));
+
+        let blk = compress(DEFAULT_TABLE_SIZE, &[1.0, 2.5, 3.0]).unwrap();
+        let mut bytes = blk.to_bytes();
+        let residual_len = layout(&bytes).unwrap().residual_len;
+        bytes[residual_len].copy_from_slice(&(blk.residual().len() as u64 - 1).to_le_bytes());
+        bytes.pop();
+        assert_eq!(
+            FPCCompressedBlock::from_bytes(&bytes),
+            Err(FpcError::ResidualLengthMismatch { expected: blk.residual().len(), actual: blk.residual().len() - 1 })
+        );
+    }
+
+    #[quickcheck]
+    fn parts_must_round_trip(to_compress: Vec<f64>) -> bool {
+        let blk = compress(DEFAULT_TABLE_SIZE, &to_compress).unwrap();
+        let expected = blk.with_full_codes().into_owned();
+        let expanded = blk.mode() == EncodingMode::Constant;
+        let (encoding_ptr, residual_ptr) = (blk.encoding.as_ptr(), blk.residual.as_ptr());
+        let (num_values, encoding, residual) = blk.into_parts();
+        // The streams are moved out, not copied, unless a constant block
+        // has to be expanded into codes.
+        (expanded || (encoding.as_ptr() == encoding_ptr && residual.as_ptr() == residual_ptr))
+            && num_values == to_compress.len()
+            && FPCCompressedBlock::from_parts(DEFAULT_TABLE_SIZE, num_values, encoding, residual) == Ok(expected)
+    }
+
+    #[test]
+    fn test_try_append_reports_malformed_blocks() {
+        let mut single = compress(DEFAULT_TABLE_SIZE, &[1.5]).unwrap();
+        single.residual.pop();
+        let before = single.clone();
+        let expected = Err(FpcError::TruncatedResidual { at_value: 0, residual_len: before.residual.len() });
+        assert_eq!(single.try_append(DEFAULT_TABLE_SIZE, &[1.5, 1.5]), expected);
+        assert_eq!(single, before);
+
+        let mut blk = compress_compact(DEFAULT_TABLE_SIZE, &encoding_mode::encoding_mode_test::noisy(20)).unwrap();
+        blk.residual.truncate(10);
+        let before = blk.clone();
+        assert!(matches!(blk.try_append(DEFAULT_TABLE_SIZE, &[2.0]), Err(FpcError::TruncatedResidual { .. })));
+        assert_eq!(blk, before);
+        assert_eq!(blk.try_append(64, &[2.0]), Err(FpcError::TableSizeMismatch { block: DEFAULT_TABLE_SIZE, given: 64 }));
+    }
+
+    #[test]
+    fn test_append_across_a_half_filled_encoding_byte() {
+        let vals: Vec<f64> = (0..11).map(|x| (x as f64) * 1.5).collect();
+        let mut blk = compress(DEFAULT_TABLE_SIZE, &vals[..5]).unwrap();
+        blk.append(DEFAULT_TABLE_SIZE, &vals[5..6]);
+        blk.append(DEFAULT_TABLE_SIZE, &[]);
+        blk.append(DEFAULT_TABLE_SIZE, &vals[6..]);
+        assert_eq!(blk, compress(DEFAULT_TABLE_SIZE, &vals).unwrap());
+        assert_eq!(decompress(DEFAULT_TABLE_SIZE, &blk), vals);
     }
 
     #[quickcheck]
     fn compression_must_be_reversible(to_compress: Vec<f64>) -> bool {
-        let compressed = compress(DEFAULT_TABLE_SIZE, &to_compress);
+        let compressed = compress(DEFAULT_TABLE_SIZE, &to_compress).unwrap();
         let decompressed = decompress(DEFAULT_TABLE_SIZE, &compressed);
-        bitwise_compare_vec_f64(&to_compress, &decompressed)
+        bitwise_eq(&to_compress, &decompressed)
     }
 
     #[quickcheck]
     fn compression_must_output_block_with_proper_header_and_leading_zero_encoding(to_compress: Vec<f64>) -> bool {
-        let compressed = compress(DEFAULT_TABLE_SIZE, &to_compress);
+        let compressed = compress(DEFAULT_TABLE_SIZE, &to_compress).unwrap();
         compressed.num_bytes_encoded == to_compress.len() &&
-            compressed.encoding.len() == (to_compress.len()+1)/2
+            compressed.encoding.len() == to_compress.len().div_ceil(2)
+    }
+
+    #[quickcheck]
+    fn decompress_for_each_must_see_every_value(to_compress: Vec<f64>) -> bool {
+        let blk = compress(DEFAULT_TABLE_SIZE, &to_compress).unwrap();
+        let mut seen = Vec::new();
+        decompress_for_each(DEFAULT_TABLE_SIZE, &blk, |v| seen.push(v)).is_ok() && bitwise_eq(&seen, &to_compress)
+    }
+
+    #[test]
+    fn test_decompress_for_each_reports_truncated_residual() {
+        let vals: Vec<f64> = (0..100).map(|x| (x as f64).sqrt()).collect();
+        let mut blk = compress(DEFAULT_TABLE_SIZE, &vals).unwrap();
+        let mut sum = 0.0;
+        decompress_for_each(DEFAULT_TABLE_SIZE, &blk, |v| sum += v).unwrap();
+        assert_eq!(sum, vals.iter().sum::<f64>());
+        blk.residual.truncate(blk.residual.len() / 2);
+        let mut count = 0;
+        let result = decompress_for_each(DEFAULT_TABLE_SIZE, &blk, |_| count += 1);
+        assert!(matches!(result, Err(FpcError::TruncatedResidual { at_value, .. }) if at_value == count));
+        assert!(decompress_for_each(64, &blk, |_| ()).is_err());
+    }
+
+    #[quickcheck]
+    fn seeded_blocks_must_round_trip(to_compress: Vec<f64>, seed: f64, split: usize) -> bool {
+        let blk = compress_with_seed(DEFAULT_TABLE_SIZE, &to_compress, seed).unwrap();
+        let split = if to_compress.is_empty() { 0 } else { split % to_compress.len() };
+        let mut appended = compress_with_seed(DEFAULT_TABLE_SIZE, &to_compress[..split], seed).unwrap();
+        appended.append(DEFAULT_TABLE_SIZE, &to_compress[split..]);
+        let iterated: Vec<f64> = blk.iter(DEFAULT_TABLE_SIZE).unwrap().collect::<Result<_, _>>().unwrap();
+        blk.seed().to_bits() == seed.to_bits()
+            && bitwise_eq(&blk.decompress(), &to_compress)
+            && bitwise_eq(&iterated, &to_compress)
+            && appended == blk
+            && transcode(DEFAULT_TABLE_SIZE, 64, &blk) == compress_with_seed(64, &to_compress, seed)
+            && FPCCompressedBlock::from_bytes(&blk.to_bytes()).as_ref() == Ok(&blk)
+    }
+
+    #[test]
+    fn test_seed_close_to_the_first_value_shrinks_its_residual() {
+        let vals: Vec<f64> = (0..4).map(|x| 1e9 + x as f64).collect();
+        let unseeded = compress(DEFAULT_TABLE_SIZE, &vals).unwrap();
+        assert_eq!(compress_with_seed(DEFAULT_TABLE_SIZE, &vals, 0.0), Ok(unseeded.clone()));
+        assert_eq!(unseeded.seed(), 0.0);
+        let seeded = compress_with_seed(DEFAULT_TABLE_SIZE, &vals, 1e9).unwrap();
+        assert!(seeded.residual().len() < unseeded.residual().len());
+        assert_eq!(seeded.decompress(), vals);
+        assert_eq!(compress_with_seed(3, &vals, 1e9), Err(FpcError::InvalidTableSize { given: 3 }));
     }
 }
\ No newline at end of file
@@ -0,0 +1,226 @@
+use alloc::vec::Vec;
+
+use crate::encoding_mode::uniform_bits;
+use crate::{
+    compress_bits_into, residual_len, validate_table_size, CompressError, EncodingMode, FPCCompressedBlock, HashParams,
+};
+
+pub(crate) const MANTISSA_BITS: u8 = 52;
+
+/// Truncates the mantissa of `bits` to its top `keep_mantissa_bits` bits.
+/// NaNs are returned untouched because clearing their payload could turn
+/// them into infinities.
+pub(crate) fn quantize_bits(bits: u64, keep_mantissa_bits: u8) -> u64 {
+    if keep_mantissa_bits >= MANTISSA_BITS || f64::from_bits(bits).is_nan() {
+        return bits;
+    }
+    let dropped = MANTISSA_BITS - keep_mantissa_bits;
+    bits & !((1_u64 << dropped) - 1)
+}
+
+/// Replaces the bits of any NaN by those of `f64::NAN`, for
+/// [`FpcOptions::canonicalize_nan`](crate::FpcOptions::canonicalize_nan).
+pub(crate) fn canonicalize_nan(bits: u64) -> u64 {
+    if f64::from_bits(bits).is_nan() {
+        f64::NAN.to_bits()
+    } else {
+        bits
+    }
+}
+
+/// Compressed size of `values` in bytes as stored, and after truncating
+/// every mantissa to `keep_mantissa_bits` bits, returned as
+/// `(bytes_lossless, bytes_quantized)`. Neither block is materialized.
+/// Values that are or become all the same count as the 8 bytes of a
+/// constant block. Fails like [`compress`](crate::compress) on an invalid
+/// table size.
+pub fn quantization_savings(
+    table_size: u64,
+    values: &[f64],
+    keep_mantissa_bits: u8,
+) -> Result<(usize, usize), CompressError> {
+    validate_table_size(table_size)?;
+    let lossless = compressed_len_of(table_size, values.iter().map(|v| v.to_bits()));
+    let quantized = if keep_mantissa_bits >= MANTISSA_BITS {
+        lossless
+    } else {
+        compressed_len_of(table_size, values.iter().map(|v| quantize_bits(v.to_bits(), keep_mantissa_bits)))
+    };
+    Ok((lossless, quantized))
+}
+
+/// Bytes of the encoding and residual [`compress`](crate::compress) would
+/// produce for `bits`.
+fn compressed_len_of(table_size: u64, bits: impl Iterator<Item = u64> + Clone) -> usize {
+    if uniform_bits(bits.clone()).is_some() {
+        return size_of::<u64>();
+    }
+    bits.clone().count().div_ceil(2) + residual_len(table_size, HashParams::DEFAULT, bits)
+}
+
+/// Compresses `values` after truncating every mantissa to its top
+/// `keep_mantissa_bits` bits, which turns the noise in the low bits into
+/// long runs of zeros in the residual. More than 52 bits keeps the values
+/// exact. The block records how many bits were dropped; see
+/// [`FPCCompressedBlock::dropped_mantissa_bits`] for the error bound.
+/// NaNs, infinities and zeros come back exactly.
+pub fn compress_lossy(
+    table_size: u64,
+    values: &[f64],
+    keep_mantissa_bits: u8,
+) -> Result<FPCCompressedBlock, CompressError> {
+    validate_table_size(table_size)?;
+    let keep_mantissa_bits = keep_mantissa_bits.min(MANTISSA_BITS);
+    if let Some(bits) = uniform_bits(values.iter().map(|v| quantize_bits(v.to_bits(), keep_mantissa_bits))) {
+        return Ok(FPCCompressedBlock {
+            dropped_mantissa_bits: MANTISSA_BITS - keep_mantissa_bits,
+            ..FPCCompressedBlock::constant(table_size, values.len(), bits)
+        });
+    }
+    let mut encoding = Vec::with_capacity(values.len().div_ceil(2));
+    let mut residual = Vec::with_capacity(size_of_val(values) / 4);
+    if !values.is_empty() {
+        let bits = values.iter().map(|v| quantize_bits(v.to_bits(), keep_mantissa_bits));
+        compress_bits_into(table_size, HashParams::DEFAULT, bits, (&mut encoding, &mut residual));
+    }
+    Ok(FPCCompressedBlock {
+        table_size,
+        num_bytes_encoded: values.len(),
+        mode: EncodingMode::Full,
+        hash_params: HashParams::DEFAULT,
+        dropped_mantissa_bits: MANTISSA_BITS - keep_mantissa_bits,
+        delta_of_delta: false,
+        seed: 0,
+        canonicalize_nan: false,
+        encoding,
+        residual,
+    })
+}
+
+#[cfg(test)]
+mod lossy_test {
+    use quickcheck_macros::quickcheck;
+
+    use crate::{bitwise_eq, compress, compress_with, decompress, FpcError, FpcOptions, DEFAULT_TABLE_SIZE};
+
+    use super::*;
+
+    #[test]
+    fn test_quantize_bits_keeps_nan_and_leading_mantissa_bits() {
+        let one_third = (1.0_f64 / 3.0).to_bits();
+        assert_eq!(quantize_bits(one_third, 52), one_third);
+        assert_eq!(f64::from_bits(quantize_bits(one_third, 0)), 0.25);
+        assert_eq!(f64::from_bits(quantize_bits(one_third, 2)), 0.3125);
+        let signaling_nan = 0x7ff0_0000_0000_0001;
+        assert_eq!(quantize_bits(signaling_nan, 0), signaling_nan);
+    }
+
+    #[test]
+    fn test_quantization_savings_of_already_coarse_data_is_zero() {
+        let vals: Vec<f64> = (0..200).map(|x| (x % 17) as f64 * 0.5).collect();
+        let (lossless, quantized) = quantization_savings(DEFAULT_TABLE_SIZE, &vals, 20).unwrap();
+        assert_eq!(lossless, quantized);
+    }
+
+    #[test]
+    fn test_quantization_savings_count_constant_blocks() {
+        assert_eq!(quantization_savings(DEFAULT_TABLE_SIZE, &[1.25; 1000], 20), Ok((8, 8)));
+        let nearly_constant: Vec<f64> = (0..1000).map(|x| 1.25 + x as f64 * 1e-12).collect();
+        let (lossless, quantized) = quantization_savings(DEFAULT_TABLE_SIZE, &nearly_constant, 10).unwrap();
+        assert!(lossless > 8);
+        assert_eq!(quantized, compress_lossy(DEFAULT_TABLE_SIZE, &nearly_constant, 10).unwrap().compressed_len());
+        assert_eq!(quantized, 8);
+        assert_eq!(quantization_savings(DEFAULT_TABLE_SIZE, &[], 10), Ok((0, 0)));
+        assert_eq!(quantization_savings(3, &[1.0], 10), Err(FpcError::InvalidTableSize { given: 3 }));
+    }
+
+    #[test]
+    fn test_quantization_savings_shrink_with_fewer_mantissa_bits() {
+        let vals: Vec<f64> = (0..1000).map(|x| 100.0 + (x as f64 * 0.37).sin()).collect();
+        let (lossless, _) = quantization_savings(DEFAULT_TABLE_SIZE, &vals, 52).unwrap();
+        let mut previous = lossless;
+        for keep in [48, 40, 32, 24, 16, 8, 4, 0] {
+            let (_, quantized) = quantization_savings(DEFAULT_TABLE_SIZE, &vals, keep).unwrap();
+            assert!(quantized <= previous, "{keep} bits: {quantized} > {previous}");
+            previous = quantized;
+        }
+        assert!(previous < lossless);
+    }
+
+    #[quickcheck]
+    fn lossy_error_must_be_bounded_by_dropped_bits(to_compress: Vec<f64>, keep: u8) -> bool {
+        let keep = keep % 60;
+        let blk = compress_lossy(DEFAULT_TABLE_SIZE, &to_compress, keep).unwrap();
+        let dropped = blk.dropped_mantissa_bits() as i32;
+        let decompressed = decompress(DEFAULT_TABLE_SIZE, &blk);
+        dropped == 52 - keep.min(52) as i32
+            && decompressed.len() == to_compress.len()
+            && to_compress.iter().zip(&decompressed).all(|(&original, &lossy)| {
+                if !original.is_finite() || original.to_bits() == lossy.to_bits() {
+                    return original.to_bits() == lossy.to_bits();
+                }
+                // Truncation moves toward zero and never crosses it.
+                let error = (original - lossy).abs();
+                lossy.abs() < original.abs()
+                    && lossy.signum() == original.signum()
+                    && if original.is_normal() {
+                        error / original.abs() < 2_f64.powi(dropped - 52)
+                    } else {
+                        error < 2_f64.powi(dropped - 1074)
+                    }
+            })
+    }
+
+    #[test]
+    fn test_compress_lossy_shrinks_noisy_data() {
+        let vals: Vec<f64> = (0..1000).map(|x| 100.0 + (x as f64 * 0.37).sin()).collect();
+        let lossless = compress(DEFAULT_TABLE_SIZE, &vals).unwrap();
+        assert_eq!(lossless.dropped_mantissa_bits(), 0);
+        let lossy = compress_lossy(DEFAULT_TABLE_SIZE, &vals, 20).unwrap();
+        assert_eq!(lossy.dropped_mantissa_bits(), 32);
+        assert_eq!(lossy.compressed_len(), quantization_savings(DEFAULT_TABLE_SIZE, &vals, 20).unwrap().1);
+        assert!(lossy.compressed_len() < lossless.compressed_len());
+        assert_eq!(compress_lossy(DEFAULT_TABLE_SIZE, &vals, 60).unwrap(), lossless);
+        assert_eq!(compress_lossy(3, &vals, 20), Err(FpcError::InvalidTableSize { given: 3 }));
+    }
+
+    #[test]
+    fn test_lossy_blocks_stay_lossy_when_appended_to_and_serialized() {
+        let vals: Vec<f64> = (0..100).map(|x| (x as f64).ln_1p()).collect();
+        let mut blk = compress_lossy(DEFAULT_TABLE_SIZE, &vals[..40], 12).unwrap();
+        blk.append(DEFAULT_TABLE_SIZE, &vals[40..]);
+        let expected = compress_lossy(DEFAULT_TABLE_SIZE, &vals, 12).unwrap();
+        assert_eq!(blk, expected);
+        assert_eq!(FPCCompressedBlock::from_bytes(&blk.to_bytes()).as_ref(), Ok(&expected));
+        let quantized: Vec<f64> = vals.iter().map(|v| f64::from_bits(quantize_bits(v.to_bits(), 12))).collect();
+        assert!(bitwise_eq(&blk.decompress(), &quantized));
+    }
+
+    #[quickcheck]
+    fn canonicalized_blocks_must_only_change_nans(to_compress: Vec<f64>, split: usize) -> bool {
+        let options = FpcOptions { canonicalize_nan: true, ..FpcOptions::default() };
+        let blk = compress_with(DEFAULT_TABLE_SIZE, &to_compress, &options).unwrap();
+        let split = if to_compress.is_empty() { 0 } else { split % to_compress.len() };
+        let mut appended = compress_with(DEFAULT_TABLE_SIZE, &to_compress[..split], &options).unwrap();
+        appended.append(DEFAULT_TABLE_SIZE, &to_compress[split..]);
+        let expected: Vec<f64> = to_compress.iter().map(|v| f64::from_bits(canonicalize_nan(v.to_bits()))).collect();
+        blk.canonicalize_nan()
+            && bitwise_eq(&blk.decompress(), &expected)
+            && appended == blk
+            && FPCCompressedBlock::from_bytes(&blk.to_bytes()).as_ref() == Ok(&blk)
+    }
+
+    #[test]
+    fn test_canonicalize_nan_is_opt_in() {
+        let nans: Vec<f64> = (1_u64..=200)
+            .map(|x| f64::from_bits(0x7ff8_0000_0000_0000 | (x.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 13)))
+            .collect();
+        let exact = compress(DEFAULT_TABLE_SIZE, &nans).unwrap();
+        assert!(!exact.canonicalize_nan());
+        assert!(bitwise_eq(&exact.decompress(), &nans));
+        let options = FpcOptions::builder().canonicalize_nan(true).build().unwrap();
+        let canonical = compress_with(DEFAULT_TABLE_SIZE, &nans, &options).unwrap();
+        assert!(canonical.compressed_len() < exact.compressed_len());
+        assert!(canonical.decompress().iter().all(|v| v.to_bits() == f64::NAN.to_bits()));
+    }
+}
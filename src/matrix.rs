@@ -0,0 +1,151 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::{compress, try_decompress, CompressError, DecompressError, FPCCompressedBlock, FpcError};
+
+/// Order in which [`compress_2d`] feeds the values of a matrix to the
+/// predictors. FPC predicts each value from the ones just before it, so
+/// the order along which neighbouring values are most alike compresses
+/// best.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Order {
+    /// Along each row, one row after another.
+    #[default]
+    RowMajor,
+    /// Down each column, one column after another.
+    ColumnMajor,
+}
+
+/// A compressed matrix, as returned by [`compress_2d`]: the block of its
+/// values in traversal order, along with its shape and that order.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MatrixBlock {
+    pub(crate) rows: usize,
+    pub(crate) cols: usize,
+    pub(crate) order: Order,
+    pub(crate) block: FPCCompressedBlock,
+}
+
+impl MatrixBlock {
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn order(&self) -> Order {
+        self.order
+    }
+
+    /// The block of the values in [`order`](Self::order).
+    pub fn block(&self) -> &FPCCompressedBlock {
+        &self.block
+    }
+}
+
+pub(crate) fn check_shape(rows: usize, cols: usize, len: usize) -> Result<(), FpcError> {
+    if rows.checked_mul(cols) != Some(len) {
+        return Err(FpcError::ShapeMismatch { rows, cols, len });
+    }
+    Ok(())
+}
+
+/// Compresses a `rows` by `cols` matrix stored row-major in `data`,
+/// traversing it in `order`. Fails with [`FpcError::ShapeMismatch`]
+/// unless `data` holds exactly `rows * cols` values, and like
+/// [`compress`] on an invalid table size.
+pub fn compress_2d(
+    table_size: u64,
+    data: &[f64],
+    rows: usize,
+    cols: usize,
+    order: Order,
+) -> Result<MatrixBlock, CompressError> {
+    check_shape(rows, cols, data.len())?;
+    let block = match order {
+        Order::RowMajor => compress(table_size, data)?,
+        Order::ColumnMajor => {
+            let transposed: Vec<f64> = (0..cols).flat_map(|col| data.iter().skip(col).step_by(cols)).copied().collect();
+            compress(table_size, &transposed)?
+        }
+    };
+    Ok(MatrixBlock { rows, cols, order, block })
+}
+
+/// Decompresses a matrix written by [`compress_2d`] back into a row-major
+/// buffer, whichever order it was traversed in. Fails like
+/// [`try_decompress`] on a malformed block, and with
+/// [`FpcError::ShapeMismatch`] if the block does not hold the matrix.
+///
+/// The buffer is in the row-major layout that
+/// `ndarray::Array2::from_shape_vec((rows, cols), data)` expects, so it
+/// turns into an array without copying. With the `ndarray` feature,
+/// `decompress_array2` returns the array directly.
+pub fn decompress_2d(table_size: u64, matrix: &MatrixBlock) -> Result<Vec<f64>, DecompressError> {
+    let MatrixBlock { rows, cols, order, ref block } = *matrix;
+    check_shape(rows, cols, block.num_values())?;
+    let values = try_decompress(table_size, block)?;
+    Ok(match order {
+        Order::RowMajor => values,
+        Order::ColumnMajor => {
+            let mut data = vec![0.0; values.len()];
+            for (index, value) in values.into_iter().enumerate() {
+                data[index % rows * cols + index / rows] = value;
+            }
+            data
+        }
+    })
+}
+
+#[cfg(test)]
+mod matrix_test {
+    use quickcheck_macros::quickcheck;
+
+    use crate::{bitwise_eq, DEFAULT_TABLE_SIZE};
+
+    use super::*;
+
+    #[quickcheck]
+    fn matrices_must_round_trip_in_either_order(data: Vec<f64>, cols: usize) -> bool {
+        let cols = 1 + cols % 8;
+        let rows = data.len() / cols;
+        let data = &data[..rows * cols];
+        [Order::RowMajor, Order::ColumnMajor].into_iter().all(|order| {
+            let matrix = compress_2d(DEFAULT_TABLE_SIZE, data, rows, cols, order).unwrap();
+            matrix.order() == order
+                && (matrix.rows(), matrix.cols()) == (rows, cols)
+                && decompress_2d(DEFAULT_TABLE_SIZE, &matrix).is_ok_and(|decoded| bitwise_eq(&decoded, data))
+        })
+    }
+
+    #[test]
+    fn test_column_major_order_follows_columns() {
+        // Every column is constant, so only going down the columns finds
+        // repeats.
+        let data: Vec<f64> = (0..40 * 25).map(|index| ((index % 25) as f64).sqrt()).collect();
+        let by_rows = compress_2d(DEFAULT_TABLE_SIZE, &data, 40, 25, Order::RowMajor).unwrap();
+        let by_cols = compress_2d(DEFAULT_TABLE_SIZE, &data, 40, 25, Order::ColumnMajor).unwrap();
+        assert_eq!(by_cols.block().decompress()[..40], [0.0; 40]);
+        assert!(by_cols.block().compressed_len() < by_rows.block().compressed_len());
+    }
+
+    #[test]
+    fn test_shape_must_match_the_data() {
+        assert_eq!(
+            compress_2d(DEFAULT_TABLE_SIZE, &[1.0; 5], 2, 3, Order::RowMajor),
+            Err(FpcError::ShapeMismatch { rows: 2, cols: 3, len: 5 })
+        );
+        assert_eq!(
+            compress_2d(DEFAULT_TABLE_SIZE, &[], usize::MAX, 2, Order::ColumnMajor),
+            Err(FpcError::ShapeMismatch { rows: usize::MAX, cols: 2, len: 0 })
+        );
+        let mut matrix = compress_2d(DEFAULT_TABLE_SIZE, &[1.0; 6], 2, 3, Order::ColumnMajor).unwrap();
+        matrix.rows = 3;
+        assert_eq!(
+            decompress_2d(DEFAULT_TABLE_SIZE, &matrix),
+            Err(FpcError::ShapeMismatch { rows: 3, cols: 3, len: 6 })
+        );
+    }
+}
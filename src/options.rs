@@ -0,0 +1,243 @@
+use crate::FpcError;
+
+/// How much residual capacity [`compress_with`](crate::compress_with)
+/// reserves up front. The compressed block is the same whichever strategy
+/// is picked; only the number of reallocations while compressing differs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResidualSizing {
+    /// Reserves 2 bytes per value, which suits compressible data.
+    #[default]
+    Conservative,
+    /// Runs the predictors over the input once beforehand to reserve
+    /// exactly as many bytes as the residual will need.
+    Exact,
+    /// Reserves the worst case of 8 bytes per value, so that incompressible
+    /// data never reallocates.
+    Pessimistic,
+}
+
+/// Which predictor a value is attributed to when the FCM and DFCM
+/// predictions are equally close, which happens exactly when they are the
+/// same. Both choices store the same residual and decode to the same value,
+/// so only the selection bit in the encoding changes and blocks do not
+/// record the policy. [`FPCCompressedBlock::append`](crate::FPCCompressedBlock::append)
+/// always uses the default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TieBreak {
+    PreferFcm,
+    /// What FPC has always done.
+    #[default]
+    PreferDfcm,
+}
+
+/// Shift amounts of the two predictor hashes. After every value the
+/// compressor updates them as
+///
+/// ```text
+/// fcm_hash  = (fcm_hash  << fcm_shift)  ^ (value >> fcm_fold)
+/// dfcm_hash = (dfcm_hash << dfcm_shift) ^ (delta >> dfcm_fold)
+/// ```
+///
+/// masked to the table size, where `delta` is the difference to the
+/// previous value. The fold picks which high bits of a value feed the hash
+/// and the shift how fast older values age out of it. Every shift must be
+/// below 64. The block records the parameters it was compressed with, so
+/// decompression always uses the matching ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HashParams {
+    pub fcm_shift: u8,
+    pub fcm_fold: u8,
+    pub dfcm_shift: u8,
+    pub dfcm_fold: u8,
+}
+
+impl HashParams {
+    /// The parameters of the original FPC, used unless configured otherwise.
+    pub const DEFAULT: HashParams = HashParams { fcm_shift: 6, fcm_fold: 48, dfcm_shift: 2, dfcm_fold: 40 };
+
+    pub(crate) fn validate(&self) -> Result<(), FpcError> {
+        if [self.fcm_shift, self.fcm_fold, self.dfcm_shift, self.dfcm_fold].iter().any(|&shift| shift >= 64) {
+            return Err(FpcError::InvalidHashParams { hash_params: *self });
+        }
+        Ok(())
+    }
+}
+
+impl Default for HashParams {
+    fn default() -> Self {
+        HashParams::DEFAULT
+    }
+}
+
+/// Knobs for [`compress_with`](crate::compress_with) and
+/// [`compress_resync`](crate::compress_resync). Build them with
+/// [`FpcOptions::builder`] to have them checked with
+/// [`FpcOptions::validate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FpcOptions {
+    pub residual_sizing: ResidualSizing,
+    /// Number of values per independently decodable segment written by
+    /// [`compress_resync`](crate::compress_resync). `None` writes a single
+    /// segment.
+    pub resync_interval: Option<usize>,
+    /// Hash shifts of the predictors. Resync segments have no room to
+    /// record them, so they must be the defaults with a `resync_interval`.
+    pub hash_params: HashParams,
+    /// Replaces every value by its XOR with a linear extrapolation of the
+    /// two values before it, which compresses series with a steady slope
+    /// far better. The block records the choice and decompression undoes
+    /// it. Cannot be combined with a `resync_interval`.
+    pub delta_of_delta: bool,
+    pub tie_break: TieBreak,
+    /// Replaces every NaN by `f64::NAN` before compression, so NaNs that
+    /// differ only in their payload compress like repeats of one value.
+    /// This loses the payloads, so it is off by default and the block
+    /// records it; with it off every NaN comes back with its exact bits.
+    /// Cannot be combined with a `resync_interval` either.
+    pub canonicalize_nan: bool,
+}
+
+impl FpcOptions {
+    pub fn builder() -> FpcOptionsBuilder {
+        FpcOptionsBuilder::default()
+    }
+
+    /// Checks that the options make sense together:
+    ///
+    /// * `resync_interval` must not be `Some(0)`, since a segment has to
+    ///   hold at least one value.
+    /// * `resync_interval` must fit in 32 bits, the width of the value
+    ///   count in a resync segment header.
+    /// * A `resync_interval` rules out non-default `hash_params`,
+    ///   `delta_of_delta` and `canonicalize_nan`, which resync segments
+    ///   cannot record.
+    /// * Every shift in `hash_params` must be below 64.
+    pub fn validate(&self) -> Result<(), FpcError> {
+        self.hash_params.validate()?;
+        match self.resync_interval {
+            Some(0) => Err(FpcError::ConflictingOptions { detail: "resync_interval must be at least 1" }),
+            Some(interval) if u32::try_from(interval).is_err() => Err(FpcError::ConflictingOptions {
+                detail: "resync_interval must fit in a 32-bit segment header",
+            }),
+            Some(_) if self.hash_params != HashParams::DEFAULT => Err(FpcError::ConflictingOptions {
+                detail: "resync segments cannot record non-default hash_params",
+            }),
+            Some(_) if self.delta_of_delta => Err(FpcError::ConflictingOptions {
+                detail: "resync segments cannot record delta_of_delta",
+            }),
+            Some(_) if self.canonicalize_nan => Err(FpcError::ConflictingOptions {
+                detail: "resync segments cannot record canonicalize_nan",
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FpcOptionsBuilder {
+    options: FpcOptions,
+}
+
+impl FpcOptionsBuilder {
+    pub fn residual_sizing(mut self, residual_sizing: ResidualSizing) -> Self {
+        self.options.residual_sizing = residual_sizing;
+        self
+    }
+
+    pub fn resync_interval(mut self, resync_interval: Option<usize>) -> Self {
+        self.options.resync_interval = resync_interval;
+        self
+    }
+
+    pub fn hash_params(mut self, hash_params: HashParams) -> Self {
+        self.options.hash_params = hash_params;
+        self
+    }
+
+    pub fn delta_of_delta(mut self, delta_of_delta: bool) -> Self {
+        self.options.delta_of_delta = delta_of_delta;
+        self
+    }
+
+    pub fn tie_break(mut self, tie_break: TieBreak) -> Self {
+        self.options.tie_break = tie_break;
+        self
+    }
+
+    pub fn canonicalize_nan(mut self, canonicalize_nan: bool) -> Self {
+        self.options.canonicalize_nan = canonicalize_nan;
+        self
+    }
+
+    pub fn build(self) -> Result<FpcOptions, FpcError> {
+        self.options.validate()?;
+        Ok(self.options)
+    }
+}
+
+#[cfg(test)]
+mod options_test {
+    use super::*;
+
+    #[test]
+    fn test_builder_accepts_sensible_options() {
+        let options = FpcOptions::builder()
+            .residual_sizing(ResidualSizing::Exact)
+            .resync_interval(Some(1024))
+            .build();
+        assert_eq!(
+            options,
+            Ok(FpcOptions {
+                residual_sizing: ResidualSizing::Exact,
+                resync_interval: Some(1024),
+                hash_params: HashParams::DEFAULT,
+                delta_of_delta: false,
+                tie_break: TieBreak::PreferDfcm,
+                canonicalize_nan: false,
+            }),
+        );
+        assert_eq!(FpcOptions::builder().build(), Ok(FpcOptions::default()));
+    }
+
+    #[test]
+    fn test_builder_rejects_invalid_resync_intervals() {
+        assert!(matches!(
+            FpcOptions::builder().resync_interval(Some(0)).build(),
+            Err(FpcError::ConflictingOptions { .. }),
+        ));
+        if let Ok(too_large) = usize::try_from(u64::from(u32::MAX) + 1) {
+            assert!(matches!(
+                FpcOptions::builder().resync_interval(Some(too_large)).build(),
+                Err(FpcError::ConflictingOptions { .. }),
+            ));
+        }
+    }
+
+    #[test]
+    fn test_builder_rejects_hash_shifts_past_the_word() {
+        let hash_params = HashParams { dfcm_fold: 64, ..HashParams::DEFAULT };
+        assert_eq!(
+            FpcOptions::builder().hash_params(hash_params).build(),
+            Err(FpcError::InvalidHashParams { hash_params }),
+        );
+        let hash_params = HashParams { fcm_shift: 63, ..HashParams::DEFAULT };
+        assert!(FpcOptions::builder().hash_params(hash_params).build().is_ok());
+    }
+
+    #[test]
+    fn test_builder_rejects_settings_resync_segments_cannot_record() {
+        let resync = || FpcOptions::builder().resync_interval(Some(64));
+        let hash_params = HashParams { fcm_shift: 5, ..HashParams::DEFAULT };
+        for builder in [
+            resync().hash_params(hash_params),
+            resync().delta_of_delta(true),
+            resync().canonicalize_nan(true),
+        ] {
+            assert!(matches!(builder.build(), Err(FpcError::ConflictingOptions { .. })));
+        }
+        let unsynced = FpcOptions::builder().hash_params(hash_params).delta_of_delta(true).canonicalize_nan(true);
+        assert!(unsynced.build().is_ok());
+        assert!(resync().tie_break(TieBreak::PreferFcm).residual_sizing(ResidualSizing::Exact).build().is_ok());
+    }
+}
@@ -0,0 +1,423 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::encoding_mode::uniform_bits;
+use crate::{
+    decode_value, encode_bits, validate_table_size, CompressError, DecompressError, EncodingMode, FPCCompressedBlock,
+    FpcError, HashParams, PredictorState, Predictors,
+};
+
+/// The FCM and DFCM predictors of the compressor, driven one value at a
+/// time without encoding anything. Useful for modelling a series, e.g. to
+/// flag values that are far from what the compressor expected.
+///
+/// Feeding values through [`Predictor::update`] advances the exact same
+/// state the compressor keeps, so [`Predictor::predictions`] matches what
+/// it would compare the next value against.
+pub struct Predictor {
+    predictors: Predictors,
+    fcm_selected: bool,
+}
+
+impl Predictor {
+    pub fn new(table_size: u64) -> Self {
+        crate::check_table_size(table_size);
+        Predictor { predictors: Predictors::new(table_size), fcm_selected: false }
+    }
+
+    /// Bit pattern of the next value as predicted by the predictor that
+    /// was closer on the previous value, with ties going to DFCM as in
+    /// the compressor. DFCM is used before any value has been seen.
+    pub fn predict(&self) -> u64 {
+        let (fcm_prediction, dfcm_prediction) = self.predictions();
+        if self.fcm_selected { fcm_prediction } else { dfcm_prediction }
+    }
+
+    /// Bit patterns predicted for the next value by FCM and DFCM.
+    pub fn predictions(&self) -> (u64, u64) {
+        self.predictors.predict()
+    }
+
+    pub fn update(&mut self, value: f64) {
+        let true_value = value.to_bits();
+        let (fcm_prediction, dfcm_prediction) = self.predictors.predict();
+        self.fcm_selected = (fcm_prediction ^ true_value) < (dfcm_prediction ^ true_value);
+        self.predictors.update(true_value);
+    }
+}
+
+/// One of the two predictors of the compressor, for experimenting with
+/// predictors other than FCM and DFCM. Named apart from [`Predictor`],
+/// which models the built-in pair.
+///
+/// The compressor keeps a hash for each predictor, starting at 0. For every
+/// value it asks [`predict`](Self::predict) for a guess under the current
+/// hash, then computes the hash for the next value with
+/// [`next_hash`](Self::next_hash) and only afterwards calls
+/// [`update`](Self::update) with the value. The decompressor makes the same
+/// calls in the same order, so any deterministic predictor round trips.
+pub trait ValuePredictor {
+    /// Bit pattern predicted for the next value.
+    fn predict(&self, hash: u64) -> u64;
+    /// Records `true_value` as the value that followed `hash`.
+    fn update(&mut self, hash: u64, true_value: u64);
+    /// Hash to predict the value after `value` with.
+    fn next_hash(&self, hash: u64, value: u64) -> u64;
+}
+
+/// The finite context method predictor of FPC: predicts the value that
+/// followed the last occurrence of the current hash of recent values.
+pub struct FcmPredictor {
+    table: Vec<u64>,
+    table_mask: u64,
+}
+
+impl FcmPredictor {
+    /// Panics unless `table_size` is a power of two.
+    pub fn new(table_size: u64) -> Self {
+        crate::check_table_size(table_size);
+        FcmPredictor { table: vec![0; table_size as usize], table_mask: table_size - 1 }
+    }
+}
+
+impl ValuePredictor for FcmPredictor {
+    fn predict(&self, hash: u64) -> u64 {
+        self.table[hash as usize]
+    }
+
+    fn update(&mut self, hash: u64, true_value: u64) {
+        self.table[hash as usize] = true_value;
+    }
+
+    fn next_hash(&self, hash: u64, value: u64) -> u64 {
+        ((hash << 6) ^ (value >> 48)) & self.table_mask
+    }
+}
+
+/// An FCM predictor whose hash takes in the last `ORDER` values at each
+/// step instead of only the newest one. The newest value contributes its
+/// top 16 bits, as in [`FcmPredictor`], and every older one the next 16
+/// bits down of its bit pattern, so that the context also covers the
+/// mantissas of recent values. `ORDER` 1 is exactly [`FcmPredictor`], and
+/// can be at most 4.
+pub struct OrderFcmPredictor<const ORDER: usize> {
+    table: Vec<u64>,
+    table_mask: u64,
+    // The most recent values, newest first.
+    history: [u64; ORDER],
+}
+
+impl<const ORDER: usize> OrderFcmPredictor<ORDER> {
+    /// Panics unless `table_size` is a power of two.
+    pub fn new(table_size: u64) -> Self {
+        const { assert!(ORDER >= 1 && ORDER <= 4, "order must be between 1 and 4") };
+        crate::check_table_size(table_size);
+        OrderFcmPredictor { table: vec![0; table_size as usize], table_mask: table_size - 1, history: [0; ORDER] }
+    }
+}
+
+impl<const ORDER: usize> ValuePredictor for OrderFcmPredictor<ORDER> {
+    fn predict(&self, hash: u64) -> u64 {
+        self.table[hash as usize]
+    }
+
+    fn update(&mut self, hash: u64, true_value: u64) {
+        self.table[hash as usize] = true_value;
+        self.history.rotate_right(1);
+        self.history[0] = true_value;
+    }
+
+    fn next_hash(&self, hash: u64, value: u64) -> u64 {
+        // The history does not hold `value` yet, so its first ORDER - 1
+        // entries are the values before it.
+        let mut folded = value >> 48;
+        for (age, previous) in self.history[..ORDER - 1].iter().enumerate() {
+            folded ^= (previous >> (32 - 16 * age)) & 0xffff;
+        }
+        ((hash << 6) ^ folded) & self.table_mask
+    }
+}
+
+/// The differential finite context method predictor of FPC: like FCM, but
+/// on the differences between consecutive values.
+pub struct DfcmPredictor {
+    table: Vec<u64>,
+    table_mask: u64,
+    last_value: u64,
+}
+
+impl DfcmPredictor {
+    /// Panics unless `table_size` is a power of two.
+    pub fn new(table_size: u64) -> Self {
+        crate::check_table_size(table_size);
+        DfcmPredictor { table: vec![0; table_size as usize], table_mask: table_size - 1, last_value: 0 }
+    }
+}
+
+impl ValuePredictor for DfcmPredictor {
+    fn predict(&self, hash: u64) -> u64 {
+        self.table[hash as usize].wrapping_add(self.last_value)
+    }
+
+    fn update(&mut self, hash: u64, true_value: u64) {
+        self.table[hash as usize] = true_value.wrapping_sub(self.last_value);
+        self.last_value = true_value;
+    }
+
+    fn next_hash(&self, hash: u64, value: u64) -> u64 {
+        ((hash << 2) ^ (value.wrapping_sub(self.last_value) >> 40)) & self.table_mask
+    }
+}
+
+/// Two [`ValuePredictor`]s and their hashes, standing in for the built-in
+/// tables in the encoding loop. The first takes the place of FCM in the
+/// codes and the second that of DFCM.
+struct PredictorPair<F, D> {
+    fcm: F,
+    fcm_hash: u64,
+    dfcm: D,
+    dfcm_hash: u64,
+}
+
+impl<F: ValuePredictor, D: ValuePredictor> PredictorState for PredictorPair<F, D> {
+    fn predict(&self) -> (u64, u64) {
+        (self.fcm.predict(self.fcm_hash), self.dfcm.predict(self.dfcm_hash))
+    }
+
+    fn update(&mut self, true_value: u64) {
+        let fcm_hash = self.fcm.next_hash(self.fcm_hash, true_value);
+        let dfcm_hash = self.dfcm.next_hash(self.dfcm_hash, true_value);
+        self.fcm.update(self.fcm_hash, true_value);
+        self.dfcm.update(self.dfcm_hash, true_value);
+        self.fcm_hash = fcm_hash;
+        self.dfcm_hash = dfcm_hash;
+    }
+}
+
+/// A block compressed with other predictors than the built-in ones, as
+/// returned by [`compress_with_predictors`] and [`compress_order`]. Its
+/// codes only decode with the same predictors, which the header has no
+/// room to record, so it is not handed out as an [`FPCCompressedBlock`]
+/// that [`decompress`](crate::decompress) would decode into wrong values.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PredictorBlock {
+    block: FPCCompressedBlock,
+}
+
+impl PredictorBlock {
+    pub fn table_size(&self) -> u64 {
+        self.block.table_size
+    }
+
+    pub fn num_values(&self) -> usize {
+        self.block.num_values()
+    }
+
+    /// Number of bytes of the encoding and residual, like
+    /// [`FPCCompressedBlock::compressed_len`].
+    pub fn compressed_len(&self) -> usize {
+        self.block.compressed_len()
+    }
+}
+
+/// Compresses `values` like [`compress`](crate::compress), with `fcm` and
+/// `dfcm` in place of the built-in predictors. The block records
+/// `table_size`, which should be the size the predictors were built with;
+/// passing [`FcmPredictor::new`] and [`DfcmPredictor::new`] of that size
+/// produces the codes and residual `compress` does.
+pub fn compress_with_predictors<F: ValuePredictor, D: ValuePredictor>(
+    table_size: u64,
+    values: &[f64],
+    fcm: F,
+    dfcm: D,
+) -> Result<PredictorBlock, CompressError> {
+    validate_table_size(table_size)?;
+    if let Some(bits) = uniform_bits(values.iter().map(|v| v.to_bits())) {
+        return Ok(PredictorBlock { block: FPCCompressedBlock::constant(table_size, values.len(), bits) });
+    }
+    let mut predictors = PredictorPair { fcm, fcm_hash: 0, dfcm, dfcm_hash: 0 };
+    let mut encoding = Vec::with_capacity(values.len().div_ceil(2));
+    let mut residual = Vec::new();
+    encode_bits(&mut predictors, values.iter().map(|v| v.to_bits()), (&mut encoding, &mut residual), None);
+    let block = FPCCompressedBlock {
+        table_size,
+        num_bytes_encoded: values.len(),
+        mode: EncodingMode::Full,
+        hash_params: HashParams::DEFAULT,
+        dropped_mantissa_bits: 0,
+        delta_of_delta: false,
+        seed: 0,
+        canonicalize_nan: false,
+        encoding,
+        residual,
+    };
+    Ok(PredictorBlock { block })
+}
+
+/// Decompresses a block made by [`compress_with_predictors`], given freshly
+/// built predictors of the same kind. Fails like
+/// [`try_decompress`](crate::try_decompress) on a malformed block or a
+/// `table_size` other than the block's.
+pub fn decompress_with_predictors<F: ValuePredictor, D: ValuePredictor>(
+    table_size: u64,
+    blk: &PredictorBlock,
+    fcm: F,
+    dfcm: D,
+) -> Result<Vec<f64>, DecompressError> {
+    let blk = &blk.block;
+    validate_table_size(table_size)?;
+    blk.check_table_size(table_size)?;
+    blk.check_encoding_len()?;
+    if let Some(bits) = blk.constant_bits() {
+        return Ok(vec![f64::from_bits(bits); blk.num_bytes_encoded]);
+    }
+    let mut predictors = PredictorPair { fcm, fcm_hash: 0, dfcm, dfcm_hash: 0 };
+    let mut residual_index = 0;
+    let mut values = Vec::with_capacity(blk.num_bytes_encoded);
+    for (index, code) in blk.codes().enumerate() {
+        let decoded = decode_value(&mut predictors, code, &blk.residual, &mut residual_index)
+            .ok_or(FpcError::TruncatedResidual { at_value: index, residual_len: blk.residual.len() })?;
+        values.push(f64::from_bits(decoded));
+    }
+    Ok(values)
+}
+
+/// Compresses `values` with an [`OrderFcmPredictor`] of order `ORDER` in
+/// place of FCM, next to the usual DFCM. `ORDER` 1 produces the codes and
+/// residual [`compress`](crate::compress) does.
+pub fn compress_order<const ORDER: usize>(
+    table_size: u64,
+    values: &[f64],
+) -> Result<PredictorBlock, CompressError> {
+    validate_table_size(table_size)?;
+    let fcm = OrderFcmPredictor::<ORDER>::new(table_size);
+    compress_with_predictors(table_size, values, fcm, DfcmPredictor::new(table_size))
+}
+
+/// Decompresses a block made by [`compress_order`] with the same `ORDER`.
+/// Fails like [`decompress_with_predictors`].
+pub fn decompress_order<const ORDER: usize>(
+    table_size: u64,
+    blk: &PredictorBlock,
+) -> Result<Vec<f64>, DecompressError> {
+    validate_table_size(table_size)?;
+    let fcm = OrderFcmPredictor::<ORDER>::new(table_size);
+    decompress_with_predictors(table_size, blk, fcm, DfcmPredictor::new(table_size))
+}
+
+#[cfg(test)]
+mod predictor_test {
+    use quickcheck_macros::quickcheck;
+
+    use crate::{bitwise_eq, compress, residual_len_of_code, DEFAULT_TABLE_SIZE};
+
+    use super::*;
+
+    #[test]
+    fn test_predictor_reproduces_compressor_predictions() {
+        let vals: Vec<f64> = (0..200).map(|x| ((x % 13) as f64 * 0.7).cos() + (x / 50) as f64).collect();
+        let blk = compress(DEFAULT_TABLE_SIZE, &vals).unwrap();
+        let mut predictor = Predictor::new(DEFAULT_TABLE_SIZE);
+        let mut residual_index = 0;
+        for (&value, code) in vals.iter().zip(blk.codes()) {
+            let (fcm_prediction, dfcm_prediction) = predictor.predictions();
+            let chosen = if code & 0b1000 != 0 { fcm_prediction } else { dfcm_prediction };
+            let len = residual_len_of_code(code);
+            let diff = (value.to_bits() ^ chosen).to_be_bytes();
+            assert_eq!(blk.residual[residual_index..residual_index + len], diff[8 - len..]);
+            residual_index += len;
+            predictor.update(value);
+        }
+        assert_eq!(residual_index, blk.residual.len());
+    }
+
+    #[test]
+    fn test_predict_follows_the_closer_predictor() {
+        let mut predictor = Predictor::new(DEFAULT_TABLE_SIZE);
+        // A constant stride is exactly what DFCM predicts.
+        for x in 0..10 {
+            predictor.update(x as f64);
+        }
+        assert_eq!(predictor.predict(), predictor.predictions().1);
+        // Repeating a short cycle is what FCM predicts.
+        for _ in 0..10 {
+            for x in [3.0, 17.5, -2.0] {
+                predictor.update(x);
+            }
+        }
+        assert_eq!(predictor.predict(), predictor.predictions().0);
+        assert_eq!(f64::from_bits(predictor.predict()), 3.0);
+    }
+
+    #[quickcheck]
+    fn built_in_value_predictors_must_match_compress(to_compress: Vec<f64>) -> bool {
+        [1, DEFAULT_TABLE_SIZE].iter().all(|&table_size| {
+            let fcm = FcmPredictor::new(table_size);
+            let dfcm = DfcmPredictor::new(table_size);
+            let blk = compress_with_predictors(table_size, &to_compress, fcm, dfcm).unwrap();
+            let decoded =
+                decompress_with_predictors(table_size, &blk, FcmPredictor::new(table_size), DfcmPredictor::new(table_size));
+            blk.block == compress(table_size, &to_compress).unwrap() && decoded.is_ok_and(|v| bitwise_eq(&v, &to_compress))
+        })
+    }
+
+    /// Predicts a repeat of the previous value, ignoring the hash.
+    struct LastValue(u64);
+
+    impl ValuePredictor for LastValue {
+        fn predict(&self, _hash: u64) -> u64 {
+            self.0
+        }
+
+        fn update(&mut self, _hash: u64, true_value: u64) {
+            self.0 = true_value;
+        }
+
+        fn next_hash(&self, _hash: u64, _value: u64) -> u64 {
+            0
+        }
+    }
+
+    #[test]
+    fn test_custom_predictors_round_trip() {
+        let vals: Vec<f64> = (0..300).map(|x| (x / 7) as f64 * 1.5).collect();
+        let blk = compress_with_predictors(DEFAULT_TABLE_SIZE, &vals, LastValue(0), LastValue(0)).unwrap();
+        // Every value repeating its predecessor is guessed exactly.
+        let repeats = vals.iter().zip([0.0].iter().chain(&vals)).filter(|(x, previous)| x == previous).count();
+        assert_eq!(blk.block.codes().filter(|&code| residual_len_of_code(code) == 0).count(), repeats);
+        assert_ne!(blk.block, compress(DEFAULT_TABLE_SIZE, &vals).unwrap());
+        let decoded = decompress_with_predictors(DEFAULT_TABLE_SIZE, &blk, LastValue(0), LastValue(0));
+        assert!(decoded.is_ok_and(|decoded| bitwise_eq(&decoded, &vals)));
+        assert_eq!(
+            decompress_with_predictors(64, &blk, LastValue(0), LastValue(0)),
+            Err(FpcError::TableSizeMismatch { block: DEFAULT_TABLE_SIZE, given: 64 }),
+        );
+    }
+
+    #[quickcheck]
+    fn orders_must_round_trip(to_compress: Vec<f64>) -> bool {
+        let round_trips = |blk: PredictorBlock, decoded: Result<Vec<f64>, FpcError>| {
+            blk.num_values() == to_compress.len() && decoded.is_ok_and(|v| bitwise_eq(&v, &to_compress))
+        };
+        let first = compress_order::<1>(DEFAULT_TABLE_SIZE, &to_compress).unwrap();
+        let second = compress_order::<2>(DEFAULT_TABLE_SIZE, &to_compress).unwrap();
+        let fourth = compress_order::<4>(DEFAULT_TABLE_SIZE, &to_compress).unwrap();
+        first.block == compress(DEFAULT_TABLE_SIZE, &to_compress).unwrap()
+            && round_trips(first.clone(), decompress_order::<1>(DEFAULT_TABLE_SIZE, &first))
+            && round_trips(second.clone(), decompress_order::<2>(DEFAULT_TABLE_SIZE, &second))
+            && round_trips(fourth.clone(), decompress_order::<4>(DEFAULT_TABLE_SIZE, &fourth))
+    }
+
+    #[test]
+    fn test_higher_orders_tell_apart_contexts_sharing_top_bits() {
+        // The values share their top 16 bits, which is all FCM of order 1
+        // sees of them, while the mantissa of the value before 1.0 + 2^-9
+        // tells which one follows it.
+        let cycle = [1.0 + 0.5 / 512.0, 1.0 + 1.0 / 512.0, 1.0 + 1.5 / 512.0, 1.0 + 1.0 / 512.0];
+        let vals: Vec<f64> = (0..3000).map(|x| cycle[x % 4]).collect();
+        let first = compress_order::<1>(1 << 16, &vals).unwrap();
+        let second = compress_order::<2>(1 << 16, &vals).unwrap();
+        assert!(second.compressed_len() < first.compressed_len());
+        assert_eq!(compress_order::<2>(3, &vals), Err(FpcError::InvalidTableSize { given: 3 }));
+    }
+}
@@ -0,0 +1,234 @@
+use alloc::vec::Vec;
+
+use crate::{
+    encode_block, try_decompress, validate_table_size, CompressError, EncodingMode, FPCCompressedBlock, FpcError,
+    FpcOptions, HashParams,
+};
+
+const SEGMENT_MARKER: [u8; 4] = *b"FPCR";
+// marker, segment index, segment count, value count, encoding length,
+// residual length and checksum
+const SEGMENT_HEADER_LEN: usize = 4 + 6 * 4;
+
+/// Values recovered by [`decompress_resync`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResyncRecovery {
+    /// Values of every intact segment, in order. Values of damaged
+    /// segments are left out rather than replaced by placeholders.
+    pub values: Vec<f64>,
+    /// Indices of the segments that could not be recovered.
+    pub damaged_segments: Vec<usize>,
+}
+
+/// Compresses `fp_values` into a byte stream of independently decodable
+/// segments of `options.resync_interval` values each (a single segment if
+/// unset). Every segment starts with a marker and is covered by a checksum,
+/// and starts from fresh predictors, so corruption is confined to the
+/// segment it hits at the cost of some warm-up at every boundary.
+///
+/// Fails with [`FpcError::InvalidTableSize`] unless `table_size` is a
+/// power of two, and like [`FpcOptions::validate`] with a
+/// `resync_interval`, even when it is unset: segment headers have no room
+/// for hash parameters or block flags. Fails with
+/// [`FpcError::SegmentFieldTooLarge`] if a segment or the segment count
+/// does not fit its 32-bit header field.
+pub fn compress_resync(table_size: u64, fp_values: &[f64], options: &FpcOptions) -> Result<Vec<u8>, CompressError> {
+    validate_table_size(table_size)?;
+    let interval = options.resync_interval.unwrap_or(fp_values.len().max(1));
+    (FpcOptions { resync_interval: Some(interval), ..*options }).validate()?;
+    let segment_count = fp_values.len().div_ceil(interval);
+    let mut out = Vec::new();
+    for (index, segment) in fp_values.chunks(interval).enumerate() {
+        let blk = encode_block(table_size, segment, options, 0);
+        let header_start = out.len();
+        out.extend_from_slice(&SEGMENT_MARKER);
+        for field in [index, segment_count, blk.num_bytes_encoded, blk.encoding.len(), blk.residual.len()] {
+            let field = u32::try_from(field).map_err(|_| FpcError::SegmentFieldTooLarge { value: field })?;
+            out.extend_from_slice(&field.to_le_bytes());
+        }
+        let checksum = segment_checksum(&out[header_start + 4..], &blk.encoding, &blk.residual);
+        out.extend_from_slice(&checksum.to_le_bytes());
+        out.extend_from_slice(&blk.encoding);
+        out.extend_from_slice(&blk.residual);
+    }
+    Ok(out)
+}
+
+/// Decodes a stream written by [`compress_resync`]. A segment whose
+/// checksum does not match, or that fails to decode even so, is skipped,
+/// and decoding resumes at the next segment marker found after it. Fails
+/// with [`FpcError::InvalidTableSize`] unless `table_size` is a power of
+/// two.
+pub fn decompress_resync(table_size: u64, bytes: &[u8]) -> Result<ResyncRecovery, FpcError> {
+    validate_table_size(table_size)?;
+    let mut values = Vec::new();
+    let mut damaged_segments = Vec::new();
+    let mut next_segment = 0;
+    let mut segment_count = None;
+    let mut pos = 0;
+    while let Some(marker_pos) = find_marker(bytes, pos) {
+        let segment = parse_segment(table_size, &bytes[marker_pos..])
+            .and_then(|(index, count, blk, len)| Some((index, count, try_decompress(table_size, &blk).ok()?, len)));
+        match segment {
+            Some((index, count, decoded, len)) if index >= next_segment && index < count => {
+                damaged_segments.extend(next_segment..index);
+                values.extend(decoded);
+                next_segment = index + 1;
+                segment_count = Some(count);
+                pos = marker_pos + len;
+            }
+            _ => pos = marker_pos + 1,
+        }
+    }
+    if let Some(count) = segment_count {
+        damaged_segments.extend(next_segment..count);
+    }
+    Ok(ResyncRecovery { values, damaged_segments })
+}
+
+fn find_marker(bytes: &[u8], from: usize) -> Option<usize> {
+    bytes
+        .get(from..)?
+        .windows(SEGMENT_MARKER.len())
+        .position(|window| window == SEGMENT_MARKER)
+        .map(|offset| from + offset)
+}
+
+/// Parses the segment at the start of `bytes`, returning its index, the
+/// total segment count, the block and the segment's length in bytes.
+fn parse_segment(table_size: u64, bytes: &[u8]) -> Option<(usize, usize, FPCCompressedBlock, usize)> {
+    let header = bytes.get(..SEGMENT_HEADER_LEN)?;
+    let field = |i: usize| {
+        let start = 4 + 4 * i;
+        u32::from_le_bytes(header[start..start + 4].try_into().unwrap()) as usize
+    };
+    let (index, count, num_values, encoding_len, residual_len) = (field(0), field(1), field(2), field(3), field(4));
+    let payload_end = SEGMENT_HEADER_LEN.checked_add(encoding_len)?.checked_add(residual_len)?;
+    let payload = bytes.get(SEGMENT_HEADER_LEN..payload_end)?;
+    let (encoding, residual) = payload.split_at(encoding_len);
+    if segment_checksum(&header[4..SEGMENT_HEADER_LEN - 4], encoding, residual) != field(5) as u32 {
+        return None;
+    }
+    if encoding_len != num_values.div_ceil(2) {
+        return None;
+    }
+    let blk = FPCCompressedBlock {
+        table_size,
+        num_bytes_encoded: num_values,
+        mode: EncodingMode::Full,
+        hash_params: HashParams::DEFAULT,
+        dropped_mantissa_bits: 0,
+        delta_of_delta: false,
+        seed: 0,
+        canonicalize_nan: false,
+        encoding: encoding.to_vec(),
+        residual: residual.to_vec(),
+    };
+    Some((index, count, blk, payload_end))
+}
+
+/// 32-bit FNV-1a over the segment header fields and payload.
+fn segment_checksum(header_fields: &[u8], encoding: &[u8], residual: &[u8]) -> u32 {
+    let mut hash: u32 = 0x811c_9dc5;
+    for &byte in header_fields.iter().chain(encoding).chain(residual) {
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod resync_test {
+    use crate::DEFAULT_TABLE_SIZE;
+
+    use super::*;
+
+    fn resync_options(interval: usize) -> FpcOptions {
+        FpcOptions { resync_interval: Some(interval), ..FpcOptions::default() }
+    }
+
+    fn segment_offsets(bytes: &[u8]) -> Vec<usize> {
+        let mut offsets = vec![];
+        let mut pos = 0;
+        while let Some(marker_pos) = find_marker(bytes, pos) {
+            offsets.push(marker_pos);
+            pos = marker_pos + 1;
+        }
+        offsets
+    }
+
+    #[test]
+    fn test_resync_round_trip_without_damage() {
+        let vals: Vec<f64> = (0..1000).map(|x| (x as f64 * 0.01).sin()).collect();
+        for options in [resync_options(100), resync_options(7), FpcOptions::default()] {
+            let bytes = compress_resync(DEFAULT_TABLE_SIZE, &vals, &options).unwrap();
+            let recovered = decompress_resync(DEFAULT_TABLE_SIZE, &bytes).unwrap();
+            assert_eq!(recovered.damaged_segments, Vec::<usize>::new());
+            assert_eq!(recovered.values, vals);
+        }
+    }
+
+    #[test]
+    fn test_resync_recovers_segments_around_a_corrupted_one() {
+        let vals: Vec<f64> = (0..1000).map(|x| (x as f64 * 0.01).sin()).collect();
+        let mut bytes = compress_resync(DEFAULT_TABLE_SIZE, &vals, &resync_options(100)).unwrap();
+        let offsets = segment_offsets(&bytes);
+        assert_eq!(offsets.len(), 10);
+        // Flip a payload byte in segment 3 and the marker of segment 7.
+        bytes[offsets[3] + SEGMENT_HEADER_LEN + 20] ^= 0x40;
+        bytes[offsets[7]] ^= 0xff;
+
+        let recovered = decompress_resync(DEFAULT_TABLE_SIZE, &bytes).unwrap();
+        assert_eq!(recovered.damaged_segments, vec![3, 7]);
+        let expected: Vec<f64> = vals
+            .chunks(100)
+            .enumerate()
+            .filter(|(i, _)| *i != 3 && *i != 7)
+            .flat_map(|(_, chunk)| chunk.iter().copied())
+            .collect();
+        assert_eq!(recovered.values, expected);
+    }
+
+    #[test]
+    fn test_resync_reports_truncated_trailing_segments() {
+        let vals: Vec<f64> = (0..300).map(|x| x as f64).collect();
+        let bytes = compress_resync(DEFAULT_TABLE_SIZE, &vals, &resync_options(100)).unwrap();
+        let offsets = segment_offsets(&bytes);
+        let recovered = decompress_resync(DEFAULT_TABLE_SIZE, &bytes[..offsets[2] + 10]).unwrap();
+        assert_eq!(recovered.damaged_segments, vec![2]);
+        assert_eq!(recovered.values, vals[..200].to_vec());
+    }
+
+    #[test]
+    fn test_resync_skips_segments_that_fail_to_decode() {
+        let vals: Vec<f64> = (0..200).map(|x| x as f64).collect();
+        let mut bytes = compress_resync(DEFAULT_TABLE_SIZE, &vals, &resync_options(100)).unwrap();
+        // A segment with an intact checksum whose two values call for 16
+        // residual bytes but carry none.
+        let mut forged = SEGMENT_MARKER.to_vec();
+        for field in [1_u32, 2, 2, 1, 0] {
+            forged.extend_from_slice(&field.to_le_bytes());
+        }
+        let checksum = segment_checksum(&forged[4..], &[0x00], &[]);
+        forged.extend_from_slice(&checksum.to_le_bytes());
+        forged.push(0x00);
+        let offsets = segment_offsets(&bytes);
+        bytes.splice(offsets[1]..offsets[1], forged);
+
+        let recovered = decompress_resync(DEFAULT_TABLE_SIZE, &bytes).unwrap();
+        assert_eq!(recovered.damaged_segments, Vec::<usize>::new());
+        assert_eq!(recovered.values, vals);
+        assert_eq!(decompress_resync(3, &bytes), Err(FpcError::InvalidTableSize { given: 3 }));
+    }
+
+    #[test]
+    fn test_compress_resync_rejects_invalid_arguments() {
+        assert_eq!(compress_resync(3, &[1.0], &resync_options(1)), Err(FpcError::InvalidTableSize { given: 3 }));
+        for options in [resync_options(0), FpcOptions { delta_of_delta: true, ..FpcOptions::default() }] {
+            assert!(matches!(
+                compress_resync(DEFAULT_TABLE_SIZE, &[1.0], &options),
+                Err(FpcError::ConflictingOptions { .. })
+            ));
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use crate::{bitwise_eq, compress, decompress, FpcError};
+
+const SELF_TEST_INPUT: [f64; 9] = [0.0, 1.0, 1.0, -2.5, 100.0, 101.0, 102.0, 100.0, 101.0];
+
+struct ExpectedBlock {
+    table_size: u64,
+    encoding: &'static [u8],
+    residual: &'static [u8],
+}
+
+const SELF_TEST_VECTORS: [ExpectedBlock; 3] = [
+    ExpectedBlock {
+        table_size: 1,
+        encoding: &[112, 240, 26, 122, 160],
+        residual: &[
+            63, 240, 0, 0, 0, 0, 0, 0, 255, 244, 0, 0, 0, 0, 0, 0, 65, 0, 0, 0, 0, 0, 0,
+            64, 0, 0, 0, 0, 0, 128, 0, 0, 0, 0, 0, 64, 0, 0, 0, 0, 0,
+        ],
+    },
+    ExpectedBlock {
+        table_size: 32,
+        encoding: &[112, 128, 24, 122, 160],
+        residual: &[
+            63, 240, 0, 0, 0, 0, 0, 0, 63, 240, 0, 0, 0, 0, 0, 0, 255, 244, 0, 0, 0, 0, 0, 0,
+            65, 0, 0, 0, 0, 0, 0, 64, 89, 64, 0, 0, 0, 0, 0, 128, 0, 0, 0, 0, 0, 64, 0, 0, 0, 0, 0,
+        ],
+    },
+    ExpectedBlock {
+        table_size: 1024,
+        encoding: &[112, 128, 18, 34, 32],
+        residual: &[
+            63, 240, 0, 0, 0, 0, 0, 0, 63, 240, 0, 0, 0, 0, 0, 0, 255, 244, 0, 0, 0, 0, 0, 0,
+            65, 0, 0, 0, 0, 0, 0, 64, 0, 0, 0, 0, 0, 192, 0, 0, 0, 0, 0, 128, 0, 0, 0, 0, 0,
+            64, 0, 0, 0, 0, 0,
+        ],
+    },
+];
+
+/// Compresses a small fixed input with several table sizes and checks
+/// the output against known-good bytes, then checks that it decompresses
+/// back bit-exactly. Cheap enough to call once at startup to guard
+/// against a miscompiled or otherwise broken build.
+pub fn self_test() -> Result<(), FpcError> {
+    let input = &SELF_TEST_INPUT[..];
+    for expected in &SELF_TEST_VECTORS {
+        let table_size = expected.table_size;
+        let compressed = compress(table_size, input)?;
+        if compressed.num_bytes_encoded != input.len() {
+            return Err(FpcError::SelfTestFailed { table_size, stage: "value count" });
+        }
+        if compressed.encoding != expected.encoding {
+            return Err(FpcError::SelfTestFailed { table_size, stage: "encoding" });
+        }
+        if compressed.residual != expected.residual {
+            return Err(FpcError::SelfTestFailed { table_size, stage: "residual" });
+        }
+        if !bitwise_eq(&decompress(table_size, &compressed), input) {
+            return Err(FpcError::SelfTestFailed { table_size, stage: "decompress" });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod self_test_test {
+    use super::*;
+
+    #[test]
+    fn test_self_test_passes() {
+        assert_eq!(self_test(), Ok(()));
+    }
+
+    #[test]
+    fn test_self_test_vectors_use_distinct_encodings() {
+        // If two table sizes produced identical output, the vectors would
+        // not be able to catch a bug in the hash masking.
+        for (i, a) in SELF_TEST_VECTORS.iter().enumerate() {
+            for b in &SELF_TEST_VECTORS[i + 1..] {
+                assert!(a.encoding != b.encoding || a.residual != b.residual);
+            }
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use alloc::vec::Vec;
+
+use crate::columns::check_column_lens;
+use crate::{compress, compress_with, try_decompress, CompressError, DecompressError, FPCCompressedBlock, FpcOptions};
+
+/// A compressed time series, as returned by [`compress_series`]: a block
+/// of the timestamps and one of the values, always of the same length.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SeriesBlock {
+    timestamps: FPCCompressedBlock,
+    values: FPCCompressedBlock,
+}
+
+impl SeriesBlock {
+    /// Number of samples in the series.
+    pub fn num_samples(&self) -> usize {
+        self.values.num_values()
+    }
+
+    pub fn timestamps(&self) -> &FPCCompressedBlock {
+        &self.timestamps
+    }
+
+    pub fn values(&self) -> &FPCCompressedBlock {
+        &self.values
+    }
+
+    /// Number of bytes the series was compressed into.
+    pub fn compressed_len(&self) -> usize {
+        self.timestamps.compressed_len() + self.values.compressed_len()
+    }
+}
+
+/// Compresses a series of `timestamps` and their `values` as two blocks,
+/// which beats interleaving them since each stream is smooth on its own.
+/// With `delta_of_delta`, the timestamps go through
+/// [`FpcOptions::delta_of_delta`] first, which turns regularly spaced
+/// timestamps into zeros. Fails with
+/// [`FpcError::ColumnLengthMismatch`](crate::FpcError::ColumnLengthMismatch)
+/// unless there are as many values as timestamps, and like [`compress`] on
+/// an invalid table size.
+pub fn compress_series(
+    table_size: u64,
+    timestamps: &[f64],
+    values: &[f64],
+    delta_of_delta: bool,
+) -> Result<SeriesBlock, CompressError> {
+    check_column_lens([timestamps.len(), values.len()].into_iter())?;
+    let options = FpcOptions { delta_of_delta, ..FpcOptions::default() };
+    let timestamps = compress_with(table_size, timestamps, &options)?;
+    Ok(SeriesBlock { timestamps, values: compress(table_size, values)? })
+}
+
+/// Decompresses a series written by [`compress_series`] with the same
+/// `table_size` into its timestamps and values. Fails like
+/// [`try_decompress`] on a malformed block, and with
+/// [`FpcError::ColumnLengthMismatch`](crate::FpcError::ColumnLengthMismatch)
+/// if the blocks hold different numbers of values.
+pub fn decompress_series(table_size: u64, series: &SeriesBlock) -> Result<(Vec<f64>, Vec<f64>), DecompressError> {
+    check_column_lens([series.timestamps.num_values(), series.values.num_values()].into_iter())?;
+    Ok((try_decompress(table_size, &series.timestamps)?, try_decompress(table_size, &series.values)?))
+}
+
+#[cfg(test)]
+mod series_test {
+    use quickcheck_macros::quickcheck;
+
+    use crate::{bitwise_eq, FpcError, DEFAULT_TABLE_SIZE};
+
+    use super::*;
+
+    #[quickcheck]
+    fn series_must_round_trip(samples: Vec<(f64, f64)>, delta_of_delta: bool) -> bool {
+        let (timestamps, values): (Vec<f64>, Vec<f64>) = samples.into_iter().unzip();
+        let series = compress_series(DEFAULT_TABLE_SIZE, &timestamps, &values, delta_of_delta).unwrap();
+        let (decoded_timestamps, decoded_values) = decompress_series(DEFAULT_TABLE_SIZE, &series).unwrap();
+        series.num_samples() == values.len()
+            && series.timestamps().delta_of_delta() == delta_of_delta
+            && bitwise_eq(&decoded_timestamps, &timestamps)
+            && bitwise_eq(&decoded_values, &values)
+    }
+
+    #[test]
+    fn test_delta_of_delta_shrinks_regular_timestamps() {
+        let timestamps: Vec<f64> = (0..4096).map(|i| 1.7e9 + i as f64 * 0.25).collect();
+        let values: Vec<f64> = (0..4096).map(|i| (i as f64 * 0.01).sin()).collect();
+        let plain = compress_series(DEFAULT_TABLE_SIZE, &timestamps, &values, false).unwrap();
+        let delta = compress_series(DEFAULT_TABLE_SIZE, &timestamps, &values, true).unwrap();
+        assert_eq!(plain.values(), delta.values());
+        assert!(delta.timestamps().compressed_len() < plain.timestamps().compressed_len());
+    }
+
+    #[test]
+    fn test_series_must_have_a_value_per_timestamp() {
+        assert_eq!(
+            compress_series(DEFAULT_TABLE_SIZE, &[1.0, 2.0, 3.0], &[4.0, 5.0], true),
+            Err(FpcError::ColumnLengthMismatch { column: 1, expected: 3, actual: 2 })
+        );
+        assert_eq!(compress_series(3, &[1.0], &[2.0], false), Err(FpcError::InvalidTableSize { given: 3 }));
+        let mut series = compress_series(DEFAULT_TABLE_SIZE, &[1.0, 2.0], &[4.0, 5.0], false).unwrap();
+        series.values = compress(DEFAULT_TABLE_SIZE, &[4.0]).unwrap();
+        assert_eq!(
+            decompress_series(DEFAULT_TABLE_SIZE, &series),
+            Err(FpcError::ColumnLengthMismatch { column: 1, expected: 2, actual: 1 })
+        );
+    }
+}
@@ -0,0 +1,198 @@
+//! Compression of `f32` values on their 32-bit patterns.
+//!
+//! The scheme mirrors the `f64` one with everything scaled to 4 bytes.
+//! Each value still takes a 4-bit code, two per encoding byte with the
+//! first value in the high nibble, and bit 3 still selects FCM over DFCM.
+//! The leading-zero byte count only gets the 2 low bits, so it stores 0,
+//! 1, 2 or 4 zero bytes. A count of 3 is stored as 2 with an extra zero
+//! byte in the residual, keeping exact predictions as cheap as possible.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::{validate_table_size, CompressError, DecompressError, FpcError};
+
+/// A block of compressed `f32` values, produced by [`compress_f32`].
+#[derive(Debug, PartialEq)]
+pub struct FPCCompressedBlockF32 {
+    table_size: u64,
+    num_values: usize,
+    encoding: Vec<u8>,
+    residual: Vec<u8>,
+}
+
+impl FPCCompressedBlockF32 {
+    /// Number of values in the block.
+    pub fn num_values(&self) -> usize {
+        self.num_values
+    }
+
+    /// Size of the compressed data in bytes.
+    pub fn compressed_len(&self) -> usize {
+        self.encoding.len() + self.residual.len()
+    }
+}
+
+/// The 32-bit counterpart of the `f64` predictors. Hashes keep the top 8
+/// (FCM) and 12 (DFCM) bits of each value, half as many as for `f64`.
+struct Predictors32 {
+    table_mask: u64,
+    last_value: u32,
+    fcm_hash: u64,
+    fcm: Vec<u32>,
+    dfcm_hash: u64,
+    dfcm: Vec<u32>,
+}
+
+impl Predictors32 {
+    fn new(table_size: u64) -> Self {
+        Predictors32 {
+            table_mask: table_size - 1,
+            last_value: 0,
+            fcm_hash: 0,
+            fcm: vec![0_u32; table_size as usize],
+            dfcm_hash: 0,
+            dfcm: vec![0_u32; table_size as usize],
+        }
+    }
+
+    fn predict(&self) -> (u32, u32) {
+        let fcm_prediction = self.fcm[self.fcm_hash as usize];
+        let dfcm_prediction = self.dfcm[self.dfcm_hash as usize].wrapping_add(self.last_value);
+        (fcm_prediction, dfcm_prediction)
+    }
+
+    fn update(&mut self, true_value: u32) {
+        self.fcm[self.fcm_hash as usize] = true_value;
+        self.fcm_hash = ((self.fcm_hash << 6) ^ (true_value >> 24) as u64) & self.table_mask;
+
+        let delta = true_value.wrapping_sub(self.last_value);
+        self.dfcm[self.dfcm_hash as usize] = delta;
+        self.dfcm_hash = ((self.dfcm_hash << 2) ^ (delta >> 20) as u64) & self.table_mask;
+        self.last_value = true_value;
+    }
+}
+
+fn residual_len_of_code(code: u8) -> usize {
+    match code & 0b0011 {
+        3 => 0,
+        lzb => 4 - lzb as usize,
+    }
+}
+
+/// Compresses `values` with the 32-bit variant of the predictors. Fails
+/// like [`compress`](crate::compress) on an invalid table size.
+pub fn compress_f32(table_size: u64, values: &[f32]) -> Result<FPCCompressedBlockF32, CompressError> {
+    validate_table_size(table_size)?;
+    let mut predictors = Predictors32::new(table_size);
+    let mut encoding = Vec::with_capacity(values.len().div_ceil(2));
+    let mut residual = Vec::with_capacity(size_of_val(values) / 4);
+    let mut pending_code = None;
+    for value in values {
+        let true_value = value.to_bits();
+        let (fcm_prediction, dfcm_prediction) = predictors.predict();
+        predictors.update(true_value);
+
+        let fcm_diff = fcm_prediction ^ true_value;
+        let dfcm_diff = dfcm_prediction ^ true_value;
+        let to_encode = fcm_diff.min(dfcm_diff);
+        let lzb = match to_encode.leading_zeros() / 8 {
+            3 => 2,
+            4 => 3,
+            lzb => lzb as u8,
+        };
+        let code = lzb | if fcm_diff < dfcm_diff { 1 << 3 } else { 0 };
+        residual.extend_from_slice(&to_encode.to_be_bytes()[4 - residual_len_of_code(code)..]);
+        match pending_code.take() {
+            Some(first) => encoding.push((first << 4) | code),
+            None => pending_code = Some(code),
+        }
+    }
+    if let Some(last) = pending_code {
+        encoding.push(last << 4);
+    }
+    Ok(FPCCompressedBlockF32 { table_size, num_values: values.len(), encoding, residual })
+}
+
+/// Decodes a block written by [`compress_f32`]. Fails like
+/// [`try_decompress`](crate::try_decompress) on an invalid table size, one
+/// other than the block's, or a malformed block.
+pub fn decompress_f32(table_size: u64, blk: &FPCCompressedBlockF32) -> Result<Vec<f32>, DecompressError> {
+    validate_table_size(table_size)?;
+    if table_size != blk.table_size {
+        return Err(FpcError::TableSizeMismatch { block: blk.table_size, given: table_size });
+    }
+    if blk.encoding.len() != blk.num_values.div_ceil(2) {
+        return Err(FpcError::InconsistentBlock { num_values: blk.num_values, encoding_len: blk.encoding.len() });
+    }
+    let mut predictors = Predictors32::new(table_size);
+    let mut values = Vec::with_capacity(blk.num_values);
+    let mut residual_index = 0;
+    let codes = blk.encoding.iter().flat_map(|&byte| [byte >> 4, byte & 0xf]).take(blk.num_values);
+    for (at_value, code) in codes.enumerate() {
+        let bytes = blk
+            .residual
+            .get(residual_index..residual_index + residual_len_of_code(code))
+            .ok_or(FpcError::TruncatedResidual { at_value, residual_len: blk.residual.len() })?;
+        residual_index += bytes.len();
+        let diff = bytes.iter().fold(0_u32, |acc, &b| (acc << 8) | b as u32);
+        let (fcm_prediction, dfcm_prediction) = predictors.predict();
+        let decoded = diff ^ if code & 0b1000 != 0 { fcm_prediction } else { dfcm_prediction };
+        predictors.update(decoded);
+        values.push(f32::from_bits(decoded));
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod single_test {
+    use quickcheck_macros::quickcheck;
+
+    use crate::DEFAULT_TABLE_SIZE;
+
+    use super::*;
+
+    #[quickcheck]
+    fn f32_compression_must_be_reversible(to_compress: Vec<f32>) -> bool {
+        let blk = compress_f32(DEFAULT_TABLE_SIZE, &to_compress).unwrap();
+        let decompressed = decompress_f32(DEFAULT_TABLE_SIZE, &blk).unwrap();
+        decompressed.len() == to_compress.len()
+            && decompressed.iter().zip(&to_compress).all(|(x, y)| x.to_bits() == y.to_bits())
+    }
+
+    #[test]
+    fn test_f32_codes_cover_every_leading_zero_count() {
+        // Predictions start at 0, so the first value's own leading zero
+        // bytes decide its code.
+        for (bits, residual_len) in [(0x8000_0001_u32, 4), (0x0080_0001, 3), (0x0000_8001, 2), (0x0000_0001, 2), (0, 0)] {
+            let blk = compress_f32(DEFAULT_TABLE_SIZE, &[f32::from_bits(bits)]).unwrap();
+            assert_eq!(blk.residual.len(), residual_len, "{bits:#x}");
+            assert_eq!(decompress_f32(DEFAULT_TABLE_SIZE, &blk).unwrap()[0].to_bits(), bits);
+        }
+    }
+
+    #[test]
+    fn test_f32_compresses_no_worse_than_widening_to_f64() {
+        let values: Vec<f32> = (0..1000).map(|x| x as f32 * 0.5).collect();
+        let widened: Vec<f64> = values.iter().map(|&v| v as f64).collect();
+        let narrow = compress_f32(DEFAULT_TABLE_SIZE, &values).unwrap();
+        let wide = crate::compress(DEFAULT_TABLE_SIZE, &widened).unwrap();
+        assert!(narrow.compressed_len() < size_of_val(values.as_slice()));
+        assert!(narrow.compressed_len() <= wide.compressed_len());
+    }
+
+    #[test]
+    fn test_f32_rejects_truncated_residual() {
+        let mut blk = compress_f32(DEFAULT_TABLE_SIZE, &[1.5, 2.5]).unwrap();
+        blk.residual.pop();
+        assert!(matches!(decompress_f32(DEFAULT_TABLE_SIZE, &blk), Err(FpcError::TruncatedResidual { .. })));
+        assert_eq!(compress_f32(3, &[1.0]), Err(FpcError::InvalidTableSize { given: 3 }));
+    }
+
+    #[test]
+    fn test_f32_rejects_other_table_sizes() {
+        let blk = compress_f32(32, &[1.5, 2.5]).unwrap();
+        assert_eq!(decompress_f32(64, &blk), Err(FpcError::TableSizeMismatch { block: 32, given: 64 }));
+        assert_eq!(decompress_f32(0, &blk), Err(FpcError::InvalidTableSize { given: 0 }));
+    }
+}
@@ -0,0 +1,139 @@
+use crate::{compress_block_seeded, try_decompress, FPCCompressedBlock, FpcError, FpcOptions};
+
+/// Compresses `values` from fresh predictors with the settings of `blk`.
+fn recompress(table_size: u64, blk: &FPCCompressedBlock, values: &[f64]) -> FPCCompressedBlock {
+    let options = FpcOptions {
+        hash_params: blk.hash_params,
+        delta_of_delta: blk.delta_of_delta,
+        canonicalize_nan: blk.canonicalize_nan,
+        ..FpcOptions::default()
+    };
+    // Dropped mantissa bits are already zero in decoded values.
+    FPCCompressedBlock {
+        dropped_mantissa_bits: blk.dropped_mantissa_bits,
+        ..compress_block_seeded(table_size, values, &options, blk.seed)
+    }
+}
+
+/// Splits `blk` into a block of its first `at` values and one of the rest,
+/// each compressed from fresh predictors so that it decodes on its own.
+/// The predictor state runs through the whole block, so the streams
+/// cannot simply be cut; the values are decoded and compressed again. Both
+/// halves keep the block's settings, and are identical to compressing
+/// each half of the values with them.
+///
+/// Fails with [`FpcError::InvalidSplit`] unless `0 < at < num_values`,
+/// and like [`try_decompress`] if the block is malformed or `table_size`
+/// is not the block's.
+pub fn split_at(
+    table_size: u64,
+    blk: &FPCCompressedBlock,
+    at: usize,
+) -> Result<(FPCCompressedBlock, FPCCompressedBlock), FpcError> {
+    if at == 0 || at >= blk.num_bytes_encoded {
+        return Err(FpcError::InvalidSplit { at, num_values: blk.num_bytes_encoded });
+    }
+    let values = try_decompress(table_size, blk)?;
+    let (head, tail) = values.split_at(at);
+    Ok((recompress(table_size, blk, head), recompress(table_size, blk, tail)))
+}
+
+/// Merges `a` and `b` into one block of the values of `a` followed by
+/// those of `b`, the reverse of [`split_at`]. The values are decoded and
+/// compressed again as one stream, so the last values of `a` warm up the
+/// predictors for `b`. The block keeps the settings of `a`, seed
+/// included, and is identical to compressing all values with them.
+///
+/// Fails with [`FpcError::ConflictingOptions`] unless both blocks share
+/// their hash parameters, dropped mantissa bits, delta-of-delta stage and
+/// NaN canonicalization, and like [`try_decompress`] if either is
+/// malformed or `table_size` is not its table size.
+pub fn merge(
+    table_size: u64,
+    a: &FPCCompressedBlock,
+    b: &FPCCompressedBlock,
+) -> Result<FPCCompressedBlock, FpcError> {
+    let settings = |blk: &FPCCompressedBlock| {
+        (blk.hash_params, blk.dropped_mantissa_bits, blk.delta_of_delta, blk.canonicalize_nan)
+    };
+    if settings(a) != settings(b) {
+        return Err(FpcError::ConflictingOptions { detail: "merged blocks must share their settings" });
+    }
+    let mut values = try_decompress(table_size, a)?;
+    values.extend(try_decompress(table_size, b)?);
+    Ok(recompress(table_size, a, &values))
+}
+
+#[cfg(test)]
+mod split_test {
+    use quickcheck_macros::quickcheck;
+
+    use crate::{bitwise_eq, compress, compress_lossy, compress_with, compress_with_seed, HashParams, DEFAULT_TABLE_SIZE};
+
+    use super::*;
+
+    #[quickcheck]
+    fn halves_must_match_compressing_each_half(to_compress: Vec<f64>, at: usize, delta_of_delta: bool) -> bool {
+        if to_compress.len() < 2 {
+            return true;
+        }
+        let at = 1 + at % (to_compress.len() - 1);
+        let options = FpcOptions { delta_of_delta, canonicalize_nan: true, ..FpcOptions::default() };
+        let blk = compress_with(DEFAULT_TABLE_SIZE, &to_compress, &options).unwrap();
+        let (head, tail) = split_at(DEFAULT_TABLE_SIZE, &blk, at).unwrap();
+        head == compress_with(DEFAULT_TABLE_SIZE, &to_compress[..at], &options).unwrap()
+            && tail == compress_with(DEFAULT_TABLE_SIZE, &to_compress[at..], &options).unwrap()
+    }
+
+    #[quickcheck]
+    fn merging_must_match_compressing_both(a: Vec<f64>, b: Vec<f64>, delta_of_delta: bool) -> bool {
+        let options = FpcOptions { delta_of_delta, ..FpcOptions::default() };
+        let compressed = |values: &[f64]| compress_with(DEFAULT_TABLE_SIZE, values, &options).unwrap();
+        let merged = merge(DEFAULT_TABLE_SIZE, &compressed(&a), &compressed(&b)).unwrap();
+        let both = [a, b].concat();
+        merged == compressed(&both) && bitwise_eq(&merged.decompress(), &both)
+    }
+
+    #[test]
+    fn test_split_keeps_block_settings() {
+        let values: Vec<f64> = (0..300).map(|x| (x as f64 * 0.01).sin() * 1e3).collect();
+        let hash_params = HashParams { fcm_shift: 5, ..HashParams::DEFAULT };
+        let blk = compress_with(64, &values, &FpcOptions { hash_params, ..FpcOptions::default() }).unwrap();
+        let (head, tail) = split_at(64, &blk, 100).unwrap();
+        assert_eq!((head.hash_params(), tail.hash_params()), (hash_params, hash_params));
+        let (head, tail) = split_at(64, &compress_with_seed(64, &values, 7.5).unwrap(), 100).unwrap();
+        assert_eq!(tail, compress_with_seed(64, &values[100..], 7.5).unwrap());
+        assert_eq!(head.seed(), 7.5);
+        let lossy = compress_lossy(DEFAULT_TABLE_SIZE, &values, 20).unwrap();
+        let (head, tail) = split_at(DEFAULT_TABLE_SIZE, &lossy, 299).unwrap();
+        assert_eq!((head.dropped_mantissa_bits(), tail.num_values()), (lossy.dropped_mantissa_bits(), 1));
+        assert_eq!([head.decompress(), tail.decompress()].concat(), lossy.decompress());
+    }
+
+    #[test]
+    fn test_split_point_must_fall_inside_the_block() {
+        let blk = compress(DEFAULT_TABLE_SIZE, &[1.0, 2.0, 3.0]).unwrap();
+        for at in [0, 3, 4] {
+            assert_eq!(split_at(DEFAULT_TABLE_SIZE, &blk, at), Err(FpcError::InvalidSplit { at, num_values: 3 }));
+        }
+        assert!(matches!(split_at(64, &blk, 1), Err(FpcError::TableSizeMismatch { .. })));
+    }
+
+    #[test]
+    fn test_merge_rejects_mismatched_blocks() {
+        let values: Vec<f64> = (0..100).map(|x| x as f64 * 0.5).collect();
+        let (head, tail) = split_at(DEFAULT_TABLE_SIZE, &compress(DEFAULT_TABLE_SIZE, &values).unwrap(), 40).unwrap();
+        assert_eq!(merge(DEFAULT_TABLE_SIZE, &head, &tail), compress(DEFAULT_TABLE_SIZE, &values));
+        let other = compress(64, &values).unwrap();
+        assert_eq!(
+            merge(DEFAULT_TABLE_SIZE, &head, &other),
+            Err(FpcError::TableSizeMismatch { block: 64, given: DEFAULT_TABLE_SIZE })
+        );
+        let lossy = compress_lossy(DEFAULT_TABLE_SIZE, &values, 20).unwrap();
+        assert!(matches!(merge(DEFAULT_TABLE_SIZE, &head, &lossy), Err(FpcError::ConflictingOptions { .. })));
+        // Settings are compared before anything is decoded.
+        let mut malformed = lossy;
+        malformed.residual.clear();
+        assert!(matches!(merge(DEFAULT_TABLE_SIZE, &head, &malformed), Err(FpcError::ConflictingOptions { .. })));
+    }
+}
@@ -0,0 +1,93 @@
+use alloc::vec::Vec;
+
+use crate::encoding_mode::uniform_bits;
+use crate::{compress_bits_into, validate_table_size, CompressError, FPCCompressedBlock, HashParams};
+
+/// Statistics of the values of a block, gathered by
+/// [`compress_with_stats`] while compressing them.
+///
+/// NaNs only count towards `count` and `nan_count`; `min`, `max` and `sum`
+/// are taken over the other values. Without any, `min` is infinity, `max`
+/// negative infinity and `sum` 0.0.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ValueStats {
+    pub min: f64,
+    pub max: f64,
+    pub sum: f64,
+    /// Number of values, NaNs included.
+    pub count: usize,
+    pub nan_count: usize,
+}
+
+impl Default for ValueStats {
+    fn default() -> Self {
+        ValueStats { min: f64::INFINITY, max: f64::NEG_INFINITY, sum: 0.0, count: 0, nan_count: 0 }
+    }
+}
+
+impl ValueStats {
+    fn push(&mut self, value: f64) {
+        self.count += 1;
+        if value.is_nan() {
+            self.nan_count += 1;
+            return;
+        }
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += value;
+    }
+}
+
+/// Compresses `values` like [`compress`](crate::compress) and gathers
+/// their [`ValueStats`] in the same pass.
+pub fn compress_with_stats(
+    table_size: u64,
+    values: &[f64],
+) -> Result<(FPCCompressedBlock, ValueStats), CompressError> {
+    validate_table_size(table_size)?;
+    let mut stats = ValueStats::default();
+    if let Some(bits) = uniform_bits(values.iter().map(|v| v.to_bits())) {
+        values.iter().for_each(|&v| stats.push(v));
+        return Ok((FPCCompressedBlock::constant(table_size, values.len(), bits), stats));
+    }
+    let mut encoding = Vec::with_capacity(values.len().div_ceil(2));
+    let mut residual = Vec::with_capacity(size_of_val(values) / 4);
+    if !values.is_empty() {
+        let bits = values.iter().map(|&v| {
+            stats.push(v);
+            v.to_bits()
+        });
+        compress_bits_into(table_size, HashParams::DEFAULT, bits, (&mut encoding, &mut residual));
+    }
+    Ok((FPCCompressedBlock::from_parts_unchecked(table_size, values.len(), encoding, residual), stats))
+}
+
+#[cfg(test)]
+mod stats_test {
+    use quickcheck_macros::quickcheck;
+
+    use crate::{compress, FpcError, DEFAULT_TABLE_SIZE};
+
+    use super::*;
+
+    #[quickcheck]
+    fn stats_must_match_a_separate_scan(values: Vec<f64>) -> bool {
+        let (blk, stats) = compress_with_stats(DEFAULT_TABLE_SIZE, &values).unwrap();
+        let numbers: Vec<f64> = values.iter().copied().filter(|v| !v.is_nan()).collect();
+        blk == compress(DEFAULT_TABLE_SIZE, &values).unwrap()
+            && stats.count == values.len()
+            && stats.nan_count == values.len() - numbers.len()
+            && stats.min == numbers.iter().copied().fold(f64::INFINITY, f64::min)
+            && stats.max == numbers.iter().copied().fold(f64::NEG_INFINITY, f64::max)
+            && stats.sum.to_bits() == numbers.iter().fold(0.0, |sum, v| sum + v).to_bits()
+    }
+
+    #[test]
+    fn test_stats_skip_nans() {
+        let (_, stats) = compress_with_stats(DEFAULT_TABLE_SIZE, &[2.0, f64::NAN, -1.5, 4.0, f64::NAN]).unwrap();
+        assert_eq!(stats, ValueStats { min: -1.5, max: 4.0, sum: 4.5, count: 5, nan_count: 2 });
+        let (_, empty) = compress_with_stats(DEFAULT_TABLE_SIZE, &[]).unwrap();
+        assert_eq!(empty, ValueStats::default());
+        assert_eq!(compress_with_stats(3, &[1.0]), Err(FpcError::InvalidTableSize { given: 3 }));
+    }
+}
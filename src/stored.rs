@@ -0,0 +1,139 @@
+use alloc::vec::Vec;
+
+use crate::{
+    compress_block, try_decompress, validate_table_size, CompressError, DecompressError, FPCCompressedBlock, FpcOptions,
+};
+
+/// A block in whichever storage won for its values: the compressed
+/// encoding, or the values themselves when compressing would not have
+/// made them smaller. Both kinds serialize with the same header, see
+/// [`StoredBlock::to_bytes`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum StoredBlock {
+    Compressed(FPCCompressedBlock),
+    /// The values as given. `table_size` is only kept so the header reads
+    /// the same as for a compressed block.
+    Raw { table_size: u64, values: Vec<f64> },
+}
+
+/// Compresses `values`, falling back to storing them raw if the encoding
+/// and residual together would take at least as many bytes as the values.
+/// Fails with [`FpcError::InvalidTableSize`](crate::FpcError::InvalidTableSize)
+/// unless `table_size` is a power of two.
+pub fn compress_or_raw(table_size: u64, values: &[f64]) -> Result<StoredBlock, CompressError> {
+    validate_table_size(table_size)?;
+    let blk = compress_block(table_size, values, &FpcOptions::default());
+    if blk.encoding.len() + blk.residual.len() >= size_of_val(values) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(table_size, num_values = values.len(), "stored the block raw");
+        Ok(StoredBlock::Raw { table_size, values: values.to_vec() })
+    } else {
+        Ok(StoredBlock::Compressed(blk))
+    }
+}
+
+/// Returns the values of `blk`, decompressing it with its own table size
+/// if necessary. Fails like [`try_decompress`] on a malformed block.
+pub fn decompress_stored(blk: &StoredBlock) -> Result<Vec<f64>, DecompressError> {
+    match blk {
+        StoredBlock::Compressed(blk) => try_decompress(blk.table_size, blk),
+        StoredBlock::Raw { values, .. } => Ok(values.clone()),
+    }
+}
+
+#[cfg(test)]
+mod stored_test {
+    use crate::format::FLAG_RAW;
+    use crate::{FpcError, DEFAULT_TABLE_SIZE};
+
+    use super::*;
+
+    fn incompressible(n: usize) -> Vec<f64> {
+        let mut state = 0x9e37_79b9_7f4a_7c15_u64;
+        (0..n)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                f64::from_bits(state)
+            })
+            .filter(|v| !v.is_nan())
+            .collect()
+    }
+
+    #[test]
+    fn test_compress_or_raw_picks_storage() {
+        let smooth: Vec<f64> = (0..1000).map(|i| i as f64).collect();
+        assert!(matches!(compress_or_raw(DEFAULT_TABLE_SIZE, &smooth).unwrap(), StoredBlock::Compressed(_)));
+        let noisy = incompressible(1000);
+        assert!(matches!(compress_or_raw(DEFAULT_TABLE_SIZE, &noisy).unwrap(), StoredBlock::Raw { .. }));
+    }
+
+    #[test]
+    fn test_round_trip_both_storages() {
+        for values in [(0..1000).map(|i| i as f64).collect(), incompressible(1000), vec![]] {
+            let blk = compress_or_raw(DEFAULT_TABLE_SIZE, &values).unwrap();
+            let parsed = StoredBlock::from_bytes(&blk.to_bytes()).unwrap();
+            assert_eq!(parsed, blk);
+            let decoded = decompress_stored(&parsed).unwrap();
+            assert_eq!(decoded.len(), values.len());
+            assert!(decoded.iter().zip(&values).all(|(a, b)| a.to_bits() == b.to_bits()));
+        }
+    }
+
+    #[test]
+    fn test_raw_values_are_little_endian() {
+        let values = vec![1.5, -0.0, f64::INFINITY, 1e-310];
+        let blk = StoredBlock::Raw { table_size: DEFAULT_TABLE_SIZE, values: values.clone() };
+        let bytes = blk.to_bytes();
+        let expected: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        assert_eq!(&bytes[crate::layout(&bytes).unwrap().residual], expected.as_slice());
+
+        // Spelled out byte by byte, so the block reads back the same on a
+        // host of either endianness.
+        let mut one = StoredBlock::Raw { table_size: DEFAULT_TABLE_SIZE, values: vec![0.0] }.to_bytes();
+        one.truncate(one.len() - 8);
+        one.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0xf8, 0x3f]);
+        assert_eq!(decompress_stored(&StoredBlock::from_bytes(&one).unwrap()), Ok(vec![1.5]));
+    }
+
+    #[test]
+    fn test_stored_blocks_fail_instead_of_panicking() {
+        assert_eq!(compress_or_raw(3, &[1.0]), Err(FpcError::InvalidTableSize { given: 3 }));
+        let mut blk = crate::compress(DEFAULT_TABLE_SIZE, &[1.0, 2.5, -3.0]).unwrap();
+        blk.residual.truncate(4);
+        assert!(matches!(
+            decompress_stored(&StoredBlock::Compressed(blk)),
+            Err(FpcError::TruncatedResidual { .. })
+        ));
+    }
+
+    #[test]
+    fn test_flag_distinguishes_raw_from_compressed() {
+        let values: Vec<f64> = (0..10).map(|i| i as f64).collect();
+        let compressed = StoredBlock::Compressed(crate::compress(DEFAULT_TABLE_SIZE, &values).unwrap()).to_bytes();
+        let raw = StoredBlock::Raw { table_size: DEFAULT_TABLE_SIZE, values }.to_bytes();
+        let flags = |bytes: &[u8]| bytes[crate::layout(bytes).unwrap().flags.start];
+        assert_eq!(flags(&compressed) & FLAG_RAW, 0);
+        assert_eq!(flags(&raw) & FLAG_RAW, FLAG_RAW);
+
+        assert!(FPCCompressedBlock::from_bytes(&compressed).is_ok());
+        assert_eq!(FPCCompressedBlock::from_bytes(&raw), Err(FpcError::UnexpectedRawBlock));
+    }
+
+    #[test]
+    fn test_raw_block_with_bad_lengths_is_rejected() {
+        let mut bytes = StoredBlock::Raw { table_size: DEFAULT_TABLE_SIZE, values: vec![1.0, 2.0] }.to_bytes();
+        let layout = crate::layout(&bytes).unwrap();
+        bytes[layout.residual_len.clone()].copy_from_slice(&8_u64.to_le_bytes());
+        bytes.truncate(bytes.len() - 8);
+        assert_eq!(
+            StoredBlock::from_bytes(&bytes),
+            Err(FpcError::ResidualLengthMismatch { expected: 16, actual: 8 })
+        );
+
+        let mut bytes = StoredBlock::Raw { table_size: DEFAULT_TABLE_SIZE, values: vec![1.0] }.to_bytes();
+        bytes[layout.flags.start] |= 1;
+        assert_eq!(StoredBlock::from_bytes(&bytes), Err(FpcError::UnsupportedFlags { flags: FLAG_RAW | 1 }));
+    }
+}
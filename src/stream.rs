@@ -0,0 +1,263 @@
+use std::io::{ErrorKind, Read};
+use std::iter::FusedIterator;
+
+use crate::format::{parse_header, split_payload, Header, HEADER_LEN};
+use crate::{decompress_stored, try_decompress, FPCCompressedBlock, FpcError, ParseError, StoredBlock};
+
+/// Reads into `buf` until it is full or the reader is exhausted, returning
+/// how many bytes were read.
+pub(crate) fn read_up_to<R: Read>(r: &mut R, buf: &mut [u8]) -> Result<usize, FpcError> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match r.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(filled)
+}
+
+/// Reads the next serialized block from `r`. Returns `Ok(None)` if the
+/// reader is exhausted exactly at a block boundary.
+pub(crate) fn read_block<R: Read>(r: &mut R) -> Result<Option<StoredBlock>, FpcError> {
+    let mut header_bytes = [0_u8; HEADER_LEN];
+    let header_read = read_up_to(r, &mut header_bytes)?;
+    if header_read == 0 {
+        return Ok(None);
+    }
+    let header = parse_header(&header_bytes[..header_read])?;
+    // Grow the payload as bytes arrive rather than trusting the declared
+    // lengths with one large allocation up front.
+    let mut payload = Vec::new();
+    r.take(header.payload_len() as u64).read_to_end(&mut payload)?;
+    block_from_payload(&header, payload).map(Some)
+}
+
+/// Turns the bytes read after `header` into the block, checking that all
+/// of them arrived.
+pub(crate) fn block_from_payload(header: &Header, mut payload: Vec<u8>) -> Result<StoredBlock, FpcError> {
+    if payload.len() < header.payload_len() {
+        return Err(FpcError::TruncatedPayload {
+            expected: header.block_len(),
+            available: HEADER_LEN + payload.len(),
+        });
+    }
+    let params = split_payload(header, &payload)?.params;
+    let encoding_start = header.params_len();
+    payload.truncate(encoding_start + header.encoding_len + header.residual_len);
+    let residual = payload.split_off(encoding_start + header.encoding_len);
+    payload.drain(..encoding_start);
+    #[cfg(feature = "tracing")]
+    tracing::trace!(block_len = header.block_len(), "read block");
+    StoredBlock::from_header_and_payload(header, params, payload, residual)
+}
+
+/// Decodes a block read from a stream, taking the values of a raw block
+/// without copying them.
+pub(crate) fn into_values(blk: StoredBlock) -> Result<Vec<f64>, FpcError> {
+    match blk {
+        StoredBlock::Compressed(blk) => try_decompress(blk.table_size(), &blk),
+        StoredBlock::Raw { values, .. } => Ok(values),
+    }
+}
+
+impl FPCCompressedBlock {
+    /// Reads one serialized block from `r`, consuming exactly its bytes.
+    /// Raw blocks are rejected as in [`FPCCompressedBlock::from_bytes`].
+    pub fn from_reader<R: Read>(r: &mut R) -> Result<FPCCompressedBlock, ParseError> {
+        match read_block(r)? {
+            Some(StoredBlock::Compressed(blk)) => Ok(blk),
+            Some(StoredBlock::Raw { .. }) => Err(FpcError::UnexpectedRawBlock),
+            None => Err(FpcError::TruncatedHeader { len: 0 }),
+        }
+    }
+}
+
+/// Decompresses a concatenation of serialized blocks, yielding the values
+/// of one block at a time. Each block is decoded with the table size
+/// recorded in its own header, so blocks tuned to different sizes can be
+/// mixed freely. Raw blocks are read back as they were stored. The
+/// iterator ends at the first error.
+pub fn decompress_stream<R: Read>(r: &mut R) -> impl Iterator<Item = Result<Vec<f64>, FpcError>> + '_ {
+    let mut failed = false;
+    std::iter::from_fn(move || {
+        if failed {
+            return None;
+        }
+        let values = read_block(r).transpose()?.and_then(|blk| decompress_stored(&blk));
+        failed = values.is_err();
+        Some(values)
+    })
+}
+
+/// Reads the values of a concatenation of serialized blocks, such as the
+/// output of [`FpcWriter`](crate::FpcWriter), one value at a time. Only the
+/// block being read is held in memory.
+///
+/// The stream ends cleanly when the reader is exhausted at a block
+/// boundary. I/O failures surface as [`FpcError::Io`] and malformed blocks
+/// as the matching parse or decompression error, after which nothing more
+/// is read.
+pub struct FpcReader<R: Read> {
+    inner: R,
+    block: Vec<f64>,
+    position: usize,
+    done: bool,
+}
+
+impl<R: Read> FpcReader<R> {
+    pub fn new(inner: R) -> FpcReader<R> {
+        FpcReader { inner, block: Vec::new(), position: 0, done: false }
+    }
+
+    /// Fills `out` with the next values and returns how many were written,
+    /// which is less than `out.len()` only at the end of the stream.
+    pub fn read_values(&mut self, out: &mut [f64]) -> Result<usize, FpcError> {
+        for (written, slot) in out.iter_mut().enumerate() {
+            match self.next() {
+                Some(value) => *slot = value?,
+                None => return Ok(written),
+            }
+        }
+        Ok(out.len())
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Reads blocks until one has values, returning `false` at the end of
+    /// the stream.
+    fn next_block(&mut self) -> Result<bool, FpcError> {
+        while self.position == self.block.len() {
+            self.block = match read_block(&mut self.inner)? {
+                None => return Ok(false),
+                Some(blk) => into_values(blk)?,
+            };
+            self.position = 0;
+        }
+        Ok(true)
+    }
+}
+
+impl<R: Read> Iterator for FpcReader<R> {
+    type Item = Result<f64, FpcError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.next_block() {
+            Ok(true) => {
+                self.position += 1;
+                Some(Ok(self.block[self.position - 1]))
+            }
+            Ok(false) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl<R: Read> FusedIterator for FpcReader<R> {}
+
+#[cfg(test)]
+mod stream_test {
+    use crate::compress;
+
+    use super::*;
+
+    #[test]
+    fn test_decompress_stream_honors_per_block_table_sizes() {
+        let blocks: Vec<(u64, Vec<f64>)> = vec![
+            (32, (0..100).map(|x| x as f64 * 0.5).collect()),
+            (256, (0..57).map(|x| (x as f64).sqrt()).collect()),
+            (1024, vec![f64::NAN, -0.0, 1e-310, 42.0]),
+        ];
+        let mut bytes = vec![];
+        for (table_size, vals) in &blocks {
+            bytes.extend(compress(*table_size, vals).unwrap().to_bytes());
+        }
+        let decoded: Vec<Vec<f64>> = decompress_stream(&mut bytes.as_slice())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(decoded.len(), blocks.len());
+        for ((_, expected), actual) in blocks.iter().zip(&decoded) {
+            assert_eq!(expected.len(), actual.len());
+            assert!(expected.iter().zip(actual).all(|(x, y)| x.to_bits() == y.to_bits()));
+        }
+    }
+
+    #[test]
+    fn test_decompress_stream_reads_raw_blocks() {
+        let raw = StoredBlock::Raw { table_size: 32, values: vec![1.0, -2.5] };
+        let bytes = [compress(32, &[3.0, 4.0]).unwrap().to_bytes(), raw.to_bytes()].concat();
+        let decoded: Vec<Vec<f64>> = decompress_stream(&mut bytes.as_slice())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(decoded, vec![vec![3.0, 4.0], vec![1.0, -2.5]]);
+    }
+
+    #[test]
+    fn test_decompress_stream_stops_at_truncated_block() {
+        let first = compress(32, &[1.0, 2.0, 3.0]).unwrap().to_bytes();
+        let second = compress(64, &[4.0, 5.0]).unwrap().to_bytes();
+        let mut bytes = [first.clone(), second].concat();
+        bytes.truncate(first.len() + 20);
+        let mut reader = bytes.as_slice();
+        let mut stream = decompress_stream(&mut reader);
+        assert_eq!(stream.next(), Some(Ok(vec![1.0, 2.0, 3.0])));
+        assert_eq!(stream.next(), Some(Err(FpcError::TruncatedHeader { len: 20 })));
+        assert_eq!(stream.next(), None);
+    }
+
+    #[test]
+    fn test_reader_yields_values_across_blocks() {
+        let values: Vec<f64> = (0..50).map(|x| x as f64 * 1.5).collect();
+        let mut writer = crate::FpcWriter::with_block_len(Vec::new(), 32, 16).unwrap();
+        writer.write_all(&values).unwrap();
+        let mut bytes = writer.finish().unwrap();
+        bytes.extend(StoredBlock::Raw { table_size: 32, values: vec![] }.to_bytes());
+        bytes.extend(StoredBlock::Raw { table_size: 32, values: vec![-1.0] }.to_bytes());
+
+        let read: Vec<f64> = FpcReader::new(bytes.as_slice()).collect::<Result<_, _>>().unwrap();
+        assert_eq!(read, [values.as_slice(), &[-1.0]].concat());
+
+        let mut reader = FpcReader::new(bytes.as_slice());
+        let mut out = [0.0; 40];
+        assert_eq!(reader.read_values(&mut out), Ok(40));
+        assert_eq!(out, values[..40]);
+        assert_eq!(reader.read_values(&mut out), Ok(11));
+        assert_eq!(out[10], -1.0);
+        assert_eq!(reader.read_values(&mut out), Ok(0));
+    }
+
+    #[test]
+    fn test_reader_stops_at_the_first_error() {
+        let mut bytes = compress(32, &[1.0, 2.0]).unwrap().to_bytes();
+        bytes.extend_from_slice(b"FPC");
+        let mut reader = FpcReader::new(bytes.as_slice());
+        assert_eq!(reader.next(), Some(Ok(1.0)));
+        assert_eq!(reader.next(), Some(Ok(2.0)));
+        assert_eq!(reader.next(), Some(Err(FpcError::TruncatedHeader { len: 3 })));
+        assert_eq!(reader.next(), None);
+
+        struct Failing;
+        impl Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(ErrorKind::ConnectionReset.into())
+            }
+        }
+        assert!(matches!(
+            FpcReader::new(Failing).next(),
+            Some(Err(FpcError::Io { kind: ErrorKind::ConnectionReset, .. }))
+        ));
+    }
+}
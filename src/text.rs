@@ -0,0 +1,164 @@
+//! Text forms of a serialized block, for JSON, logs and other places that
+//! do not take binary. Both wrap [`FPCCompressedBlock::to_bytes`] and
+//! [`FPCCompressedBlock::from_bytes`].
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::{FPCCompressedBlock, FpcError, ParseError};
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+fn hex_digit(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(2 * bytes.len());
+    for &byte in bytes {
+        text.push(HEX_DIGITS[(byte >> 4) as usize] as char);
+        text.push(HEX_DIGITS[(byte & 0x0f) as usize] as char);
+    }
+    text
+}
+
+fn decode_hex(text: &str) -> Result<Vec<u8>, FpcError> {
+    let text = text.as_bytes();
+    if !text.len().is_multiple_of(2) {
+        return Err(FpcError::InvalidText { position: text.len() - 1 });
+    }
+    let digit = |position: usize| hex_digit(text[position]).ok_or(FpcError::InvalidText { position });
+    (0..text.len()).step_by(2).map(|position| Ok(digit(position)? << 4 | digit(position + 1)?)).collect()
+}
+
+#[cfg(feature = "base64")]
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+#[cfg(feature = "base64")]
+fn base64_digit(c: u8) -> Option<u32> {
+    BASE64_ALPHABET.iter().position(|&digit| digit == c).map(|digit| digit as u32)
+}
+
+/// Standard base64 of RFC 4648, padded with `=` to whole groups of 4.
+#[cfg(feature = "base64")]
+fn encode_base64(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(4 * bytes.len().div_ceil(3));
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0_u32, |group, (i, &byte)| group | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(BASE64_ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+#[cfg(feature = "base64")]
+fn decode_base64(text: &str) -> Result<Vec<u8>, FpcError> {
+    let text = text.as_bytes();
+    if !text.len().is_multiple_of(4) {
+        return Err(FpcError::InvalidText { position: text.len() - text.len() % 4 });
+    }
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+    for (start, group) in (0..text.len()).step_by(4).zip(text.chunks(4)) {
+        // Only the last group may be padded, with at most two `=`.
+        let padding = if start + 4 == text.len() { group.iter().rev().take_while(|&&c| c == b'=').count() } else { 0 };
+        if padding > 2 {
+            return Err(FpcError::InvalidText { position: start + 4 - padding });
+        }
+        let mut value = 0;
+        for (i, &c) in group[..4 - padding].iter().enumerate() {
+            value |= base64_digit(c).ok_or(FpcError::InvalidText { position: start + i })? << (18 - 6 * i);
+        }
+        bytes.extend_from_slice(&value.to_be_bytes()[1..4 - padding]);
+    }
+    Ok(bytes)
+}
+
+impl FPCCompressedBlock {
+    /// The bytes of [`FPCCompressedBlock::to_bytes`] as lowercase hex.
+    pub fn to_hex(&self) -> String {
+        encode_hex(&self.to_bytes())
+    }
+
+    /// Parses a block written by [`FPCCompressedBlock::to_hex`], taking
+    /// digits of either case. Fails with [`FpcError::InvalidText`] if
+    /// `text` is not hex, and like [`FPCCompressedBlock::from_bytes`] if the
+    /// bytes are not a block.
+    pub fn from_hex(text: &str) -> Result<FPCCompressedBlock, ParseError> {
+        FPCCompressedBlock::from_bytes(&decode_hex(text)?)
+    }
+
+    /// The bytes of [`FPCCompressedBlock::to_bytes`] in standard, padded
+    /// base64.
+    #[cfg(feature = "base64")]
+    pub fn to_base64(&self) -> String {
+        encode_base64(&self.to_bytes())
+    }
+
+    /// Parses a block written by [`FPCCompressedBlock::to_base64`]. Fails
+    /// with [`FpcError::InvalidText`] if `text` is not padded base64, and
+    /// like [`FPCCompressedBlock::from_bytes`] if the bytes are not a block.
+    #[cfg(feature = "base64")]
+    pub fn from_base64(text: &str) -> Result<FPCCompressedBlock, ParseError> {
+        FPCCompressedBlock::from_bytes(&decode_base64(text)?)
+    }
+}
+
+#[cfg(test)]
+mod text_test {
+    use quickcheck_macros::quickcheck;
+
+    use crate::{compress, DEFAULT_TABLE_SIZE};
+
+    use super::*;
+
+    #[quickcheck]
+    fn hex_must_round_trip(to_compress: Vec<f64>) -> bool {
+        let blk = compress(DEFAULT_TABLE_SIZE, &to_compress).unwrap();
+        let text = blk.to_hex();
+        text.len() == 2 * blk.to_bytes().len()
+            && FPCCompressedBlock::from_hex(&text) == Ok(blk.clone())
+            && FPCCompressedBlock::from_hex(&text.to_uppercase()) == Ok(blk)
+    }
+
+    #[test]
+    fn test_decode_hex_rejects_bad_text() {
+        assert_eq!(decode_hex("00ff7A"), Ok(vec![0x00, 0xff, 0x7a]));
+        assert_eq!(decode_hex("0g"), Err(FpcError::InvalidText { position: 1 }));
+        assert_eq!(decode_hex("abc"), Err(FpcError::InvalidText { position: 2 }));
+        assert_eq!(FPCCompressedBlock::from_hex(""), Err(FpcError::TruncatedHeader { len: 0 }));
+    }
+
+    #[cfg(feature = "base64")]
+    #[quickcheck]
+    fn base64_must_round_trip(to_compress: Vec<f64>, bytes: Vec<u8>) -> bool {
+        let blk = compress(DEFAULT_TABLE_SIZE, &to_compress).unwrap();
+        FPCCompressedBlock::from_base64(&blk.to_base64()) == Ok(blk)
+            && decode_base64(&encode_base64(&bytes)).as_ref() == Ok(&bytes)
+    }
+
+    #[cfg(feature = "base64")]
+    #[test]
+    fn test_base64_matches_rfc_4648_vectors() {
+        let vectors =
+            [("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v"), ("foob", "Zm9vYg=="), ("foobar", "Zm9vYmFy")];
+        for (bytes, text) in vectors {
+            assert_eq!(encode_base64(bytes.as_bytes()), text);
+            assert_eq!(decode_base64(text).unwrap(), bytes.as_bytes());
+        }
+        assert_eq!(decode_base64("Zm9"), Err(FpcError::InvalidText { position: 0 }));
+        assert_eq!(decode_base64("Zm=v"), Err(FpcError::InvalidText { position: 2 }));
+        assert_eq!(decode_base64("Zg==Zm9v"), Err(FpcError::InvalidText { position: 2 }));
+        assert_eq!(decode_base64("Z==="), Err(FpcError::InvalidText { position: 1 }));
+        assert_eq!(decode_base64("Zm9v*A=="), Err(FpcError::InvalidText { position: 4 }));
+    }
+}
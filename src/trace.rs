@@ -0,0 +1,153 @@
+//! The span shared by the decompression functions, and tests for all of
+//! the crate's tracing output. Without the `tracing` feature the span
+//! expands to nothing and its arguments are never evaluated.
+
+/// Enters a `decompress` span lasting until the end of the enclosing
+/// block.
+macro_rules! decompress_span {
+    ($table_size:expr, $blk:expr) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "decompress",
+            table_size = $table_size,
+            num_values = $blk.num_values(),
+            compressed_len = $blk.compressed_len(),
+        )
+        .entered();
+    };
+}
+
+pub(crate) use decompress_span;
+
+#[cfg(all(test, feature = "tracing"))]
+mod trace_test {
+    use std::fmt::Debug;
+    use std::sync::{Arc, Mutex};
+
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    use crate::{
+        compress, compress_or_raw, compress_with, decompress, try_decompress, FpcOptions, FpcReader, FpcWriter,
+        ResidualSizing,
+    };
+
+    /// Records every span and event as its name or message followed by its
+    /// fields, with the fields recorded later appended to the span.
+    #[derive(Clone, Default)]
+    struct Recorder {
+        lines: Arc<Mutex<Vec<String>>>,
+    }
+
+    struct Fields<'a>(&'a mut String);
+
+    impl Visit for Fields<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            if field.name() == "message" {
+                self.0.push_str(&format!("{value:?}"));
+            } else {
+                self.0.push_str(&format!(" {}={value:?}", field.name()));
+            }
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut lines = self.lines.lock().unwrap();
+            let mut line = span.metadata().name().to_string();
+            span.record(&mut Fields(&mut line));
+            lines.push(line);
+            Id::from_u64(lines.len() as u64)
+        }
+
+        fn record(&self, span: &Id, values: &Record<'_>) {
+            let mut lines = self.lines.lock().unwrap();
+            values.record(&mut Fields(&mut lines[span.into_u64() as usize - 1]));
+        }
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let mut line = String::new();
+            event.record(&mut Fields(&mut line));
+            self.lines.lock().unwrap().push(line);
+        }
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    fn recorded(f: impl FnOnce()) -> Vec<String> {
+        let recorder = Recorder::default();
+        tracing::subscriber::with_default(recorder.clone(), f);
+        let lines = recorder.lines.lock().unwrap();
+        lines.clone()
+    }
+
+    #[test]
+    fn test_compress_and_decompress_record_block_sizes() {
+        let values: Vec<f64> = (0..100).map(|x| x as f64).collect();
+        let mut compressed_len = 0;
+        let lines = recorded(|| {
+            let blk = compress(64, &values).unwrap();
+            compressed_len = blk.compressed_len();
+            decompress(64, &blk);
+            try_decompress(64, &blk).unwrap();
+        });
+        let decompress_line = format!("decompress table_size=64 num_values=100 compressed_len={compressed_len}");
+        assert_eq!(
+            lines,
+            [
+                format!("compress table_size=64 num_values=100 compressed_len={compressed_len} mode=Full"),
+                decompress_line.clone(),
+                decompress_line,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fallbacks_emit_events() {
+        let mut state = 0x9e37_79b9_7f4a_7c15_u64;
+        let noise: Vec<f64> = (0..64)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                f64::from_bits(state)
+            })
+            .collect();
+        let lines = recorded(|| {
+            compress_or_raw(32, &noise).unwrap();
+        });
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("residual outgrew its conservative capacity residual_capacity=128 "));
+        assert_eq!(lines[2], "stored the block raw table_size=32 num_values=64");
+
+        // Sizing the residual exactly never outgrows it.
+        let options = FpcOptions { residual_sizing: ResidualSizing::Exact, ..FpcOptions::default() };
+        let lines = recorded(|| {
+            compress_with(32, &noise, &options).unwrap();
+        });
+        assert_eq!(lines.len(), 1);
+    }
+
+    #[test]
+    fn test_streams_record_every_block() {
+        let lines = recorded(|| {
+            let mut writer = FpcWriter::with_block_len(Vec::new(), 32, 2).unwrap();
+            writer.write_all(&[1.0, 2.0, 3.0]).unwrap();
+            let bytes = writer.finish().unwrap();
+            FpcReader::new(bytes.as_slice()).for_each(drop);
+        });
+        let blocks: Vec<&str> = lines.iter().map(String::as_str).filter(|line| line.contains(" block")).collect();
+        assert_eq!(blocks.len(), 4);
+        assert!(blocks[..2].iter().all(|line| line.starts_with("wrote block")));
+        assert!(blocks[2..].iter().all(|line| line.starts_with("read block")));
+    }
+}
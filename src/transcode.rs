@@ -0,0 +1,112 @@
+use alloc::vec::Vec;
+
+use crate::{decode_value, encode_bits, validate_table_size, EncodingMode, FPCCompressedBlock, FpcError, Predictors};
+
+/// Recompresses `blk` with predictor tables of `new_table_size`, feeding
+/// the decoded bit patterns straight into the new predictors without
+/// collecting them as `f64`s. The result is identical to decompressing
+/// the block and compressing the values again with the same settings; the
+/// hash parameters, the dropped mantissa bits, the seed and any
+/// delta-of-delta stage carry over, and the codes are always written in
+/// [`EncodingMode::Full`] unless the block is constant.
+///
+/// Fails like [`try_decompress`](crate::try_decompress) if
+/// `old_table_size` is not the block's or the block is malformed, and with
+/// [`FpcError::InvalidTableSize`] on an invalid `new_table_size`.
+pub fn transcode(
+    old_table_size: u64,
+    new_table_size: u64,
+    blk: &FPCCompressedBlock,
+) -> Result<FPCCompressedBlock, FpcError> {
+    validate_table_size(old_table_size)?;
+    blk.check_table_size(old_table_size)?;
+    blk.check_encoding_len()?;
+    validate_table_size(new_table_size)?;
+    // A constant block does not depend on the tables.
+    if blk.mode == EncodingMode::Constant {
+        return Ok(FPCCompressedBlock { table_size: new_table_size, ..blk.clone() });
+    }
+    let mut encoding = Vec::with_capacity(blk.num_bytes_encoded.div_ceil(2));
+    let mut residual = Vec::with_capacity(blk.residual.len());
+    if blk.num_bytes_encoded != 0 {
+        let mut predictors = blk.predictors(old_table_size);
+        let mut residual_index = 0;
+        // Index of the value the residual ran out at, if it did.
+        let mut truncated_at = None;
+        // Delta-of-delta blocks stay transformed, so the bits are taken as
+        // decoded without undoing it.
+        let bits = blk.codes().enumerate().map_while(|(index, code)| {
+            let bits = decode_value(&mut predictors, code, &blk.residual, &mut residual_index);
+            if bits.is_none() {
+                truncated_at = Some(index);
+            }
+            bits
+        });
+        let mut new_predictors = Predictors::with_hash_params(new_table_size, blk.hash_params).seeded(blk.seed);
+        encode_bits(&mut new_predictors, bits, (&mut encoding, &mut residual), None);
+        if let Some(at_value) = truncated_at {
+            return Err(FpcError::TruncatedResidual { at_value, residual_len: blk.residual.len() });
+        }
+    }
+    Ok(FPCCompressedBlock {
+        table_size: new_table_size,
+        num_bytes_encoded: blk.num_bytes_encoded,
+        mode: EncodingMode::Full,
+        hash_params: blk.hash_params,
+        dropped_mantissa_bits: blk.dropped_mantissa_bits,
+        delta_of_delta: blk.delta_of_delta,
+        seed: blk.seed,
+        canonicalize_nan: blk.canonicalize_nan,
+        encoding,
+        residual,
+    })
+}
+
+#[cfg(test)]
+mod transcode_test {
+    use quickcheck_macros::quickcheck;
+
+    use crate::{compress, compress_compact, compress_with, FpcOptions, HashParams, DEFAULT_TABLE_SIZE};
+
+    use super::*;
+
+    #[quickcheck]
+    fn transcoding_must_match_recompressing(to_compress: Vec<f64>, log_size: u8) -> bool {
+        let new_table_size = 1 << (log_size % 16);
+        let blk = compress(DEFAULT_TABLE_SIZE, &to_compress).unwrap();
+        transcode(DEFAULT_TABLE_SIZE, new_table_size, &blk) == compress(new_table_size, &to_compress)
+    }
+
+    #[test]
+    fn test_transcode_keeps_block_settings() {
+        let values: Vec<f64> = (0..500).map(|x| (x as f64 * 0.01).sin() * 1e3).collect();
+        let options = FpcOptions {
+            hash_params: HashParams { fcm_shift: 5, ..HashParams::DEFAULT },
+            delta_of_delta: true,
+            ..FpcOptions::default()
+        };
+        let blk = compress_with(16, &values, &options).unwrap();
+        assert_eq!(transcode(16, 1024, &blk), compress_with(1024, &values, &options));
+
+        let compact = compress_compact(16, &crate::encoding_mode::encoding_mode_test::noisy(99)).unwrap();
+        assert_eq!(compact.mode(), EncodingMode::Compact);
+        let transcoded = transcode(16, 64, &compact).unwrap();
+        assert_eq!(transcoded.mode(), EncodingMode::Full);
+        assert_eq!(transcoded.decompress(), compact.decompress());
+    }
+
+    #[test]
+    fn test_transcode_rejects_mismatched_and_malformed_blocks() {
+        let mut blk = compress(DEFAULT_TABLE_SIZE, &[1.0, 2.0, -7.5]).unwrap();
+        assert_eq!(
+            transcode(64, DEFAULT_TABLE_SIZE, &blk),
+            Err(FpcError::TableSizeMismatch { block: DEFAULT_TABLE_SIZE, given: 64 })
+        );
+        assert_eq!(transcode(DEFAULT_TABLE_SIZE, 3, &blk), Err(FpcError::InvalidTableSize { given: 3 }));
+        blk.residual.truncate(4);
+        assert_eq!(
+            transcode(DEFAULT_TABLE_SIZE, 64, &blk),
+            Err(FpcError::TruncatedResidual { at_value: 0, residual_len: 4 })
+        );
+    }
+}
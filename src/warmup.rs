@@ -0,0 +1,129 @@
+use alloc::vec::Vec;
+
+use crate::{
+    encode_bits, validate_table_size, CompressError, DecompressError, EncodingMode, FPCCompressedBlock, FpcError,
+    HashParams, Predictors,
+};
+
+fn primed_predictors(table_size: u64, warmup: impl Iterator<Item = f64>) -> Predictors {
+    let mut predictors = Predictors::new(table_size);
+    for value in warmup {
+        predictors.update(value.to_bits());
+    }
+    predictors
+}
+
+/// Compresses `values[skip..]` with predictors primed on `context`
+/// followed by `values[..skip]`, none of which end up in the block.
+///
+/// With `skip == 0` this is a plain warm start from `context`. A positive
+/// `skip` additionally leaves the first values of the series itself out
+/// of the block, for streams where the decoder always has them at hand.
+/// Either way, [`decompress_after_warmup`] must be given exactly the same
+/// warm-up values, `context` followed by `values[..skip]`, to decode it.
+///
+/// Fails with [`FpcError::InvalidTableSize`] unless `table_size` is a
+/// power of two, and with [`FpcError::RangeOutOfBounds`] if
+/// `skip > values.len()`.
+pub fn compress_skip_warmup(
+    table_size: u64,
+    context: &[f64],
+    values: &[f64],
+    skip: usize,
+) -> Result<FPCCompressedBlock, CompressError> {
+    validate_table_size(table_size)?;
+    let (warmup, stored) = values
+        .split_at_checked(skip)
+        .ok_or(FpcError::RangeOutOfBounds { start: 0, end: skip, num_values: values.len() })?;
+    let mut predictors = primed_predictors(table_size, context.iter().chain(warmup).copied());
+    let mut encoding = Vec::with_capacity(stored.len().div_ceil(2));
+    let mut residual = Vec::with_capacity(size_of_val(stored) / 4);
+    encode_bits(&mut predictors, stored.iter().map(|v| v.to_bits()), (&mut encoding, &mut residual), None);
+    Ok(FPCCompressedBlock {
+        table_size,
+        num_bytes_encoded: stored.len(),
+        mode: EncodingMode::Full,
+        hash_params: HashParams::DEFAULT,
+        dropped_mantissa_bits: 0,
+        delta_of_delta: false,
+        seed: 0,
+        canonicalize_nan: false,
+        encoding,
+        residual,
+    })
+}
+
+/// Decodes a block written by [`compress_skip_warmup`], priming the
+/// predictors with `warmup` first. The warm-up values are not part of the
+/// result. Fails like [`try_decompress`](crate::try_decompress) on a
+/// malformed block or a `table_size` other than the block's.
+pub fn decompress_after_warmup(
+    table_size: u64,
+    warmup: &[f64],
+    blk: &FPCCompressedBlock,
+) -> Result<Vec<f64>, DecompressError> {
+    validate_table_size(table_size)?;
+    blk.check_table_size(table_size)?;
+    blk.check_encoding_len()?;
+    blk.iter_from(primed_predictors(table_size, warmup.iter().copied())).collect()
+}
+
+#[cfg(test)]
+mod warmup_test {
+    use crate::{compress, DEFAULT_TABLE_SIZE};
+
+    use super::*;
+
+    fn series() -> Vec<f64> {
+        (0..64).map(|x| ((x % 8) as f64 * 0.25).exp()).collect()
+    }
+
+    #[test]
+    fn test_decompress_after_warmup_reconstructs_stored_values() {
+        let context = series();
+        let values: Vec<f64> = series().iter().map(|x| x + 1.0).collect();
+        for skip in [0, 1, 10, values.len()] {
+            let blk = compress_skip_warmup(DEFAULT_TABLE_SIZE, &context, &values, skip).unwrap();
+            let warmup = [&context[..], &values[..skip]].concat();
+            let decoded = decompress_after_warmup(DEFAULT_TABLE_SIZE, &warmup, &blk).unwrap();
+            assert_eq!(decoded, values[skip..]);
+        }
+    }
+
+    #[test]
+    fn test_skipping_warmup_shrinks_the_block() {
+        let values = series();
+        let cold = compress(DEFAULT_TABLE_SIZE, &values).unwrap();
+        let warm = compress_skip_warmup(DEFAULT_TABLE_SIZE, &[], &values, 16).unwrap();
+        assert_eq!(warm.num_bytes_encoded, values.len() - 16);
+        assert!(warm.residual.len() < cold.residual.len());
+        assert!(warm.encoding.len() < cold.encoding.len());
+    }
+
+    #[test]
+    fn test_warmup_without_skip_matches_append() {
+        let context = series();
+        let values: Vec<f64> = series().iter().map(|x| -x).collect();
+        let blk = compress_skip_warmup(DEFAULT_TABLE_SIZE, &context, &values, 0).unwrap();
+        let mut whole = compress(DEFAULT_TABLE_SIZE, &context).unwrap();
+        whole.append(DEFAULT_TABLE_SIZE, &values);
+        let skip_context = whole.residual.len() - blk.residual.len();
+        assert_eq!(whole.residual[skip_context..], blk.residual[..]);
+    }
+
+    #[test]
+    fn test_warmup_rejects_invalid_arguments() {
+        let values = series();
+        assert_eq!(compress_skip_warmup(3, &[], &values, 0), Err(FpcError::InvalidTableSize { given: 3 }));
+        assert_eq!(
+            compress_skip_warmup(DEFAULT_TABLE_SIZE, &[], &values, 65),
+            Err(FpcError::RangeOutOfBounds { start: 0, end: 65, num_values: 64 })
+        );
+        let blk = compress_skip_warmup(64, &[], &values, 0).unwrap();
+        assert_eq!(decompress_after_warmup(3, &[], &blk), Err(FpcError::InvalidTableSize { given: 3 }));
+        assert_eq!(
+            decompress_after_warmup(DEFAULT_TABLE_SIZE, &[], &blk),
+            Err(FpcError::TableSizeMismatch { block: 64, given: DEFAULT_TABLE_SIZE })
+        );
+    }
+}
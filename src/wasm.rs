@@ -0,0 +1,45 @@
+//! Bindings for JavaScript, built with the `wasm` feature. A `&[f64]`
+//! argument takes a `Float64Array`, a `&[u8]` one a `Uint8Array`, and the
+//! returned vectors come back as typed arrays of the same kinds. Errors
+//! are thrown as JavaScript `Error`s carrying the [`FpcError`](crate::FpcError)
+//! message.
+
+use alloc::vec::Vec;
+
+use wasm_bindgen::prelude::*;
+
+use crate::{compress, try_decompress, FPCCompressedBlock};
+
+/// Compresses `values` into a self-contained block serialized with
+/// [`FPCCompressedBlock::to_bytes`].
+#[wasm_bindgen]
+pub fn wasm_compress(table_size: u64, values: &[f64]) -> Result<Vec<u8>, JsError> {
+    Ok(compress(table_size, values)?.to_bytes())
+}
+
+/// Decompresses a block serialized by [`wasm_compress`] or
+/// [`FPCCompressedBlock::to_bytes`].
+#[wasm_bindgen]
+pub fn wasm_decompress(table_size: u64, bytes: &[u8]) -> Result<Vec<f64>, JsError> {
+    Ok(try_decompress(table_size, &FPCCompressedBlock::from_bytes(bytes)?)?)
+}
+
+#[cfg(test)]
+mod wasm_test {
+    // Only the success paths run natively; building a JsError calls into
+    // JavaScript.
+    use quickcheck_macros::quickcheck;
+
+    use crate::{bitwise_eq, DEFAULT_TABLE_SIZE};
+
+    use super::*;
+
+    #[quickcheck]
+    fn wasm_bindings_must_round_trip(to_compress: Vec<f64>) -> bool {
+        let Ok(bytes) = wasm_compress(DEFAULT_TABLE_SIZE, &to_compress) else {
+            return false;
+        };
+        bytes == compress(DEFAULT_TABLE_SIZE, &to_compress).unwrap().to_bytes()
+            && wasm_decompress(DEFAULT_TABLE_SIZE, &bytes).is_ok_and(|values| bitwise_eq(&values, &to_compress))
+    }
+}
@@ -0,0 +1,116 @@
+use std::io::Write;
+
+use crate::{compress_unchecked, validate_table_size, CompressError, FpcError};
+
+pub(crate) const DEFAULT_BLOCK_LEN: usize = 1024;
+
+/// Compresses a stream of values straight into an inner writer. Values are
+/// buffered until a block is full, which is then written out serialized as
+/// by [`FPCCompressedBlock::to_bytes`](crate::FPCCompressedBlock::to_bytes).
+/// Read the result back with [`FpcReader`](crate::FpcReader) or
+/// [`decompress_stream`](crate::decompress_stream).
+///
+/// Call [`FpcWriter::finish`] at the end: values still buffered when the
+/// writer is dropped are lost.
+pub struct FpcWriter<W: Write> {
+    inner: W,
+    table_size: u64,
+    block_len: usize,
+    buffer: Vec<f64>,
+}
+
+impl<W: Write> FpcWriter<W> {
+    /// Writes blocks of 1024 values. Fails like [`compress`](crate::compress)
+    /// on an invalid table size.
+    pub fn new(inner: W, table_size: u64) -> Result<FpcWriter<W>, CompressError> {
+        FpcWriter::with_block_len(inner, table_size, DEFAULT_BLOCK_LEN)
+    }
+
+    /// Writes blocks of `block_len` values, which must be at least 1.
+    pub fn with_block_len(inner: W, table_size: u64, block_len: usize) -> Result<FpcWriter<W>, CompressError> {
+        validate_table_size(table_size)?;
+        if block_len == 0 {
+            return Err(FpcError::InvalidBlockLen { given: block_len });
+        }
+        Ok(FpcWriter { inner, table_size, block_len, buffer: Vec::with_capacity(block_len) })
+    }
+
+    pub fn write(&mut self, value: f64) -> Result<(), FpcError> {
+        self.buffer.push(value);
+        if self.buffer.len() == self.block_len {
+            self.write_block()?;
+        }
+        Ok(())
+    }
+
+    pub fn write_all(&mut self, values: &[f64]) -> Result<(), FpcError> {
+        for &value in values {
+            self.write(value)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the buffered values as a block, even if it is not full, and
+    /// flushes the inner writer.
+    pub fn flush(&mut self) -> Result<(), FpcError> {
+        self.write_block()?;
+        self.inner.flush()?;
+        Ok(())
+    }
+
+    /// Flushes the last partial block and returns the inner writer.
+    pub fn finish(mut self) -> Result<W, FpcError> {
+        self.flush()?;
+        Ok(self.inner)
+    }
+
+    fn write_block(&mut self) -> Result<(), FpcError> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let blk = compress_unchecked(self.table_size, &self.buffer);
+        self.buffer.clear();
+        let bytes = blk.to_bytes();
+        #[cfg(feature = "tracing")]
+        tracing::trace!(num_values = blk.num_values(), block_len = bytes.len(), "wrote block");
+        self.inner.write_all(&bytes)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod writer_test {
+    use crate::{bitwise_eq, decompress_stream};
+
+    use super::*;
+
+    #[test]
+    fn test_writer_splits_values_into_blocks() {
+        let values: Vec<f64> = (0..2500).map(|x| (x as f64 * 0.01).sin()).collect();
+        let mut writer = FpcWriter::new(Vec::new(), 64).unwrap();
+        writer.write_all(&values).unwrap();
+        let bytes = writer.finish().unwrap();
+        let blocks: Vec<Vec<f64>> = decompress_stream(&mut bytes.as_slice()).collect::<Result<_, _>>().unwrap();
+        assert_eq!(blocks.iter().map(Vec::len).collect::<Vec<_>>(), [1024, 1024, 452]);
+        assert!(bitwise_eq(&blocks.concat(), &values));
+    }
+
+    #[test]
+    fn test_flush_writes_a_partial_block() {
+        let mut writer = FpcWriter::with_block_len(Vec::new(), 32, 4).unwrap();
+        writer.write_all(&[1.0, 2.0]).unwrap();
+        writer.flush().unwrap();
+        // Nothing is buffered, so neither call writes an empty block.
+        writer.flush().unwrap();
+        writer.write_all(&[3.0, 4.0, 5.0, 6.0]).unwrap();
+        let bytes = writer.finish().unwrap();
+        let blocks: Vec<Vec<f64>> = decompress_stream(&mut bytes.as_slice()).collect::<Result<_, _>>().unwrap();
+        assert_eq!(blocks, vec![vec![1.0, 2.0], vec![3.0, 4.0, 5.0, 6.0]]);
+    }
+
+    #[test]
+    fn test_writer_rejects_bad_configuration() {
+        assert!(matches!(FpcWriter::new(Vec::new(), 3), Err(FpcError::InvalidTableSize { given: 3 })));
+        assert!(matches!(FpcWriter::with_block_len(Vec::new(), 32, 0), Err(FpcError::InvalidBlockLen { given: 0 })));
+    }
+}
@@ -0,0 +1,116 @@
+//! Checks that the per-value loops never allocate. Only built with the
+//! `testing` feature, since it replaces the global allocator of this test
+//! binary: `cargo test --features testing --test allocations`.
+#![cfg(feature = "testing")]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use fpc_compression::{
+    compress, compress_into, compress_with, compress_with_context, decompress_into, decompress_into_slice, FpcContext,
+    FpcOptions, ResidualSizing, DEFAULT_TABLE_SIZE,
+};
+
+/// Counts allocations made by the current thread, so tests running in
+/// parallel do not see each other's allocations.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn allocations_during(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    f();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+// The FCM and DFCM tables are the only allocations an encode or decode
+// pass is allowed to make.
+const TABLE_ALLOCATIONS: usize = 2;
+
+fn sample_values() -> Vec<f64> {
+    (0..10_000).map(|x| (x as f64 * 0.01).sin() * 1e3).collect()
+}
+
+#[test]
+fn compress_into_presized_buffers_only_allocates_tables() {
+    let vals = sample_values();
+    let mut encoding = Vec::with_capacity(vals.len().div_ceil(2));
+    let mut residual = Vec::with_capacity(vals.len() * size_of::<f64>());
+    let allocations = allocations_during(|| compress_into(DEFAULT_TABLE_SIZE, &vals, (&mut encoding, &mut residual)).unwrap());
+    assert_eq!(allocations, TABLE_ALLOCATIONS);
+}
+
+#[test]
+fn decompress_into_presized_buffer_only_allocates_tables() {
+    let vals = sample_values();
+    let blk = compress(DEFAULT_TABLE_SIZE, &vals).unwrap();
+    let mut res = Vec::with_capacity(vals.len());
+    let allocations = allocations_during(|| decompress_into(DEFAULT_TABLE_SIZE, &blk, &mut res));
+    assert_eq!(allocations, TABLE_ALLOCATIONS);
+    assert_eq!(res, vals);
+}
+
+#[test]
+fn decompress_into_slice_only_allocates_tables() {
+    let vals = sample_values();
+    let blk = compress(DEFAULT_TABLE_SIZE, &vals).unwrap();
+    let mut out = vec![0.0; vals.len()];
+    let mut written = 0;
+    let allocations = allocations_during(|| written = decompress_into_slice(DEFAULT_TABLE_SIZE, &blk, &mut out).unwrap());
+    assert_eq!(written, vals.len());
+    assert_eq!(allocations, TABLE_ALLOCATIONS);
+    assert_eq!(out, vals);
+}
+
+#[test]
+fn compress_with_context_does_not_allocate() {
+    let vals = sample_values();
+    let mut ctx = FpcContext::new(DEFAULT_TABLE_SIZE).unwrap();
+    let mut encoding = Vec::with_capacity(vals.len().div_ceil(2));
+    let mut residual = Vec::with_capacity(vals.len() * size_of::<f64>());
+    let allocations = allocations_during(|| {
+        compress_with_context(&mut ctx, DEFAULT_TABLE_SIZE, &vals, &mut encoding, &mut residual).unwrap()
+    });
+    assert_eq!(allocations, 0);
+}
+
+#[test]
+fn pessimistic_residual_sizing_never_reallocates() {
+    let mut state = 0x9e37_79b9_7f4a_7c15_u64;
+    let vals: Vec<f64> = (0..10_000)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            f64::from_bits(state)
+        })
+        .collect();
+    let allocations_with = |residual_sizing| {
+        let options = FpcOptions { residual_sizing, ..FpcOptions::default() };
+        allocations_during(|| drop(compress_with(DEFAULT_TABLE_SIZE, &vals, &options).unwrap()))
+    };
+    // The tables plus one allocation each for the encoding and residual.
+    assert_eq!(allocations_with(ResidualSizing::Pessimistic), TABLE_ALLOCATIONS + 2);
+    assert!(allocations_with(ResidualSizing::Conservative) > TABLE_ALLOCATIONS + 2);
+}